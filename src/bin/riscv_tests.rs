@@ -0,0 +1,269 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// riscv-tests / riscv-arch-test 测试运行器：
+// 通过 HTIF 的 tohost 判断通过与失败，并导出 begin_signature..end_signature 之间的签名
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use riscv_emu::cpu::Cpu;
+use riscv_emu::debugger::WatchKind;
+use riscv_emu::error::EmuError;
+use riscv_emu::isa::{Isa, DEFAULT_ISA};
+
+const DEFAULT_PREFIX: &str = "rv32ui-p-";
+const DEFAULT_LIMIT: u64 = 1_000_000;
+
+fn print_usage(program: &str) {
+    eprintln!("Usage: {} <test-dir|test-elf> [options]", program);
+    eprintln!("Options:");
+    eprintln!(
+        "  --prefix <name>      Only run tests whose file name starts with this (default: {})",
+        DEFAULT_PREFIX
+    );
+    eprintln!(
+        "  --isa <string>       XLEN and enabled extensions (default: {})",
+        DEFAULT_ISA
+    );
+    eprintln!(
+        "  --limit <n>          Instruction limit per test (default: {})",
+        DEFAULT_LIMIT
+    );
+    eprintln!("  --signature-dir <d>  Write <test>.signature files for riscv-arch-test");
+}
+
+enum Outcome {
+    Pass,
+    Fail(u64), // riscv-tests 的失败用例编号
+    Timeout,
+    Error(String),
+}
+
+impl Outcome {
+    fn describe(&self) -> String {
+        match self {
+            Outcome::Pass => "PASS".to_string(),
+            Outcome::Fail(case) => format!("FAIL (test #{})", case),
+            Outcome::Timeout => "TIMEOUT".to_string(),
+            Outcome::Error(e) => format!("ERROR ({})", e),
+        }
+    }
+}
+
+struct Report {
+    name: String,
+    outcome: Outcome,
+    instructions: u64,
+}
+
+// tohost 写入非零值时测试结束：1 表示通过，其他奇数值的高位是失败的用例编号
+fn run_test(path: &Path, isa: Isa, limit: u64, signature_dir: Option<&Path>) -> Report {
+    let name = path.file_name().unwrap().to_string_lossy().to_string();
+    let mut report = Report {
+        name,
+        outcome: Outcome::Timeout,
+        instructions: 0,
+    };
+
+    let mut cpu = Cpu::new(0x03000000);
+    cpu.set_isa(isa);
+    cpu.set_itrace(false);
+    cpu.set_mtrace(false);
+    cpu.set_regtrace(false);
+    // 测试环境自带陷阱处理程序，RVTEST_PASS/FAIL 的 ecall 由它写入 tohost
+    cpu.set_host_syscalls(false);
+    if let Err(e) = cpu.load_program(&path.to_string_lossy()) {
+        report.outcome = Outcome::Error(e.to_string());
+        return report;
+    }
+
+    let symbols = &cpu.debugger().symbols;
+    let tohost = symbols.find("tohost");
+    let signature = symbols
+        .find("begin_signature")
+        .zip(symbols.find("end_signature"));
+    let Some(tohost) = tohost else {
+        report.outcome = Outcome::Error("no tohost symbol".to_string());
+        return report;
+    };
+    cpu.debugger_mut()
+        .add_watchpoint(tohost, 4, WatchKind::Write);
+
+    while report.instructions < limit {
+        let result = cpu.step();
+        report.instructions += 1;
+        if let Err(e) = result {
+            // 没有 tohost 协议的程序通过 exit 系统调用结束
+            report.outcome = match e {
+                EmuError::Exit { code: 0 } => Outcome::Pass,
+                EmuError::Exit { code } => Outcome::Fail(code as u64),
+                e => Outcome::Error(e.to_string()),
+            };
+            break;
+        }
+        if cpu.debugger_mut().watch_hit.take().is_none() {
+            continue;
+        }
+        match cpu.debug_read(tohost, 4) {
+            Ok(0) => continue,
+            Ok(1) => report.outcome = Outcome::Pass,
+            Ok(value) if value & 1 == 1 => report.outcome = Outcome::Fail((value >> 1) as u64),
+            Ok(value) => {
+                report.outcome = Outcome::Error(format!("unsupported HTIF command 0x{:x}", value))
+            }
+            Err(e) => report.outcome = Outcome::Error(e.to_string()),
+        }
+        break;
+    }
+
+    if let (Some(dir), Some((begin, end))) = (signature_dir, signature) {
+        if let Err(e) = write_signature(&mut cpu, dir, &report.name, begin, end) {
+            report.outcome = Outcome::Error(format!("signature: {}", e));
+        }
+    }
+    report
+}
+
+// riscv-arch-test 的签名格式：每行一个 32 位字，8 位小写十六进制
+fn write_signature(
+    cpu: &mut Cpu,
+    dir: &Path,
+    name: &str,
+    begin: u64,
+    end: u64,
+) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut file = fs::File::create(dir.join(format!("{}.signature", name)))?;
+    for addr in (begin..end).step_by(4) {
+        let word = cpu.debug_read(addr, 4).map_err(std::io::Error::other)?;
+        writeln!(file, "{:08x}", word)?;
+    }
+    Ok(())
+}
+
+// 目录中按名称排序的测试 ELF，跳过反汇编文件
+fn collect_tests(target: &Path, prefix: &str) -> std::io::Result<Vec<PathBuf>> {
+    if target.is_file() {
+        return Ok(vec![target.to_path_buf()]);
+    }
+    let mut tests: Vec<PathBuf> = fs::read_dir(target)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            path.is_file() && name.starts_with(prefix) && !name.ends_with(".dump")
+        })
+        .collect();
+    tests.sort();
+    Ok(tests)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        print_usage(&args[0]);
+        std::process::exit(1);
+    }
+
+    let target = PathBuf::from(&args[1]);
+    let mut prefix = DEFAULT_PREFIX.to_string();
+    let mut isa = Isa::default();
+    let mut limit = DEFAULT_LIMIT;
+    let mut signature_dir: Option<PathBuf> = None;
+
+    // 处理命令行选项
+    let mut options = args[2..].iter();
+    while let Some(arg) = options.next() {
+        let value = options.next();
+        match (arg.as_str(), value) {
+            ("--prefix", Some(value)) => prefix = value.clone(),
+            ("--isa", Some(value)) => match Isa::parse(value) {
+                Ok(parsed) => isa = parsed,
+                Err(e) => {
+                    eprintln!("Invalid ISA string: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            ("--limit", Some(value)) => match value.parse() {
+                Ok(n) => limit = n,
+                Err(_) => {
+                    eprintln!("--limit requires a number");
+                    std::process::exit(1);
+                }
+            },
+            ("--signature-dir", Some(value)) => signature_dir = Some(PathBuf::from(value)),
+            _ => {
+                eprintln!("Unknown option or missing value: {}", arg);
+                print_usage(&args[0]);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let tests = collect_tests(&target, &prefix)?;
+    if tests.is_empty() {
+        eprintln!(
+            "No tests matching {}* found in {}",
+            prefix,
+            target.display()
+        );
+        std::process::exit(1);
+    }
+
+    let reports: Vec<Report> = tests
+        .iter()
+        .map(|path| run_test(path, isa, limit, signature_dir.as_deref()))
+        .collect();
+
+    // 汇总表
+    let width = reports
+        .iter()
+        .map(|r| r.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    println!();
+    println!(
+        "{:<width$}  {:>12}  Result",
+        "Test",
+        "Instructions",
+        width = width
+    );
+    println!("{}", "-".repeat(width + 24));
+    for report in &reports {
+        println!(
+            "{:<width$}  {:>12}  {}",
+            report.name,
+            report.instructions,
+            report.outcome.describe(),
+            width = width
+        );
+    }
+    let passed = reports
+        .iter()
+        .filter(|r| matches!(r.outcome, Outcome::Pass))
+        .count();
+    println!("{}", "-".repeat(width + 24));
+    println!("Passed {}/{} ({})", passed, reports.len(), isa);
+
+    if passed != reports.len() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
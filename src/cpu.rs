@@ -15,57 +15,254 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::csr::{CsrFile, Privilege, MIP_MTIP, MSTATUS_TSR, MSTATUS_TVM, MSTATUS_TW};
 use crate::debugger::Debugger;
-use crate::inst::{decode_instruction, BranchOp, NextPc, Operation, RegOp, SystemCallType};
+use crate::devices::{MmioDevice, IRQ_TIMER};
+use crate::disasm::disassemble;
+use crate::error::EmuError;
+use crate::fpu::{self, FpFormat, RoundingMode};
+use crate::inst::{
+    decode_instruction, AmoOp, BranchOp, CsrOp, FpOp, NextPc, Operation, RegOp, SystemCallType,
+};
+use crate::isa::{Extension, Isa, Xlen};
 use crate::loader::Loader;
-use crate::memory::Memory;
-use crate::register::RegisterFile;
+use crate::memory::{Memory, RegionKind};
+use crate::mmu::{self, AccessType, Mmu};
+use crate::monitor;
+use crate::register::{FpRegisterFile, RegisterFile};
+use crate::rvc;
+use crate::syscall::{self, GuestMemory, SyscallResult, Syscalls};
+use crate::trap::Exception;
 
-// System Call Constants
-const SYS_EXIT: u32 = 93;
-const SYS_WRITE: u32 = 64;
+// 浮点指令的结果写回浮点寄存器或整数寄存器
+enum FpResult {
+    Float(u64),
+    Int(u64),
+}
+
+fn float_result((value, flags): (u64, u32)) -> (FpResult, u32) {
+    (FpResult::Float(value), flags)
+}
+
+fn int_result((value, flags): (bool, u32)) -> (FpResult, u32) {
+    (FpResult::Int(value as u64), flags)
+}
+
+// 把 size 字节的值符号扩展到 64 位
+fn sign_extend(value: u64, size: usize) -> u64 {
+    let shift = 64 - 8 * size as u32;
+    (((value << shift) as i64) >> shift) as u64
+}
+
+// Cpu::run 停止执行的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    Exited(u32),      // 程序通过 exit 系统调用退出，携带退出码
+    Breakpoint(u64),  // 未处理的 EBREAK，携带其 PC
+    InstructionLimit, // 执行完指令预算
+    Halted,           // 在 WFI 中等待且没有使能任何中断，永远不会被唤醒
+    Quit,             // 在交互调试器中退出
+    Fault(EmuError),
+}
 
 pub struct Cpu {
     registers: RegisterFile,
-    pc: u32,
+    fp_registers: FpRegisterFile,
+    csrs: CsrFile,
+    isa: Isa,
+    pc: u64,
     memory: Memory,
+    mmu: Mmu,
     debugger: Debugger,
+    exit_code: Option<u32>,
+    waiting: bool, // 执行 WFI 后等待中断
+    syscalls: Syscalls,
+    host_syscalls: bool, // ECALL 由模拟器按 riscv-pk 约定处理，而不是进入客户机的陷阱
+    cycles: u64,         // 已执行的周期数，系统调用的虚拟时间由它推算
+}
+
+// 系统调用按当前地址空间的虚拟地址逐字节访问客户机内存，与调试器访存相同，不检查页权限
+struct SyscallMemory<'a> {
+    mmu: &'a Mmu,
+    memory: &'a mut Memory,
+    csrs: &'a CsrFile,
+}
+
+impl SyscallMemory<'_> {
+    fn translate(&mut self, addr: u64) -> Result<usize, EmuError> {
+        self.mmu
+            .translate_debug(self.memory, self.csrs, addr)
+            .map(|paddr| paddr as usize)
+    }
+}
+
+impl GuestMemory for SyscallMemory<'_> {
+    fn read(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), EmuError> {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            let paddr = self.translate(addr.wrapping_add(i as u64))?;
+            *byte = self.memory.vread(paddr, 1)? as u8;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), EmuError> {
+        for (i, &byte) in data.iter().enumerate() {
+            let paddr = self.translate(addr.wrapping_add(i as u64))?;
+            self.memory.vwrite(paddr, byte as u32, 1)?;
+        }
+        Ok(())
+    }
 }
 
 impl Cpu {
     pub fn new(memory_size: usize) -> Self {
+        Self::with_memory(Memory::new(memory_size))
+    }
+
+    // 使用自定义内存映射创建 CPU
+    pub fn with_memory(memory: Memory) -> Self {
         Self {
             registers: RegisterFile::new(),
+            fp_registers: FpRegisterFile::new(),
+            csrs: CsrFile::new(),
+            isa: Isa::default(),
             pc: 0x80000000, // init pc=0x80000000
-            memory: Memory::new(memory_size),
+            memory,
+            mmu: Mmu::new(),
             debugger: Debugger::new(),
+            exit_code: None,
+            waiting: false,
+            syscalls: Syscalls::new(),
+            host_syscalls: true,
+            cycles: 0,
         }
     }
 
-    pub fn step(&mut self) -> Result<(), &'static str> {
+    pub fn step(&mut self) -> Result<(), EmuError> {
+        // 在指令之间采样设备中断线
+        let irq = self.memory.check_interrupts();
+        self.csrs.set_pending(MIP_MTIP, irq & IRQ_TIMER != 0);
+
+        if let Some(code) = self.csrs.pending_interrupt() {
+            self.waiting = false;
+            self.take_interrupt(code);
+        } else if self.waiting {
+            // WFI 等待期间不执行指令；使能的中断挂起后即使被全局屏蔽也继续执行
+            self.waiting = !self.csrs.wfi_wakeup();
+        } else {
+            match self.execute() {
+                Ok(next_pc) => self.pc = next_pc,
+                Err(e) => self.take_trap(e)?,
+            }
+        }
+
+        self.cycles += 1;
+
+        // 主机系统调用请求退出
+        if let Some(code) = self.exit_code {
+            return Err(EmuError::Exit { code });
+        }
+
+        // 更新设备状态
+        self.memory.tick_devices();
+
+        // 单步执行：到达断点、观察点或单步计数用完时进入交互命令行
+        if self.debugger.single_step
+            && !self.waiting
+            && self.debugger.should_stop(self.pc)
+            && !monitor::prompt(self)
+        {
+            return Err(EmuError::DebuggerQuit);
+        }
+        Ok(())
+    }
+
+    // 连续执行，直到程序退出、出错、遇到断点或执行完 limit 条指令（None 表示不限）
+    // WFI 中等待的周期同样计入指令预算
+    pub fn run(&mut self, limit: Option<u64>) -> StopReason {
+        let mut count = 0;
+        while limit.is_none_or(|limit| count < limit) {
+            if let Err(e) = self.step() {
+                return match e {
+                    EmuError::Exit { code } => StopReason::Exited(code),
+                    EmuError::Breakpoint { pc } => StopReason::Breakpoint(pc),
+                    EmuError::DebuggerQuit => StopReason::Quit,
+                    e => StopReason::Fault(e),
+                };
+            }
+            count += 1;
+            self.show_registers();
+            if self.waiting && !self.csrs.interrupts_enabled() {
+                return StopReason::Halted;
+            }
+        }
+        StopReason::InstructionLimit
+    }
+
+    // 执行一条指令，返回下一条指令地址
+    fn execute(&mut self) -> Result<u64, Exception> {
         let raw_inst = self.fetch()?;
-        let decoded = decode_instruction(raw_inst)?;
+        let xlen = self.isa.xlen();
+        let illegal = Exception::IllegalInstruction(raw_inst as u64);
+        let inst_len = if rvc::is_compressed(raw_inst) { 2 } else { 4 };
+        let decoded = if inst_len == 2 {
+            rvc::expand(raw_inst as u16, xlen).and_then(|inst| decode_instruction(inst, xlen))
+        } else {
+            decode_instruction(raw_inst, xlen)
+        }
+        .map_err(|_| illegal)?;
+
+        // ISA 配置中未启用的扩展
+        let disabled = |ext| !self.isa.has(ext);
+        if (inst_len == 2 && disabled(Extension::C)) || decoded.op.extension().is_some_and(disabled)
+        {
+            return Err(illegal);
+        }
+
+        // 特权指令：MRET 只能在 M 模式执行，SRET/SFENCE.VMA 需要 S 模式且未被 TSR/TVM 拦截
+        let privilege = self.csrs.privilege();
+        let supervisor_allowed = |trap_bit| match privilege {
+            Privilege::Machine => true,
+            Privilege::Supervisor => self.csrs.mstatus() & trap_bit == 0,
+            Privilege::User => false,
+        };
+        let allowed = match decoded.op {
+            Operation::SystemCall(SystemCallType::Mret) => privilege == Privilege::Machine,
+            Operation::SystemCall(SystemCallType::Sret) => supervisor_allowed(MSTATUS_TSR),
+            Operation::SystemCall(SystemCallType::Wfi) => supervisor_allowed(MSTATUS_TW),
+            Operation::SystemCall(SystemCallType::SfenceVma { .. }) => {
+                supervisor_allowed(MSTATUS_TVM)
+            }
+            _ => true,
+        };
+        if !allowed {
+            return Err(illegal);
+        }
 
         // 执行指令前的调试信息
-        if self.debugger.itrace_enabled {
-            self.debugger
-                .trace_instruction(self.pc, raw_inst, "TODO: add disassembly");
+        if self.debugger.itrace_active() {
+            let disasm = disassemble(raw_inst, self.pc, xlen);
+            self.debugger.trace_instruction(self.pc, raw_inst, &disasm);
         }
 
+        // 先计算下一条 PC，避免 rd 与 rs1 相同时读到写回后的值
+        let next_pc = self.next_pc(&decoded.next_pc, inst_len)?;
+
         // 执行操作
         match decoded.op {
             Operation::RegWrite { rd, value } => {
-                self.registers.write(rd, value);
+                // RV64 中 LUI 的结果符号扩展
+                self.registers.write(rd, value as i32 as u64);
             }
             Operation::RegImmOp { rd, rs1, imm, op } => {
                 let rs1_val = self.registers.read(rs1);
-                let result = Self::execute_alu_op(op, rs1_val, imm as u32);
+                let result = self.alu(op, rs1_val, imm as u64);
                 self.registers.write(rd, result);
             }
             Operation::RegRegOp { rd, rs1, rs2, op } => {
                 let rs1_val = self.registers.read(rs1);
                 let rs2_val = self.registers.read(rs2);
-                let result = Self::execute_alu_op(op, rs1_val, rs2_val);
+                let result = self.alu(op, rs1_val, rs2_val);
                 self.registers.write(rd, result);
             }
             Operation::Load {
@@ -73,9 +270,19 @@ impl Cpu {
                 rs1,
                 offset,
                 size,
+                signed,
             } => {
-                let addr = self.registers.read(rs1).wrapping_add(offset as u32);
-                let value = self.read(addr as usize, size)?;
+                let addr = self.effective_address(rs1, offset);
+                if !addr.is_multiple_of(size as u64) {
+                    return Err(Exception::LoadAddressMisaligned(addr));
+                }
+                self.debugger.check_watchpoints(addr, size as u64, false);
+                let value = self.load(addr, size)?;
+                let value = if signed {
+                    sign_extend(value, size)
+                } else {
+                    value
+                };
                 self.registers.write(rd, value);
             }
             Operation::Store {
@@ -84,54 +291,120 @@ impl Cpu {
                 offset,
                 size,
             } => {
-                let addr = self.registers.read(rs1).wrapping_add(offset as u32);
+                let addr = self.effective_address(rs1, offset);
+                if !addr.is_multiple_of(size as u64) {
+                    return Err(Exception::StoreAddressMisaligned(addr));
+                }
+                self.debugger.check_watchpoints(addr, size as u64, true);
                 let value = self.registers.read(rs2);
-                self.write(addr as usize, value, size)?;
+                self.store(addr, value, size)?;
             }
             Operation::Jump { rd, offset: _ } => {
-                self.registers.write(rd, self.pc.wrapping_add(4));
+                self.registers.write(rd, self.pc.wrapping_add(inst_len));
+            }
+            Operation::Auipc { rd, offset } => {
+                self.registers
+                    .write(rd, self.pc.wrapping_add(offset as i64 as u64));
             }
             Operation::Branch { .. } => (), // 分支操作在 next_pc 中处理
-            Operation::SystemCall(syscall_type) => {
-                match syscall_type {
-                    SystemCallType::Ebreak => {
-                        println!("[SYSTEM] Breakpoint hit at PC: 0x{:08x}", self.pc);
-                        // exit with code 0
-                        std::process::exit(0);
-                    }
-                    SystemCallType::Ecall => {
-                        // 获取系统调用号（在 a7 寄存器中）
-                        let syscall_num = self.registers.read(17); // a7 寄存器
-                                                                   // 获取参数（在 a0-a6 寄存器中）
-                        let a0 = self.registers.read(10);
-
-                        // 处理系统调用
-                        match syscall_num {
-                            SYS_EXIT => {
-                                // exit
-                                println!("[SYSTEM] Program exit with code: {}", a0);
-                                return Err("Program exit");
-                            }
-                            SYS_WRITE => {
-                                // write
-                                println!("[SYSTEM] Write syscall: {}", a0);
-                            }
-                            _ => {
-                                println!("[SYSTEM] Unimplemented syscall: {}", syscall_num);
-                            }
-                        }
-                    }
+            Operation::Fence => (),
+            Operation::Csr { rd, rs1, csr, op } => {
+                self.execute_csr_op(op, rd, rs1, csr).map_err(|_| illegal)?;
+            }
+            Operation::Atomic {
+                rd,
+                rs1,
+                rs2,
+                size,
+                op,
+            } => {
+                self.execute_atomic_op(op, rd, rs1, rs2, size)?;
+            }
+            Operation::FpLoad {
+                rd,
+                rs1,
+                offset,
+                fmt,
+            } => {
+                if !self.csrs.fpu_enabled() {
+                    return Err(illegal);
+                }
+                let addr = self.effective_address(rs1, offset);
+                let size = Self::fp_size(fmt);
+                if !addr.is_multiple_of(size as u64) {
+                    return Err(Exception::LoadAddressMisaligned(addr));
+                }
+                self.debugger.check_watchpoints(addr, size as u64, false);
+                let value = self.load(addr, size)?;
+                self.fp_registers.write(rd, fmt, value);
+                self.csrs.mark_fp_dirty();
+            }
+            Operation::FpStore {
+                rs1,
+                rs2,
+                offset,
+                fmt,
+            } => {
+                if !self.csrs.fpu_enabled() {
+                    return Err(illegal);
                 }
+                let addr = self.effective_address(rs1, offset);
+                let size = Self::fp_size(fmt);
+                if !addr.is_multiple_of(size as u64) {
+                    return Err(Exception::StoreAddressMisaligned(addr));
+                }
+                self.debugger.check_watchpoints(addr, size as u64, true);
+                // FSW 只存储低 32 位，不检查 NaN-boxing
+                let value = self.fp_registers.read_raw(rs2);
+                self.store(addr, value, size)?;
+            }
+            Operation::Fp {
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                fmt,
+                rm,
+                op,
+            } => {
+                let rm = self.fp_rounding_mode(rm).ok_or(illegal)?;
+                self.execute_fp_op(op, fmt, rm, rd, rs1, rs2, rs3);
             }
+            Operation::SystemCall(syscall_type) => match syscall_type {
+                SystemCallType::Ebreak => {
+                    return Err(Exception::Breakpoint(self.pc));
+                }
+                SystemCallType::Ecall => {
+                    if !self.host_syscalls {
+                        return Err(match privilege {
+                            Privilege::User => Exception::EcallFromUMode,
+                            Privilege::Supervisor => Exception::EcallFromSMode,
+                            Privilege::Machine => Exception::EcallFromMMode,
+                        });
+                    }
+                    self.handle_syscall();
+                }
+                // 状态恢复在 next_pc 中处理
+                SystemCallType::Mret | SystemCallType::Sret => (),
+                SystemCallType::Wfi => self.waiting = true,
+                SystemCallType::SfenceVma { rs1, rs2 } => {
+                    let vaddr = (rs1 != 0).then(|| self.registers.read(rs1));
+                    let asid = (rs2 != 0).then(|| self.registers.read(rs2));
+                    self.mmu.flush(vaddr, asid);
+                }
+            },
         }
 
-        // 更新 PC
-        self.pc = match decoded.next_pc {
-            NextPc::Plus4 => self.pc.wrapping_add(4),
-            NextPc::Jump(offset) => self.pc.wrapping_add(offset as u32),
+        Ok(next_pc)
+    }
+
+    fn next_pc(&mut self, next_pc: &NextPc, inst_len: u64) -> Result<u64, Exception> {
+        let mask = self.isa.xlen().mask();
+        let target = match *next_pc {
+            NextPc::Next => return Ok(self.pc.wrapping_add(inst_len) & mask),
+            NextPc::Jump(offset) => self.pc.wrapping_add(offset as u64) & mask,
             NextPc::JumpReg { rs1, offset, .. } => {
-                let rs1_val = self.registers.read(rs1);
-                (rs1_val.wrapping_add(offset as u32)) & !1 // 确保最低位为0
+                self.effective_address(rs1, offset) & !1 // 确保最低位为0
             }
             NextPc::Branch {
                 cond,
@@ -144,29 +417,165 @@ impl Cpu {
                 let take_branch = match cond {
                     BranchOp::Eq => rs1_val == rs2_val,
                     BranchOp::Ne => rs1_val != rs2_val,
-                    BranchOp::Lt => (rs1_val as i32) < (rs2_val as i32),
-                    BranchOp::Ge => (rs1_val as i32) >= (rs2_val as i32),
+                    BranchOp::Lt => self.signed(rs1_val) < self.signed(rs2_val),
+                    BranchOp::Ge => self.signed(rs1_val) >= self.signed(rs2_val),
                     BranchOp::Ltu => rs1_val < rs2_val,
                     BranchOp::Geu => rs1_val >= rs2_val,
                 };
-                if take_branch {
-                    self.pc.wrapping_add(offset as u32)
-                } else {
-                    self.pc.wrapping_add(4)
+                if !take_branch {
+                    return Ok(self.pc.wrapping_add(inst_len) & mask);
                 }
+                self.pc.wrapping_add(offset as u64) & mask
             }
+            NextPc::TrapReturn(level) => return Ok(self.csrs.trap_return(level)),
         };
 
-        // 更新设备状态
-        self.memory.tick_devices();
+        // 启用 C 扩展时跳转目标只需 2 字节对齐
+        if !target.is_multiple_of(self.instruction_alignment()) {
+            return Err(Exception::InstructionAddressMisaligned(target));
+        }
+        Ok(target)
+    }
 
-        // 单步执行等待
-        if self.debugger.single_step {
-            self.debugger.wait_for_next();
+    // 跳转到 mtvec（委托时为 stvec）；目标模式未安装处理程序时将异常返回给主机
+    fn take_trap(&mut self, e: Exception) -> Result<(), EmuError> {
+        if !self.csrs.trap_handler_installed(false, e.code()) {
+            if let Exception::Breakpoint(pc) = e {
+                println!(
+                    "[SYSTEM] Breakpoint hit at PC: 0x{:08x}{}",
+                    pc,
+                    self.debugger.symbols.describe(pc)
+                );
+            } else {
+                println!(
+                    "[SYSTEM] Unhandled exception at PC: 0x{:08x}{}: {} (tval=0x{:08x})",
+                    self.pc,
+                    self.debugger.symbols.describe(self.pc),
+                    e.description(),
+                    e.tval()
+                );
+            }
+            return Err(self.host_error(e));
         }
+
+        // 陷阱会打断 LR/SC 序列
+        self.memory.clear_reservation();
+        self.pc = self.csrs.trap_enter(false, e.code(), self.pc, e.tval());
         Ok(())
     }
 
+    // 未处理的异常转换为返回给主机的错误，此时 PC 仍指向出错的指令
+    fn host_error(&mut self, e: Exception) -> EmuError {
+        match e {
+            Exception::IllegalInstruction(word) => EmuError::IllegalInstruction {
+                pc: self.pc,
+                word: word as u32,
+            },
+            Exception::Breakpoint(pc) => EmuError::Breakpoint { pc },
+            Exception::InstructionAddressMisaligned(addr) => EmuError::MisalignedAccess {
+                addr,
+                size: self.instruction_alignment() as usize,
+            },
+            Exception::LoadAddressMisaligned(addr) | Exception::StoreAddressMisaligned(addr) => {
+                EmuError::MisalignedAccess {
+                    addr,
+                    size: self.access_size(),
+                }
+            }
+            Exception::InstructionAccessFault(addr)
+            | Exception::LoadAccessFault(addr)
+            | Exception::StoreAccessFault(addr) => EmuError::AccessFault { addr },
+            Exception::InstructionPageFault(addr)
+            | Exception::LoadPageFault(addr)
+            | Exception::StorePageFault(addr) => EmuError::PageFault { addr },
+            Exception::EcallFromUMode | Exception::EcallFromSMode | Exception::EcallFromMMode => {
+                EmuError::UnhandledEcall { pc: self.pc }
+            }
+        }
+    }
+
+    // 异常不记录访存宽度，从出错的指令重新解码
+    fn access_size(&mut self) -> usize {
+        let xlen = self.isa.xlen();
+        let decoded = self
+            .debug_fetch(self.pc)
+            .and_then(|raw| {
+                if rvc::is_compressed(raw) {
+                    rvc::expand(raw as u16, xlen)
+                } else {
+                    Ok(raw)
+                }
+            })
+            .and_then(|inst| decode_instruction(inst, xlen));
+        match decoded.map(|d| d.op) {
+            Ok(Operation::Load { size, .. })
+            | Ok(Operation::Store { size, .. })
+            | Ok(Operation::Atomic { size, .. }) => size,
+            Ok(Operation::FpLoad { fmt, .. }) | Ok(Operation::FpStore { fmt, .. }) => {
+                Self::fp_size(fmt)
+            }
+            _ => 0,
+        }
+    }
+
+    // 异步中断：mepc/sepc 指向尚未执行的指令
+    fn take_interrupt(&mut self, code: u64) {
+        if self.debugger.itrace_enabled {
+            println!(
+                "[SYSTEM] Interrupt {} taken at PC: 0x{:08x}{}",
+                code,
+                self.pc,
+                self.debugger.symbols.describe(self.pc)
+            );
+        }
+        self.memory.clear_reservation();
+        self.pc = self.csrs.trap_enter(true, code, self.pc, 0);
+    }
+
+    fn handle_syscall(&mut self) {
+        // 获取系统调用号（在 a7 寄存器中）
+        let syscall_num = self.registers.read(17);
+        // 获取参数（在 a0-a5 寄存器中）
+        let args: [u64; 6] = std::array::from_fn(|i| self.registers.read(10 + i));
+
+        let mut memory = SyscallMemory {
+            mmu: &self.mmu,
+            memory: &mut self.memory,
+            csrs: &self.csrs,
+        };
+        let xlen = self.isa.xlen();
+        match self
+            .syscalls
+            .handle(syscall_num, args, xlen, &mut memory, self.cycles)
+        {
+            SyscallResult::Return(value) => self.registers.write(10, value as u64),
+            SyscallResult::Exit(code) => {
+                println!("[SYSTEM] Program exit with code: {}", code);
+                self.exit_code = Some(code);
+            }
+        }
+    }
+
+    // 按 XLEN 选择 32 位或 64 位运算
+    fn alu(&self, op: RegOp, val1: u64, val2: u64) -> u64 {
+        match self.isa.xlen() {
+            Xlen::Rv32 => Self::execute_alu_op(op, val1 as u32, val2 as u32) as u64,
+            Xlen::Rv64 => Self::execute_alu_op64(op, val1, val2),
+        }
+    }
+
+    // 按 XLEN 解释为有符号数
+    fn signed(&self, value: u64) -> i64 {
+        match self.isa.xlen() {
+            Xlen::Rv32 => value as i32 as i64,
+            Xlen::Rv64 => value as i64,
+        }
+    }
+
+    fn effective_address(&self, rs1: usize, offset: i32) -> u64 {
+        self.registers.read(rs1).wrapping_add(offset as u64) & self.isa.xlen().mask()
+    }
+
     fn execute_alu_op(op: RegOp, val1: u32, val2: u32) -> u32 {
         match op {
             RegOp::Add | RegOp::Addi => val1.wrapping_add(val2),
@@ -179,32 +588,545 @@ impl Cpu {
             RegOp::Sra | RegOp::Srai => ((val1 as i32) >> (val2 & 0x1f)) as u32,
             RegOp::Or | RegOp::Ori => val1 | val2,
             RegOp::And | RegOp::Andi => val1 & val2,
+            RegOp::Mul => val1.wrapping_mul(val2),
+            RegOp::Mulh => (((val1 as i32 as i64) * (val2 as i32 as i64)) >> 32) as u32,
+            RegOp::Mulhsu => (((val1 as i32 as i64) * (val2 as i64)) >> 32) as u32,
+            RegOp::Mulhu => (((val1 as u64) * (val2 as u64)) >> 32) as u32,
+            // 除零和有符号溢出的结果由规范规定，不产生异常
+            RegOp::Div => {
+                if val2 == 0 {
+                    u32::MAX
+                } else {
+                    (val1 as i32).wrapping_div(val2 as i32) as u32
+                }
+            }
+            RegOp::Divu => val1.checked_div(val2).unwrap_or(u32::MAX),
+            RegOp::Rem => {
+                if val2 == 0 {
+                    val1
+                } else {
+                    (val1 as i32).wrapping_rem(val2 as i32) as u32
+                }
+            }
+            RegOp::Remu => val1.checked_rem(val2).unwrap_or(val1),
+            RegOp::Sh1add => (val1 << 1).wrapping_add(val2),
+            RegOp::Sh2add => (val1 << 2).wrapping_add(val2),
+            RegOp::Sh3add => (val1 << 3).wrapping_add(val2),
+            RegOp::Andn => val1 & !val2,
+            RegOp::Orn => val1 | !val2,
+            RegOp::Xnor => !(val1 ^ val2),
+            RegOp::Clz => val1.leading_zeros(),
+            RegOp::Ctz => val1.trailing_zeros(),
+            RegOp::Cpop => val1.count_ones(),
+            RegOp::Max => (val1 as i32).max(val2 as i32) as u32,
+            RegOp::Maxu => val1.max(val2),
+            RegOp::Min => (val1 as i32).min(val2 as i32) as u32,
+            RegOp::Minu => val1.min(val2),
+            RegOp::SextB => val1 as i8 as u32,
+            RegOp::SextH => val1 as i16 as u32,
+            RegOp::ZextH => val1 & 0xffff,
+            RegOp::Rol => val1.rotate_left(val2 & 0x1f),
+            RegOp::Ror | RegOp::Rori => val1.rotate_right(val2 & 0x1f),
+            RegOp::OrcB => (0..4).fold(0, |acc, i| {
+                if (val1 >> (8 * i)) & 0xff != 0 {
+                    acc | (0xff << (8 * i))
+                } else {
+                    acc
+                }
+            }),
+            RegOp::Rev8 => val1.swap_bytes(),
+            // 无进位乘法：clmul 取低 32 位，clmulh 取高 32 位，clmulr 取第 62..31 位
+            RegOp::Clmul => Self::carryless_mul(val1 as u64, val2 as u64) as u32,
+            RegOp::Clmulh => (Self::carryless_mul(val1 as u64, val2 as u64) >> 32) as u32,
+            RegOp::Clmulr => (Self::carryless_mul(val1 as u64, val2 as u64) >> 31) as u32,
+            RegOp::Bclr | RegOp::Bclri => val1 & !(1 << (val2 & 0x1f)),
+            RegOp::Bext | RegOp::Bexti => (val1 >> (val2 & 0x1f)) & 1,
+            RegOp::Binv | RegOp::Binvi => val1 ^ (1 << (val2 & 0x1f)),
+            RegOp::Bset | RegOp::Bseti => val1 | (1 << (val2 & 0x1f)),
+            // RV32 下不会解码出 RV64 专有的指令
+            RegOp::Addiw
+            | RegOp::Slliw
+            | RegOp::Srliw
+            | RegOp::Sraiw
+            | RegOp::Addw
+            | RegOp::Subw
+            | RegOp::Sllw
+            | RegOp::Srlw
+            | RegOp::Sraw
+            | RegOp::Mulw
+            | RegOp::Divw
+            | RegOp::Divuw
+            | RegOp::Remw
+            | RegOp::Remuw
+            | RegOp::AddUw
+            | RegOp::Sh1addUw
+            | RegOp::Sh2addUw
+            | RegOp::Sh3addUw
+            | RegOp::SlliUw
+            | RegOp::Clzw
+            | RegOp::Ctzw
+            | RegOp::Cpopw
+            | RegOp::Rolw
+            | RegOp::Rorw
+            | RegOp::Roriw => unreachable!("RV64-only operation in RV32 mode"),
+        }
+    }
+
+    fn execute_alu_op64(op: RegOp, val1: u64, val2: u64) -> u64 {
+        // *W 指令复用 32 位运算，结果符号扩展到 64 位
+        let word = |op| Self::execute_alu_op(op, val1 as u32, val2 as u32) as i32 as u64;
+        let shamt = (val2 & 0x3f) as u32;
+        let uw = val1 & 0xffffffff;
+
+        match op {
+            RegOp::Add | RegOp::Addi => val1.wrapping_add(val2),
+            RegOp::Sub => val1.wrapping_sub(val2),
+            RegOp::Sll | RegOp::Slli => val1 << shamt,
+            RegOp::Slt | RegOp::Slti => ((val1 as i64) < (val2 as i64)) as u64,
+            RegOp::Sltu | RegOp::Sltiu => (val1 < val2) as u64,
+            RegOp::Xor | RegOp::Xori => val1 ^ val2,
+            RegOp::Srl | RegOp::Srli => val1 >> shamt,
+            RegOp::Sra | RegOp::Srai => ((val1 as i64) >> shamt) as u64,
+            RegOp::Or | RegOp::Ori => val1 | val2,
+            RegOp::And | RegOp::Andi => val1 & val2,
+            RegOp::Mul => val1.wrapping_mul(val2),
+            RegOp::Mulh => (((val1 as i64 as i128) * (val2 as i64 as i128)) >> 64) as u64,
+            RegOp::Mulhsu => (((val1 as i64 as i128) * (val2 as i128)) >> 64) as u64,
+            RegOp::Mulhu => (((val1 as u128) * (val2 as u128)) >> 64) as u64,
+            RegOp::Div => {
+                if val2 == 0 {
+                    u64::MAX
+                } else {
+                    (val1 as i64).wrapping_div(val2 as i64) as u64
+                }
+            }
+            RegOp::Divu => val1.checked_div(val2).unwrap_or(u64::MAX),
+            RegOp::Rem => {
+                if val2 == 0 {
+                    val1
+                } else {
+                    (val1 as i64).wrapping_rem(val2 as i64) as u64
+                }
+            }
+            RegOp::Remu => val1.checked_rem(val2).unwrap_or(val1),
+            RegOp::Sh1add => (val1 << 1).wrapping_add(val2),
+            RegOp::Sh2add => (val1 << 2).wrapping_add(val2),
+            RegOp::Sh3add => (val1 << 3).wrapping_add(val2),
+            RegOp::Andn => val1 & !val2,
+            RegOp::Orn => val1 | !val2,
+            RegOp::Xnor => !(val1 ^ val2),
+            RegOp::Clz => val1.leading_zeros() as u64,
+            RegOp::Ctz => val1.trailing_zeros() as u64,
+            RegOp::Cpop => val1.count_ones() as u64,
+            RegOp::Max => (val1 as i64).max(val2 as i64) as u64,
+            RegOp::Maxu => val1.max(val2),
+            RegOp::Min => (val1 as i64).min(val2 as i64) as u64,
+            RegOp::Minu => val1.min(val2),
+            RegOp::SextB => val1 as i8 as u64,
+            RegOp::SextH => val1 as i16 as u64,
+            RegOp::ZextH => val1 & 0xffff,
+            RegOp::Rol => val1.rotate_left(shamt),
+            RegOp::Ror | RegOp::Rori => val1.rotate_right(shamt),
+            RegOp::OrcB => (0..8).fold(0, |acc, i| {
+                if (val1 >> (8 * i)) & 0xff != 0 {
+                    acc | (0xff << (8 * i))
+                } else {
+                    acc
+                }
+            }),
+            RegOp::Rev8 => val1.swap_bytes(),
+            RegOp::Clmul => Self::carryless_mul(val1, val2) as u64,
+            RegOp::Clmulh => (Self::carryless_mul(val1, val2) >> 64) as u64,
+            RegOp::Clmulr => (Self::carryless_mul(val1, val2) >> 63) as u64,
+            RegOp::Bclr | RegOp::Bclri => val1 & !(1 << shamt),
+            RegOp::Bext | RegOp::Bexti => (val1 >> shamt) & 1,
+            RegOp::Binv | RegOp::Binvi => val1 ^ (1 << shamt),
+            RegOp::Bset | RegOp::Bseti => val1 | (1 << shamt),
+            RegOp::Addw | RegOp::Addiw => word(RegOp::Add),
+            RegOp::Subw => word(RegOp::Sub),
+            RegOp::Sllw | RegOp::Slliw => word(RegOp::Sll),
+            RegOp::Srlw | RegOp::Srliw => word(RegOp::Srl),
+            RegOp::Sraw | RegOp::Sraiw => word(RegOp::Sra),
+            RegOp::Mulw => word(RegOp::Mul),
+            RegOp::Divw => word(RegOp::Div),
+            RegOp::Divuw => word(RegOp::Divu),
+            RegOp::Remw => word(RegOp::Rem),
+            RegOp::Remuw => word(RegOp::Remu),
+            RegOp::Clzw => word(RegOp::Clz),
+            RegOp::Ctzw => word(RegOp::Ctz),
+            RegOp::Cpopw => word(RegOp::Cpop),
+            RegOp::Rolw => word(RegOp::Rol),
+            RegOp::Rorw | RegOp::Roriw => word(RegOp::Ror),
+            // .uw 指令先把 rs1 的低 32 位零扩展
+            RegOp::AddUw => uw.wrapping_add(val2),
+            RegOp::Sh1addUw => (uw << 1).wrapping_add(val2),
+            RegOp::Sh2addUw => (uw << 2).wrapping_add(val2),
+            RegOp::Sh3addUw => (uw << 3).wrapping_add(val2),
+            RegOp::SlliUw => uw << shamt,
         }
     }
 
-    fn fetch(&mut self) -> Result<u32, &'static str> {
-        self.read(self.pc as usize, 4)
+    fn carryless_mul(val1: u64, val2: u64) -> u128 {
+        (0..64)
+            .filter(|i| (val2 >> i) & 1 != 0)
+            .fold(0, |acc, i| acc ^ ((val1 as u128) << i))
+    }
+
+    fn execute_atomic_op(
+        &mut self,
+        op: AmoOp,
+        rd: usize,
+        rs1: usize,
+        rs2: usize,
+        size: usize,
+    ) -> Result<(), Exception> {
+        let addr = self.registers.read(rs1);
+        // .W 指令的操作数和结果都按 32 位符号扩展，64 位比较与 32 位比较结果一致
+        let src = sign_extend(self.registers.read(rs2), size);
+        let (watch_addr, watch_len) = (addr, size as u64);
+
+        // 原子指令要求自然对齐，LR 按读访问处理，SC/AMO 按写访问处理
+        let access = match op {
+            AmoOp::Lr => AccessType::Load,
+            _ => AccessType::Store,
+        };
+        if !addr.is_multiple_of(size as u64) {
+            return Err(match access {
+                AccessType::Load => Exception::LoadAddressMisaligned(addr),
+                _ => Exception::StoreAddressMisaligned(addr),
+            });
+        }
+        // 保留按物理地址记录，与其他虚拟地址上的写入比较
+        let paddr = self.translate(addr, size, access)?;
+        let fault = access.access_fault(addr);
+
+        match op {
+            AmoOp::Lr => {
+                self.debugger
+                    .check_watchpoints(watch_addr, watch_len, false);
+                let value = self.load_physical(paddr, size).map_err(|_| fault)?;
+                self.memory.set_reservation(paddr as usize, size);
+                self.registers.write(rd, sign_extend(value, size));
+            }
+            AmoOp::Sc => {
+                if self.memory.take_reservation(paddr as usize, size) {
+                    self.debugger.check_watchpoints(watch_addr, watch_len, true);
+                    self.store_physical(paddr, src, size).map_err(|_| fault)?;
+                    self.registers.write(rd, 0);
+                } else {
+                    self.registers.write(rd, 1);
+                }
+            }
+            _ => {
+                self.debugger
+                    .check_watchpoints(watch_addr, watch_len, false);
+                self.debugger.check_watchpoints(watch_addr, watch_len, true);
+                let old = self.load_physical(paddr, size).map_err(|_| fault)?;
+                let old = sign_extend(old, size);
+                let new = match op {
+                    AmoOp::Swap => src,
+                    AmoOp::Add => old.wrapping_add(src),
+                    AmoOp::Xor => old ^ src,
+                    AmoOp::And => old & src,
+                    AmoOp::Or => old | src,
+                    AmoOp::Min => (old as i64).min(src as i64) as u64,
+                    AmoOp::Max => (old as i64).max(src as i64) as u64,
+                    AmoOp::Minu => old.min(src),
+                    AmoOp::Maxu => old.max(src),
+                    AmoOp::Lr | AmoOp::Sc => unreachable!(),
+                };
+                self.store_physical(paddr, new, size).map_err(|_| fault)?;
+                self.registers.write(rd, old);
+            }
+        }
+        Ok(())
+    }
+
+    fn fp_size(fmt: FpFormat) -> usize {
+        match fmt {
+            FpFormat::Single => 4,
+            FpFormat::Double => 8,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute_fp_op(
+        &mut self,
+        op: FpOp,
+        fmt: FpFormat,
+        rm: RoundingMode,
+        rd: usize,
+        rs1: usize,
+        rs2: usize,
+        rs3: usize,
+    ) {
+        let a = self.fp_registers.read(rs1, fmt);
+        let b = self.fp_registers.read(rs2, fmt);
+        let c = self.fp_registers.read(rs3, fmt);
+        let x = self.registers.read(rs1);
+
+        let (result, flags) = match op {
+            FpOp::Madd | FpOp::Msub | FpOp::Nmsub | FpOp::Nmadd => {
+                let negate_product = matches!(op, FpOp::Nmsub | FpOp::Nmadd);
+                let negate_addend = matches!(op, FpOp::Msub | FpOp::Nmadd);
+                let (value, flags) =
+                    fpu::fused_mul_add(fmt, a, b, c, negate_product, negate_addend, rm);
+                (FpResult::Float(value), flags)
+            }
+            FpOp::Add => float_result(fpu::add(fmt, a, b, rm)),
+            FpOp::Sub => float_result(fpu::sub(fmt, a, b, rm)),
+            FpOp::Mul => float_result(fpu::mul(fmt, a, b, rm)),
+            FpOp::Div => float_result(fpu::div(fmt, a, b, rm)),
+            FpOp::Sqrt => float_result(fpu::sqrt(fmt, a, rm)),
+            FpOp::Sgnj => (
+                FpResult::Float(fpu::sign_inject(fmt, a, b, false, false)),
+                0,
+            ),
+            FpOp::Sgnjn => (FpResult::Float(fpu::sign_inject(fmt, a, b, true, false)), 0),
+            FpOp::Sgnjx => (FpResult::Float(fpu::sign_inject(fmt, a, b, false, true)), 0),
+            FpOp::Min => float_result(fpu::min_max(fmt, a, b, false)),
+            FpOp::Max => float_result(fpu::min_max(fmt, a, b, true)),
+            FpOp::CvtFmt => {
+                let from = match fmt {
+                    FpFormat::Single => FpFormat::Double,
+                    FpFormat::Double => FpFormat::Single,
+                };
+                let value = self.fp_registers.read(rs1, from);
+                float_result(fpu::convert(from, fmt, value, rm))
+            }
+            // 32 位转换的结果符号扩展到 XLEN
+            FpOp::CvtW | FpOp::CvtWu | FpOp::CvtL | FpOp::CvtLu => {
+                let signed = matches!(op, FpOp::CvtW | FpOp::CvtL);
+                let width = if matches!(op, FpOp::CvtW | FpOp::CvtWu) {
+                    32
+                } else {
+                    64
+                };
+                let (value, flags) = fpu::to_int(fmt, a, signed, width, rm);
+                (FpResult::Int(value), flags)
+            }
+            FpOp::CvtFromW => float_result(fpu::from_int(fmt, x, true, 32, rm)),
+            FpOp::CvtFromWu => float_result(fpu::from_int(fmt, x, false, 32, rm)),
+            FpOp::CvtFromL => float_result(fpu::from_int(fmt, x, true, 64, rm)),
+            FpOp::CvtFromLu => float_result(fpu::from_int(fmt, x, false, 64, rm)),
+            // FMV.X.W 把单精度位模式符号扩展
+            FpOp::MvToInt => {
+                let raw = self.fp_registers.read_raw(rs1);
+                let value = match fmt {
+                    FpFormat::Single => raw as u32 as i32 as u64,
+                    FpFormat::Double => raw,
+                };
+                (FpResult::Int(value), 0)
+            }
+            FpOp::MvFromInt => (FpResult::Float(x), 0),
+            FpOp::Eq => int_result(fpu::eq(fmt, a, b)),
+            FpOp::Lt => int_result(fpu::lt(fmt, a, b)),
+            FpOp::Le => int_result(fpu::le(fmt, a, b)),
+            FpOp::Class => (FpResult::Int(fpu::classify(fmt, a) as u64), 0),
+        };
+
+        match result {
+            FpResult::Float(value) => {
+                self.fp_registers.write(rd, fmt, value);
+                self.csrs.mark_fp_dirty();
+            }
+            FpResult::Int(value) => self.registers.write(rd, value),
+        }
+        if flags != 0 {
+            self.csrs.accrue_fflags(flags);
+            self.csrs.mark_fp_dirty();
+        }
+    }
+
+    // FPU 关闭时没有可用的舍入模式；rm = 7 时使用 frm，frm 中的保留值同样是非法指令
+    fn fp_rounding_mode(&self, rm: u32) -> Option<RoundingMode> {
+        if !self.csrs.fpu_enabled() {
+            return None;
+        }
+        let rm = if rm == 7 { self.csrs.frm() } else { rm };
+        RoundingMode::from_bits(rm)
+    }
+
+    fn execute_csr_op(
+        &mut self,
+        op: CsrOp,
+        rd: usize,
+        rs1: usize,
+        csr: u16,
+    ) -> Result<(), EmuError> {
+        self.csrs.check_access(csr)?;
+        let src = match op {
+            CsrOp::Rw | CsrOp::Rs | CsrOp::Rc => self.registers.read(rs1),
+            CsrOp::Rwi | CsrOp::Rsi | CsrOp::Rci => rs1 as u64, // uimm
+        };
+
+        // CSRRW 的 rd=x0 时不读 CSR；CSRRS/CSRRC 的 rs1=x0 (uimm=0) 时不写 CSR
+        let old = match op {
+            CsrOp::Rw | CsrOp::Rwi if rd == 0 => None,
+            _ => Some(self.csrs.read(csr)?),
+        };
+        let new = match op {
+            CsrOp::Rw | CsrOp::Rwi => Some(src),
+            CsrOp::Rs | CsrOp::Rsi if rs1 != 0 => old.map(|v| v | src),
+            CsrOp::Rc | CsrOp::Rci if rs1 != 0 => old.map(|v| v & !src),
+            _ => None,
+        };
+
+        if let Some(value) = new {
+            self.csrs.write(csr, value)?;
+        }
+        if let Some(value) = old {
+            self.registers.write(rd, value);
+        }
+        Ok(())
+    }
+
+    fn instruction_alignment(&self) -> u64 {
+        if self.isa.has(Extension::C) {
+            2
+        } else {
+            4
+        }
+    }
+
+    // 取指：16 位压缩指令只读取低半字
+    fn fetch(&mut self) -> Result<u32, Exception> {
+        let pc = self.pc;
+        let high_addr = pc.wrapping_add(2) & self.isa.xlen().mask();
+        if !pc.is_multiple_of(self.instruction_alignment()) {
+            return Err(Exception::InstructionAddressMisaligned(pc));
+        }
+        // 4 字节对齐的字不会跨越 PMP 区域的边界
+        let len = if pc.is_multiple_of(4) { 4 } else { 2 };
+        let paddr = self.translate(pc, len, AccessType::Fetch)?;
+        if pc.is_multiple_of(4) {
+            return self
+                .read(paddr as usize, 4)
+                .map(|inst| {
+                    if rvc::is_compressed(inst) {
+                        inst & 0xffff
+                    } else {
+                        inst
+                    }
+                })
+                .map_err(|_| Exception::InstructionAccessFault(pc));
+        }
+
+        let low = self
+            .read(paddr as usize, 2)
+            .map_err(|_| Exception::InstructionAccessFault(pc))?;
+        if rvc::is_compressed(low) {
+            return Ok(low);
+        }
+        // 跨页的 32 位指令，高半字单独转换
+        let high_paddr = self.translate(high_addr, 2, AccessType::Fetch)?;
+        let high = self
+            .read(high_paddr as usize, 2)
+            .map_err(|_| Exception::InstructionAccessFault(high_addr))?;
+        Ok(low | (high << 16))
+    }
+
+    // 地址转换后按物理地址做 PMP 检查，取指还要求所在区域可执行
+    fn translate(&mut self, addr: u64, size: usize, access: AccessType) -> Result<u64, Exception> {
+        let paddr = self
+            .mmu
+            .translate(&mut self.memory, &self.csrs, addr, access)?;
+        let privilege = mmu::effective_privilege(&self.csrs, access);
+        if !self.csrs.pmp().check(paddr, size, access, privilege) {
+            return Err(access.access_fault(addr));
+        }
+        if access == AccessType::Fetch && !self.memory.executable(paddr as usize) {
+            return Err(access.access_fault(addr));
+        }
+        Ok(paddr)
+    }
+
+    // 虚拟地址访问：自然对齐的访问不会跨页，只需转换一次
+    fn load(&mut self, addr: u64, size: usize) -> Result<u64, Exception> {
+        let paddr = self.translate(addr, size, AccessType::Load)?;
+        self.load_physical(paddr, size)
+            .map_err(|_| Exception::LoadAccessFault(addr))
+    }
+
+    fn store(&mut self, addr: u64, value: u64, size: usize) -> Result<(), Exception> {
+        let paddr = self.translate(addr, size, AccessType::Store)?;
+        self.store_physical(paddr, value, size)
+            .map_err(|_| Exception::StoreAccessFault(addr))
+    }
+
+    // 8 字节的访问拆成两次 4 字节访问
+    fn load_physical(&mut self, addr: u64, size: usize) -> Result<u64, EmuError> {
+        if size <= 4 {
+            return self.read(addr as usize, size).map(u64::from);
+        }
+        let low = self.read(addr as usize, 4)?;
+        let high = self.read(addr as usize + 4, 4)?;
+        Ok(((high as u64) << 32) | low as u64)
+    }
+
+    fn store_physical(&mut self, addr: u64, value: u64, size: usize) -> Result<(), EmuError> {
+        if size <= 4 {
+            return self.write(addr as usize, value as u32, size);
+        }
+        self.write(addr as usize, value as u32, 4)?;
+        self.write(addr as usize + 4, (value >> 32) as u32, 4)
     }
 
     // memory read/write
-    fn read(&mut self, addr: usize, len: usize) -> Result<u32, &'static str> {
+    fn read(&mut self, addr: usize, len: usize) -> Result<u32, EmuError> {
         let value = self.memory.vread(addr, len)?;
-        if self.debugger.mtrace_enabled {
+        if self.debugger.mtrace_active() {
             self.debugger.trace_memory_read(addr, len, value);
         }
         Ok(value)
     }
 
-    fn write(&mut self, addr: usize, value: u32, len: usize) -> Result<(), &'static str> {
-        if self.debugger.mtrace_enabled {
+    fn write(&mut self, addr: usize, value: u32, len: usize) -> Result<(), EmuError> {
+        if self.debugger.mtrace_active() {
             self.debugger.trace_memory_write(addr, len, value);
         }
         self.memory.vwrite(addr, value, len)
     }
 
-    fn set_pc(&mut self, new_pc: u32) -> Result<(), &'static str> {
-        if new_pc % 4 != 0 {
-            return Err("PC must be aligned to 4 bytes");
+    // 按 ISA 字符串选择 XLEN 并启用扩展，同时更新 misa
+    pub fn set_isa(&mut self, isa: Isa) {
+        self.isa = isa;
+        self.registers.set_xlen(isa.xlen());
+        self.csrs.set_isa(&isa);
+        self.pc &= isa.xlen().mask();
+    }
+
+    pub fn isa(&self) -> Isa {
+        self.isa
+    }
+
+    pub fn xlen(&self) -> Xlen {
+        self.isa.xlen()
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    // 挂载自定义外设，访问该地址范围的读写转发给设备
+    pub fn register_device(
+        &mut self,
+        base: usize,
+        device: Box<dyn MmioDevice>,
+    ) -> Result<(), EmuError> {
+        self.memory.register_device(base, device)
+    }
+
+    pub fn pc(&self) -> u64 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, new_pc: u64) -> Result<(), EmuError> {
+        if !new_pc.is_multiple_of(2) {
+            return Err(EmuError::MisalignedAccess {
+                addr: new_pc,
+                size: 2,
+            });
         }
         self.pc = new_pc;
         Ok(())
@@ -213,19 +1135,111 @@ impl Cpu {
     // 添加新方法
     pub fn load_program(&mut self, filename: &str) -> std::io::Result<()> {
         let loader = Loader::new();
-        loader.load_program(&mut self.memory, filename)?;
+        let program = loader.load_program(&mut self.memory, filename, self.isa.xlen())?;
+        self.debugger.symbols = program.symbols;
 
         // 设置 PC 为程序入口点
-        self.set_pc(loader.get_entry_point() as u32)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        self.set_pc(program.entry).map_err(std::io::Error::other)?;
+
+        // 裸机程序自己设置 sp，不占用它的内存
+        if !self.host_syscalls {
+            return Ok(());
+        }
+
+        // 在入口所在 RAM 区域的顶端建立 riscv-pk 风格的初始栈，堆从程序映像末尾增长到栈下方
+        let ram = self
+            .memory
+            .regions()
+            .filter(|r| r.kind == RegionKind::Ram)
+            .find(|r| (r.base..r.end()).contains(&(program.entry as usize)))
+            .copied();
+        if let Some(ram) = ram {
+            let mut top = ram.end() as u64;
+            if self.isa.xlen() == Xlen::Rv32 {
+                top = top.min(1 << 32);
+            }
+            let (sp, stack) = syscall::initial_stack(top, &[filename], self.isa.xlen());
+            self.memory
+                .write_bytes(sp as usize, &stack)
+                .map_err(std::io::Error::other)?;
+            self.registers.write(2, sp);
+            self.syscalls
+                .set_brk(program.end, sp.saturating_sub(syscall::STACK_SIZE));
+        }
 
         Ok(())
     }
 
+    // 关闭后 ECALL 总是作为异常进入客户机的陷阱处理程序（裸机程序、riscv-tests），
+    // load_program 也不再建立初始栈，需要在加载程序之前设置
+    pub fn set_host_syscalls(&mut self, enabled: bool) {
+        self.host_syscalls = enabled;
+    }
+
+    // 严格 PMP：没有匹配表项的 S/U 模式访问失败，即使所有表项都关闭
+    pub fn set_pmp_strict(&mut self, strict: bool) {
+        self.csrs.pmp_mut().set_strict(strict);
+    }
+
+    // 客户机的 openat 只能访问该目录下的文件
+    pub fn set_sandbox(&mut self, dir: &std::path::Path) -> std::io::Result<()> {
+        self.syscalls.set_sandbox(dir)
+    }
+
+    pub fn read_register(&self, index: usize) -> u64 {
+        self.registers.read(index)
+    }
+
+    pub fn write_register(&mut self, index: usize, value: u64) {
+        self.registers.write(index, value);
+    }
+
+    pub fn exit_code(&self) -> Option<u32> {
+        self.exit_code
+    }
+
+    // 调试器访存：不经过 mtrace，也不触发观察点
+    // 调试访问使用当前地址空间的虚拟地址
+    fn debug_translate(&mut self, addr: u64) -> Result<usize, EmuError> {
+        self.mmu
+            .translate_debug(&mut self.memory, &self.csrs, addr)
+            .map(|paddr| paddr as usize)
+    }
+
+    pub fn debug_read(&mut self, addr: u64, len: usize) -> Result<u32, EmuError> {
+        let paddr = self.debug_translate(addr)?;
+        self.memory.vread(paddr, len)
+    }
+
+    pub fn debug_write(&mut self, addr: u64, value: u32, len: usize) -> Result<(), EmuError> {
+        let paddr = self.debug_translate(addr)?;
+        self.memory.vwrite(paddr, value, len)
+    }
+
+    // 调试器读取指令：按半字读取，支持 2 字节对齐的 32 位指令
+    pub fn debug_fetch(&mut self, addr: u64) -> Result<u32, EmuError> {
+        let paddr = self.debug_translate(addr)?;
+        let low = self.memory.vread(paddr, 2)?;
+        if rvc::is_compressed(low) {
+            return Ok(low);
+        }
+        let high_paddr = self.debug_translate(addr.wrapping_add(2))?;
+        let high = self.memory.vread(high_paddr, 2)?;
+        Ok(low | (high << 16))
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
     // 添加内存转储方法（用于调试）
-    pub fn dump_memory(&mut self, start: u32, length: usize) -> Vec<u8> {
+    pub fn dump_memory(&mut self, start: u64, length: usize) -> Vec<u8> {
         if let Ok(data) = self.memory.read_bytes(start as usize, length) {
-            data.to_vec()
+            data
         } else {
             // fallback if read_bytes fails or is not implemented for the region
             let mut result = Vec::with_capacity(length);
@@ -259,9 +1273,818 @@ impl Cpu {
 
     pub fn show_registers(&self) {
         if self.debugger.regtrace_enabled {
-            println!("=== Register State ===");
-            println!("PC: 0x{:08x}", self.pc);
-            self.registers.dump();
+            self.dump_registers();
+        }
+    }
+
+    pub fn dump_registers(&self) {
+        println!("=== Register State ===");
+        println!(
+            "PC: 0x{:08x}{}  Mode: {}",
+            self.pc,
+            self.debugger.symbols.describe(self.pc),
+            self.csrs.privilege().name()
+        );
+        self.registers.dump(&self.debugger.symbols);
+    }
+
+    pub fn dump_fp_registers(&self) {
+        println!("=== FP Register State ===");
+        println!(
+            "fcsr: 0x{:02x}",
+            self.csrs.read(crate::csr::FCSR).unwrap_or(0)
+        );
+        self.fp_registers.dump();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_words(words: &[u32]) -> Cpu {
+        let mut cpu = Cpu::new(0x03000000);
+        cpu.set_itrace(false);
+        cpu.set_mtrace(false);
+        cpu.set_regtrace(false);
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        cpu.memory.write_bytes(0x80000000, &bytes).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_trap_and_mret() {
+        let mut cpu = load_words(&[
+            0x800002b7, // lui t0, 0x80000
+            0x02028293, // addi t0, t0, 0x20
+            0x30529073, // csrw mtvec, t0
+            0x00000000, // 非法指令
+            0x02a00513, // addi a0, zero, 42
+            0x00000013, // nop
+            0x00000013, // nop
+            0x00000013, // nop
+            // 0x80000020: 陷阱处理程序
+            0x34102373, // csrr t1, mepc
+            0x00430313, // addi t1, t1, 4
+            0x34131073, // csrw mepc, t1
+            0x30200073, // mret
+        ]);
+
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.pc, 0x80000020);
+        assert_eq!(cpu.csrs.read(crate::csr::MCAUSE).unwrap(), 2);
+        assert_eq!(cpu.csrs.read(crate::csr::MEPC).unwrap(), 0x8000000c);
+
+        for _ in 0..5 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.registers.read(10), 42);
+        assert_eq!(cpu.pc, 0x80000014);
+    }
+
+    #[test]
+    fn test_privilege_modes_and_delegation() {
+        let mut cpu = load_words(&[
+            0x800002b7, // lui t0, 0x80000
+            0x04028293, // addi t0, t0, 0x40
+            0x10529073, // csrw stvec, t0
+            0x800002b7, // lui t0, 0x80000
+            0x06028293, // addi t0, t0, 0x60
+            0x30529073, // csrw mtvec, t0
+            0x10000293, // li t0, 0x100        委托 U 模式 ecall
+            0x30229073, // csrw medeleg, t0
+            0x800002b7, // lui t0, 0x80000
+            0x03428293, // addi t0, t0, 0x34
+            0x34129073, // csrw mepc, t0
+            0x30001073, // csrw mstatus, zero  MPP = U
+            0x30200073, // mret
+            // 0x80000034: U 模式
+            0x00000073, // ecall
+            0x30002573, // csrr a0, mstatus    U 模式访问 M 模式 CSR
+            0x00000013, // nop
+            // 0x80000040: S 模式处理程序
+            0x142025f3, // csrr a1, scause
+            0x14102673, // csrr a2, sepc
+            0x00460613, // addi a2, a2, 4
+            0x14161073, // csrw sepc, a2
+            0x10200073, // sret
+            0x00000013, // nop
+            0x00000013, // nop
+            0x00000013, // nop
+            // 0x80000060: M 模式处理程序
+            0x342026f3, // csrr a3, mcause
+            0x34102773, // csrr a4, mepc
+        ]);
+        cpu.set_host_syscalls(false);
+
+        for _ in 0..13 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.pc, 0x80000034);
+        assert_eq!(cpu.csrs.privilege(), Privilege::User);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x80000040);
+        assert_eq!(cpu.csrs.privilege(), Privilege::Supervisor);
+
+        for _ in 0..5 {
+            cpu.step().unwrap();
         }
+        assert_eq!(cpu.pc, 0x80000038);
+        assert_eq!(cpu.csrs.privilege(), Privilege::User);
+        assert_eq!(cpu.registers.read(11), 8);
+
+        // 非法指令没有委托，进入 M 模式
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.csrs.privilege(), Privilege::Machine);
+        assert_eq!(cpu.registers.read(10), 0);
+        assert_eq!(cpu.registers.read(13), 2);
+        assert_eq!(cpu.registers.read(14), 0x80000038);
+        assert_eq!(cpu.csrs.mstatus() & crate::csr::MSTATUS_MPP, 0);
+    }
+
+    #[test]
+    fn test_delegation_without_mtvec() {
+        // 只设置 stvec：委托给 S 模式的异常进入 S 模式处理程序，而不是返回给主机
+        let mut cpu = load_words(&[
+            0x800002b7, // lui t0, 0x80000
+            0x04028293, // addi t0, t0, 0x40
+            0x10529073, // csrw stvec, t0
+            0x10000293, // li t0, 0x100        委托 U 模式 ecall
+            0x30229073, // csrw medeleg, t0
+            0x800002b7, // lui t0, 0x80000
+            0x02828293, // addi t0, t0, 0x28
+            0x34129073, // csrw mepc, t0
+            0x30001073, // csrw mstatus, zero  MPP = U
+            0x30200073, // mret
+            // 0x80000028: U 模式
+            0x00000073, // ecall
+            0x00000013, // nop
+            0x00000013, // nop
+            0x00000013, // nop
+            0x00000013, // nop
+            0x00000013, // nop
+            // 0x80000040: S 模式处理程序
+            0x142025f3, // csrr a1, scause
+        ]);
+        cpu.set_host_syscalls(false);
+
+        for _ in 0..11 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.pc, 0x80000040);
+        assert_eq!(cpu.csrs.privilege(), Privilege::Supervisor);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.read(11), 8);
+    }
+
+    #[test]
+    fn test_sv32_paging() {
+        let mut words = vec![
+            0x800802b7, // lui t0, 0x80080
+            0x01028293, // addi t0, t0, 0x10   Sv32，根页表 0x80010000
+            0x18029073, // csrw satp, t0
+            0x800002b7, // lui t0, 0x80000
+            0x10028293, // addi t0, t0, 0x100
+            0x30529073, // csrw mtvec, t0
+            0x800002b7, // lui t0, 0x80000
+            0x03428293, // addi t0, t0, 0x34
+            0x34129073, // csrw mepc, t0
+            0x00100293, // li t0, 1
+            0x00b29293, // slli t0, t0, 11     MPP = S
+            0x30029073, // csrw mstatus, t0
+            0x30200073, // mret
+            // 0x80000034: S 模式
+            0x004015b7, // lui a1, 0x401       与取指不在同一 TLB 项
+            0x02a00613, // li a2, 42
+            0x00c5a023, // sw a2, 0(a1)
+            0x0005a683, // lw a3, 0(a1)
+            0x12000073, // sfence.vma
+            0x0005a703, // lw a4, 0(a1)
+            0x004027b7, // lui a5, 0x402
+            0x00c7a023, // sw a2, 0(a5)        只读页
+        ];
+        words.resize(0x40, 0x00000013);
+        // 0x80000100: M 模式处理程序
+        words.extend([
+            0x34202873, // csrr a6, mcause
+            0x343028f3, // csrr a7, mtval
+        ]);
+        let mut cpu = load_words(&words);
+
+        let write_word = |cpu: &mut Cpu, addr: u32, value: u32| {
+            cpu.memory
+                .write_bytes(addr as usize, &value.to_le_bytes())
+                .unwrap()
+        };
+        // 0x80000000 开始的 4 MiB 大页恒等映射，0x00401000 起经二级页表映射
+        write_word(&mut cpu, 0x80010000 + 0x200 * 4, 0x200000cf);
+        write_word(&mut cpu, 0x80010000 + 4, 0x20004401);
+        write_word(&mut cpu, 0x80011004, 0x20008007); // -> 0x80020000，可读写
+        write_word(&mut cpu, 0x80011008, 0x20008403); // -> 0x80021000，只读
+        write_word(&mut cpu, 0x80021000, 7);
+
+        for _ in 0..16 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.csrs.privilege(), Privilege::Supervisor);
+        assert_eq!(cpu.debug_read(0x00401000, 4).unwrap(), 42);
+        assert_eq!(cpu.memory.vread(0x80020000, 4).unwrap(), 42);
+        // 写访问置位 A/D
+        assert_eq!(cpu.memory.vread(0x80011004, 4).unwrap(), 0x200080c7);
+
+        // 修改页表后，在 SFENCE.VMA 之前仍使用 TLB 中的旧映射
+        write_word(&mut cpu, 0x80011004, 0x200084c7);
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.registers.read(13), 42);
+        assert_eq!(cpu.registers.read(14), 7);
+
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.csrs.privilege(), Privilege::Machine);
+        assert_eq!(cpu.registers.read(16), 15);
+        assert_eq!(cpu.registers.read(17), 0x00402000);
+        assert_eq!(cpu.memory.vread(0x80011008, 4).unwrap(), 0x20008403);
+    }
+
+    #[test]
+    fn test_pmp_sandbox() {
+        let mut words = vec![
+            0x800002b7, // lui t0, 0x80000
+            0x10028293, // addi t0, t0, 0x100
+            0x30529073, // csrw mtvec, t0
+            0x200002b7, // lui t0, 0x20000
+            0x1ff28293, // addi t0, t0, 0x1ff
+            0x3b029073, // csrw pmpaddr0, t0   0x80000000 起 4 KiB NAPOT
+            0x200012b7, // lui t0, 0x20001
+            0x80028293, // addi t0, t0, -2048
+            0x3b129073, // csrw pmpaddr1, t0   0x80002000 NA4
+            0x40028293, // addi t0, t0, 0x400
+            0x3b229073, // csrw pmpaddr2, t0   0x80003000 NA4
+            0x009012b7, // lui t0, 0x901
+            0x11d28293, // addi t0, t0, 0x11d
+            0x3a029073, // csrw pmpcfg0, t0    RX / R / 锁定且无权限
+            0x800002b7, // lui t0, 0x80000
+            0x05028293, // addi t0, t0, 0x50
+            0x34129073, // csrw mepc, t0
+            0x30001073, // csrw mstatus, zero  MPP = U
+            0x30200073, // mret
+            0x00000013, // nop
+            // 0x80000050: U 模式
+            0x800025b7, // lui a1, 0x80002
+            0x0005a603, // lw a2, 0(a1)
+            0x00c5a023, // sw a2, 0(a1)        只读区域
+        ];
+        words.resize(0x40, 0x00000013);
+        // 0x80000100: M 模式处理程序
+        words.extend([
+            0x342026f3, // csrr a3, mcause
+            0x34302773, // csrr a4, mtval
+            0x80003337, // lui t1, 0x80003
+            0x00032023, // sw zero, 0(t1)      锁定的区域对 M 模式也生效
+        ]);
+        let mut cpu = load_words(&words);
+        cpu.memory
+            .write_bytes(0x80002000, &7u32.to_le_bytes())
+            .unwrap();
+
+        for _ in 0..21 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.csrs.privilege(), Privilege::User);
+        assert_eq!(cpu.registers.read(12), 7);
+
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.registers.read(13), 7);
+        assert_eq!(cpu.registers.read(14), 0x80002000);
+        assert_eq!(cpu.memory.vread(0x80002000, 4).unwrap(), 7);
+
+        for _ in 0..2 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.pc, 0x80000100);
+        assert_eq!(cpu.csrs.read(crate::csr::MEPC).unwrap(), 0x8000010c);
+        assert_eq!(cpu.csrs.read(crate::csr::MCAUSE).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_custom_mmio_device() {
+        // 偏移 0 是可读写的寄存器，偏移 4 是只读的 tick 次数，寄存器非零时拉高中断线
+        struct Scratch {
+            value: u32,
+            ticks: u32,
+        }
+
+        impl MmioDevice for Scratch {
+            fn name(&self) -> &str {
+                "scratch"
+            }
+
+            fn size(&self) -> usize {
+                8
+            }
+
+            fn read(&mut self, offset: usize, _size: usize) -> Result<u32, EmuError> {
+                Ok(if offset == 0 { self.value } else { self.ticks })
+            }
+
+            fn write(&mut self, offset: usize, value: u32, _size: usize) -> Result<(), EmuError> {
+                if offset != 0 {
+                    return Err(EmuError::device(self.name(), offset));
+                }
+                self.value = value;
+                Ok(())
+            }
+
+            fn tick(&mut self) {
+                self.ticks += 1;
+            }
+
+            fn interrupt_lines(&self) -> u32 {
+                if self.value != 0 {
+                    IRQ_TIMER
+                } else {
+                    0
+                }
+            }
+        }
+
+        let mut cpu = load_words(&[
+            0x10000537, // lui a0, 0x10000
+            0x02a00593, // li a1, 42
+            0x00b52023, // sw a1, 0(a0)
+            0x00052603, // lw a2, 0(a0)
+            0x00452683, // lw a3, 4(a0)
+        ]);
+        let scratch = || Box::new(Scratch { value: 0, ticks: 0 });
+        cpu.register_device(0x10000000, scratch()).unwrap();
+        assert_eq!(
+            cpu.register_device(0x02000008, scratch()),
+            Err(EmuError::DeviceOverlap {
+                base: 0x02000008,
+                size: 8
+            })
+        );
+        assert_eq!(
+            cpu.register_device(usize::MAX - 4, scratch()),
+            Err(EmuError::InvalidDeviceRange {
+                base: usize::MAX - 4,
+                size: 8
+            })
+        );
+        assert_eq!(
+            cpu.set_pc(0x80000001),
+            Err(EmuError::MisalignedAccess {
+                addr: 0x80000001,
+                size: 2
+            })
+        );
+
+        for _ in 0..5 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.registers.read(12), 42);
+        assert_eq!(cpu.registers.read(13), 4);
+        assert_eq!(cpu.memory.check_interrupts(), IRQ_TIMER);
+        assert_eq!(
+            cpu.debug_write(0x10000004, 0, 4),
+            Err(EmuError::DeviceError {
+                device: "scratch".to_string(),
+                offset: 4
+            })
+        );
+        assert!(cpu
+            .memory
+            .devices()
+            .iter()
+            .any(|(base, device)| base == 0x10000000 && device.name() == "scratch"));
+    }
+
+    #[test]
+    fn test_timer_interrupt() {
+        let mut cpu = load_words(&[
+            0x800002b7, // lui t0, 0x80000
+            0x04028293, // addi t0, t0, 0x40
+            0x30529073, // csrw mtvec, t0
+            0x08000313, // addi t1, zero, 0x80
+            0x30431073, // csrw mie, t1 (MTIE)
+            0x30046073, // csrsi mstatus, 8 (MIE)
+            0x020003b7, // lui t2, 0x2000
+            0x20038393, // addi t2, t2, 0x200
+            0x00300313, // addi t1, zero, 3
+            0x0063a423, // sw t1, 8(t2)   TIMER_COMPARE = 3
+            0x0063a223, // sw t1, 4(t2)   TIMER_CONTROL = ENABLE | INTERRUPT
+            0x00000013, // nop
+            0x00000013, // nop
+            0x00000013, // nop
+            0x00000013, // nop
+            0x00000013, // nop
+            // 0x80000040: 中断处理程序
+            0x34202573, // csrr a0, mcause
+        ]);
+
+        for _ in 0..16 {
+            if cpu.pc == 0x80000040 {
+                break;
+            }
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.pc, 0x80000040);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.read(10), 0x80000007);
+        // 进入陷阱后 MIE 被清零，不会重复响应
+        assert_eq!(cpu.csrs.pending_interrupt(), None);
+    }
+
+    #[test]
+    fn test_compressed_instructions() {
+        let mut cpu = load_words(&[
+            0x05054515, // c.li a0, 5; c.addi a0, 1
+            0x00050593, // addi a1, a0, 0
+            0x00012019, // c.jal 6; c.nop
+            0x06130001, // c.nop; addi a2, zero, 7 (低半字)
+            0x00000070, // addi a2, zero, 7 (高半字)
+        ]);
+
+        for _ in 0..5 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.registers.read(10), 6);
+        assert_eq!(cpu.registers.read(11), 6);
+        assert_eq!(cpu.registers.read(1), 0x8000000a);
+        assert_eq!(cpu.registers.read(12), 7);
+        assert_eq!(cpu.pc, 0x80000012);
+    }
+
+    #[test]
+    fn test_atomic_instructions() {
+        let mut cpu = load_words(&[
+            0x800005b7, // lui a1, 0x80000
+            0x10058593, // addi a1, a1, 0x100
+            0x00500613, // li a2, 5
+            0x00c5a023, // sw a2, 0(a1)
+            0x1005a52f, // lr.w a0, (a1)
+            0x18c5a2af, // sc.w t0, a2, (a1)    成功
+            0x18c5a32f, // sc.w t1, a2, (a1)    保留已失效
+            0x00c5a3af, // amoadd.w t2, a2, (a1)
+            0x1005a52f, // lr.w a0, (a1)
+            0x0005a023, // sw zero, 0(a1)       冲突写使保留失效
+            0x18c5ae2f, // sc.w t3, a2, (a1)
+            0xffd00693, // li a3, -3
+            0x80d5aeaf, // amomin.w t4, a3, (a1)
+            0xe0d5af2f, // amomaxu.w t5, a3, (a1)
+            0x0005af83, // lw t6, 0(a1)
+        ]);
+
+        for _ in 0..15 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.registers.read(10), 10);
+        assert_eq!(cpu.registers.read(5), 0);
+        assert_eq!(cpu.registers.read(6), 1);
+        assert_eq!(cpu.registers.read(7), 5);
+        assert_eq!(cpu.registers.read(28), 1);
+        assert_eq!(cpu.registers.read(29), 0);
+        assert_eq!(cpu.registers.read(30), 0xfffffffd);
+        assert_eq!(cpu.registers.read(31), 0xfffffffd);
+    }
+
+    #[test]
+    fn test_floating_point_instructions() {
+        let mut cpu = load_words(&[
+            0x800005b7, // lui a1, 0x80000
+            0x10058593, // addi a1, a1, 0x100
+            0x404002b7, // lui t0, 0x40400      3.0f
+            0xf0028553, // fmv.w.x fa0, t0
+            0x00100313, // addi t1, zero, 1
+            0xd00375d3, // fcvt.s.w fa1, t1
+            0x18a5f653, // fdiv.s fa2, fa1, fa0
+            0x00c5a027, // fsw fa2, 0(a1)
+            0x420606d3, // fcvt.d.s fa3, fa2
+            0x00d5b427, // fsd fa3, 8(a1)
+            0x0085b707, // fld fa4, 8(a1)
+            0x00b777d3, // fadd.s fa5, fa4, fa1  fa4 未装箱，按 NaN 处理
+            0xc0051553, // fcvt.w.s a0, fa0, rtz
+            0x00102673, // frflags a2
+            0xa0f7a6d3, // feq.s a3, fa5, fa5
+            0xe0079753, // fclass.s a4, fa5
+        ]);
+
+        for _ in 0..16 {
+            cpu.step().unwrap();
+        }
+        let third = 1.0f32 / 3.0;
+        assert_eq!(cpu.debug_read(0x80000100, 4).unwrap(), third.to_bits());
+        let double = (third as f64).to_bits();
+        assert_eq!(cpu.debug_read(0x80000108, 4).unwrap(), double as u32);
+        assert_eq!(
+            cpu.debug_read(0x8000010c, 4).unwrap(),
+            (double >> 32) as u32
+        );
+        assert_eq!(cpu.fp_registers.read_raw(15), 0xffffffff_7fc00000);
+        assert_eq!(cpu.registers.read(10), 3);
+        assert_eq!(cpu.registers.read(12), crate::fpu::FFLAGS_NX as u64);
+        assert_eq!(cpu.registers.read(13), 0);
+        assert_eq!(cpu.registers.read(14), 1 << 9);
+    }
+
+    #[test]
+    fn test_m_extension_edge_cases() {
+        let alu = Cpu::execute_alu_op;
+        assert_eq!(alu(RegOp::Mul, 0xffffffff, 0xffffffff), 1);
+        assert_eq!(alu(RegOp::Mulh, 0xffffffff, 0xffffffff), 0);
+        assert_eq!(alu(RegOp::Mulhu, 0xffffffff, 0xffffffff), 0xfffffffe);
+        assert_eq!(alu(RegOp::Mulhsu, 0xffffffff, 0xffffffff), 0xffffffff);
+        // 除零
+        assert_eq!(alu(RegOp::Div, 7, 0), 0xffffffff);
+        assert_eq!(alu(RegOp::Divu, 7, 0), 0xffffffff);
+        assert_eq!(alu(RegOp::Rem, 7, 0), 7);
+        assert_eq!(alu(RegOp::Remu, 7, 0), 7);
+        // 有符号溢出
+        assert_eq!(alu(RegOp::Div, 0x80000000, 0xffffffff), 0x80000000);
+        assert_eq!(alu(RegOp::Rem, 0x80000000, 0xffffffff), 0);
+        assert_eq!(alu(RegOp::Div, (-7i32) as u32, 2), (-3i32) as u32);
+        assert_eq!(alu(RegOp::Rem, (-7i32) as u32, 2), (-1i32) as u32);
+    }
+
+    // funct7 = 0x01 的 R 型编码经过译码后执行
+    #[test]
+    fn test_m_extension_decode() {
+        let mut cpu = load_words(&[
+            0xff900293, // li t0, -7
+            0x00300313, // li t1, 3
+            0x02628533, // mul a0, t0, t1
+            0x026295b3, // mulh a1, t0, t1
+            0x0262a633, // mulhsu a2, t0, t1
+            0x0262b6b3, // mulhu a3, t0, t1
+            0x0262c733, // div a4, t0, t1
+            0x0262d7b3, // divu a5, t0, t1
+            0x0262e833, // rem a6, t0, t1
+            0x0262f8b3, // remu a7, t0, t1
+        ]);
+
+        for _ in 0..10 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.registers.read(10), 0xffffffeb);
+        assert_eq!(cpu.registers.read(11), 0xffffffff);
+        assert_eq!(cpu.registers.read(12), 0xffffffff);
+        assert_eq!(cpu.registers.read(13), 2);
+        assert_eq!(cpu.registers.read(14), 0xfffffffe);
+        assert_eq!(cpu.registers.read(15), 0x55555553);
+        assert_eq!(cpu.registers.read(16), 0xffffffff);
+        assert_eq!(cpu.registers.read(17), 0);
+    }
+
+    #[test]
+    fn test_bitmanip_operations() {
+        let alu = Cpu::execute_alu_op;
+        assert_eq!(alu(RegOp::Sh2add, 3, 100), 112);
+        assert_eq!(alu(RegOp::Andn, 0xff00ff00, 0x0ff00ff0), 0xf000f000);
+        assert_eq!(alu(RegOp::Xnor, 0xffff0000, 0xff00ff00), 0xff0000ff);
+        assert_eq!(alu(RegOp::Clz, 0, 0), 32);
+        assert_eq!(alu(RegOp::Ctz, 0x80, 0), 7);
+        assert_eq!(alu(RegOp::Cpop, 0xf0f0f0f0, 0), 16);
+        assert_eq!(alu(RegOp::Min, 0xffffffff, 1), 0xffffffff);
+        assert_eq!(alu(RegOp::Minu, 0xffffffff, 1), 1);
+        assert_eq!(alu(RegOp::SextB, 0x80, 0), 0xffffff80);
+        assert_eq!(alu(RegOp::ZextH, 0xdeadbeef, 0), 0xbeef);
+        assert_eq!(alu(RegOp::Rori, 0x12345678, 0x608), 0x78123456);
+        assert_eq!(alu(RegOp::OrcB, 0x00120300, 0), 0x00ffff00);
+        assert_eq!(alu(RegOp::Rev8, 0x12345678, 0), 0x78563412);
+        assert_eq!(alu(RegOp::Clmul, 0x80000003, 0x3), 0x80000005);
+        assert_eq!(alu(RegOp::Clmulh, 0x80000003, 0x3), 0x1);
+        assert_eq!(alu(RegOp::Clmulr, 0x80000003, 0x3), 0x3);
+        assert_eq!(alu(RegOp::Bexti, 0x8, 0x483), 1);
+        assert_eq!(alu(RegOp::Binv, 0x1, 0x20), 0x0);
+        assert_eq!(alu(RegOp::Bset, 0, 31), 0x80000000);
+    }
+
+    #[test]
+    fn test_disabled_extension_is_illegal() {
+        let mut cpu = load_words(&[
+            0x02b50533, // mul a0, a0, a1
+            0x60059513, // clz a0, a1
+        ]);
+        cpu.set_isa(Isa::parse("rv32im").unwrap());
+        cpu.step().unwrap();
+        assert_eq!(
+            cpu.step(),
+            Err(EmuError::IllegalInstruction {
+                pc: 0x80000004,
+                word: 0x60059513
+            })
+        );
+        assert_eq!(cpu.csrs.read(crate::csr::MISA).unwrap(), 0x40141100);
+    }
+
+    #[test]
+    fn test_host_errors() {
+        // 未安装陷阱处理程序时，异常带着出错的上下文返回给主机
+        let mut cpu = load_words(&[
+            0x80000537, // lui a0, 0x80000
+            0x00252583, // lw a1, 2(a0)
+        ]);
+        cpu.step().unwrap();
+        assert_eq!(
+            cpu.step(),
+            Err(EmuError::MisalignedAccess {
+                addr: 0x80000002,
+                size: 4
+            })
+        );
+
+        let mut cpu = load_words(&[0x00b01023]); // sh a1, 0(zero)
+        assert_eq!(cpu.step(), Err(EmuError::AccessFault { addr: 0 }));
+
+        let mut cpu = load_words(&[0x00100073]); // ebreak
+        assert_eq!(cpu.step(), Err(EmuError::Breakpoint { pc: 0x80000000 }));
+
+        let mut cpu = load_words(&[
+            0x00300513, // li a0, 3
+            0x05d00893, // li a7, 93
+            0x00000073, // ecall
+        ]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.step(), Err(EmuError::Exit { code: 3 }));
+
+        // 主机系统调用与 mtvec 无关：安装了处理程序的程序同样可以调用 exit
+        let mut cpu = load_words(&[
+            0x800002b7, // lui t0, 0x80000
+            0x30529073, // csrw mtvec, t0
+            0x00300513, // li a0, 3
+            0x05d00893, // li a7, 93
+            0x00000073, // ecall
+        ]);
+        assert_eq!(cpu.run(None), StopReason::Exited(3));
+
+        let mut cpu = load_words(&[0x00000073]); // ecall
+        cpu.set_host_syscalls(false);
+        assert_eq!(cpu.step(), Err(EmuError::UnhandledEcall { pc: 0x80000000 }));
+    }
+
+    #[test]
+    fn test_initial_stack_only_with_host_syscalls() {
+        let name = format!("riscv_emu_stack_test_{}.bin", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, 0x00000013u32.to_le_bytes()).unwrap(); // nop
+        let path = path.to_str().unwrap();
+
+        // riscv-pk 风格：栈建立在 RAM 顶端，6 个字的 argc/argv/envp/auxv 之后是参数字符串
+        let mut cpu = Cpu::new(0x03000000);
+        cpu.load_program(path).unwrap();
+        let sp = cpu.registers.read(2);
+        assert_eq!(sp, (0x83000000 - 24 - (path.len() as u64 + 1)) & !0xf);
+        assert_eq!(cpu.debug_read(sp, 4).unwrap(), 1);
+
+        // 裸机程序的 sp 和栈区内存保持不变
+        let mut cpu = Cpu::new(0x03000000);
+        cpu.set_host_syscalls(false);
+        cpu.load_program(path).unwrap();
+        assert_eq!(cpu.registers.read(2), 0);
+        assert_eq!(
+            cpu.memory.read_bytes(0x82ffff00, 0x100).unwrap(),
+            [0; 0x100]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_run_stop_reasons() {
+        let mut cpu = load_words(&[
+            0x00300513, // li a0, 3
+            0x05d00893, // li a7, 93
+            0x00000073, // ecall
+        ]);
+        assert_eq!(cpu.run(None), StopReason::Exited(3));
+
+        let mut cpu = load_words(&[0x0000006f]); // j .
+        assert_eq!(cpu.run(Some(10)), StopReason::InstructionLimit);
+
+        let mut cpu = load_words(&[0x10500073]); // wfi
+        assert_eq!(cpu.run(Some(10)), StopReason::Halted);
+        assert_eq!(cpu.pc, 0x80000004);
+
+        // 定时器中断挂起时 WFI 恢复执行，mstatus.MIE 为 0 所以不进入陷阱
+        let mut cpu = load_words(&[
+            0x020002b7, // lui t0, 0x2000
+            0x20028293, // addi t0, t0, 0x200
+            0x00500313, // li t1, 5
+            0x0062a423, // sw t1, 8(t0)
+            0x00300313, // li t1, 3
+            0x0062a223, // sw t1, 4(t0)
+            0x08000313, // li t1, 0x80
+            0x30431073, // csrw mie, t1
+            0x10500073, // wfi
+            0x00000513, // li a0, 0
+            0x05d00893, // li a7, 93
+            0x00000073, // ecall
+        ]);
+        assert_eq!(cpu.run(Some(100)), StopReason::Exited(0));
+        assert_eq!(cpu.csrs.read(crate::csr::MCAUSE).unwrap(), 0);
+
+        // S 模式下 mstatus.TW 使 WFI 成为非法指令
+        let mut cpu = load_words(&[
+            0x002012b7, // lui t0, 0x201
+            0x80028293, // addi t0, t0, -2048 (MPP=S, TW=1)
+            0x30029073, // csrw mstatus, t0
+            0x00000317, // auipc t1, 0
+            0x01030313, // addi t1, t1, 16
+            0x34131073, // csrw mepc, t1
+            0x30200073, // mret
+            0x10500073, // wfi
+        ]);
+        assert_eq!(
+            cpu.run(None),
+            StopReason::Fault(EmuError::IllegalInstruction {
+                pc: 0x8000001c,
+                word: 0x10500073
+            })
+        );
+    }
+
+    #[test]
+    fn test_rv64_instructions() {
+        let mut cpu = load_words(&[
+            0x00100593, // li a1, 1
+            0x01f59593, // slli a1, a1, 31
+            0x10058593, // addi a1, a1, 0x100
+            0xfff00613, // li a2, -1
+            0x00c5b023, // sd a2, 0(a1)
+            0x0005a683, // lw a3, 0(a1)
+            0x0005e703, // lwu a4, 0(a1)
+            0x0005b783, // ld a5, 0(a1)
+            0x0017081b, // addiw a6, a4, 1
+            0x00100293, // li t0, 1
+            0x01f29293, // slli t0, t0, 31
+            0x0002833b, // addw t1, t0, zero
+            0x4042d39b, // sraiw t2, t0, 4
+            0x0042de1b, // srliw t3, t0, 4
+            0x00300e93, // li t4, 3
+            0x01d5bf2f, // amoadd.d t5, t4, (a1)
+            0x0005bf83, // ld t6, 0(a1)
+            0x03d288bb, // mulw a7, t0, t4
+        ]);
+        cpu.set_isa(Isa::parse("rv64imac").unwrap());
+        assert_eq!(cpu.csrs.read(crate::csr::MISA).unwrap() >> 62, 2);
+
+        for _ in 0..18 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.registers.read(11), 0x80000100);
+        assert_eq!(cpu.registers.read(13), u64::MAX);
+        assert_eq!(cpu.registers.read(14), 0xffffffff);
+        assert_eq!(cpu.registers.read(15), u64::MAX);
+        assert_eq!(cpu.registers.read(16), 0);
+        assert_eq!(cpu.registers.read(5), 0x80000000);
+        assert_eq!(cpu.registers.read(6), 0xffffffff_80000000);
+        assert_eq!(cpu.registers.read(7), 0xffffffff_f8000000);
+        assert_eq!(cpu.registers.read(28), 0x08000000);
+        assert_eq!(cpu.registers.read(30), u64::MAX);
+        assert_eq!(cpu.registers.read(31), 2);
+        assert_eq!(cpu.registers.read(17), 0xffffffff_80000000);
+
+        // RV32 下 RV64 专有指令非法
+        let mut cpu = load_words(&[0x0005b783]); // ld a5, 0(a1)
+        assert_eq!(
+            cpu.step(),
+            Err(EmuError::IllegalInstruction {
+                pc: 0x80000000,
+                word: 0x0005b783
+            })
+        );
+    }
+
+    #[test]
+    fn test_rv64_alu_operations() {
+        let alu = Cpu::execute_alu_op64;
+        assert_eq!(alu(RegOp::Addw, 0x7fffffff, 1), 0xffffffff_80000000);
+        assert_eq!(alu(RegOp::Sllw, 1, 31), 0xffffffff_80000000);
+        assert_eq!(
+            alu(RegOp::Divw, 0x80000000, 0xffffffff),
+            0xffffffff_80000000
+        );
+        assert_eq!(alu(RegOp::Remuw, 7, 0), 7);
+        assert_eq!(alu(RegOp::Sll, 1, 63), 1 << 63);
+        assert_eq!(alu(RegOp::Sra, 1 << 63, 63), u64::MAX);
+        assert_eq!(alu(RegOp::Mulhu, u64::MAX, u64::MAX), u64::MAX - 1);
+        assert_eq!(alu(RegOp::Div, 1 << 63, u64::MAX), 1 << 63);
+        assert_eq!(alu(RegOp::AddUw, u64::MAX, 1), 0x1_0000_0000);
+        assert_eq!(alu(RegOp::SlliUw, u64::MAX, 4), 0xf_ffff_fff0);
+        assert_eq!(alu(RegOp::Clz, 1, 0), 63);
+        assert_eq!(alu(RegOp::Clzw, 1, 0), 31);
+        assert_eq!(alu(RegOp::Rev8, 0x0102030405060708, 0), 0x0807060504030201);
+        assert_eq!(alu(RegOp::Clmulh, 1 << 63, 2), 1);
+        assert_eq!(alu(RegOp::Bset, 0, 40), 1 << 40);
     }
 }
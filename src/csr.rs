@@ -0,0 +1,743 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::error::EmuError;
+use crate::isa::{Isa, Xlen};
+use crate::pmp::{Pmp, PMP_ENTRIES};
+
+// 浮点 CSR 地址
+pub const FFLAGS: u16 = 0x001;
+pub const FRM: u16 = 0x002;
+pub const FCSR: u16 = 0x003;
+
+// Supervisor 模式 CSR 地址
+pub const SSTATUS: u16 = 0x100;
+pub const SIE: u16 = 0x104;
+pub const STVEC: u16 = 0x105;
+pub const SSCRATCH: u16 = 0x140;
+pub const SEPC: u16 = 0x141;
+pub const SCAUSE: u16 = 0x142;
+pub const STVAL: u16 = 0x143;
+pub const SIP: u16 = 0x144;
+pub const SATP: u16 = 0x180;
+
+// Machine 模式 CSR 地址
+pub const MSTATUS: u16 = 0x300;
+pub const MISA: u16 = 0x301;
+pub const MEDELEG: u16 = 0x302;
+pub const MIDELEG: u16 = 0x303;
+pub const MIE: u16 = 0x304;
+pub const MTVEC: u16 = 0x305;
+pub const MSCRATCH: u16 = 0x340;
+pub const MEPC: u16 = 0x341;
+pub const MCAUSE: u16 = 0x342;
+pub const MTVAL: u16 = 0x343;
+pub const MIP: u16 = 0x344;
+pub const PMPCFG0: u16 = 0x3A0;
+pub const PMPCFG3: u16 = 0x3A3;
+pub const PMPADDR0: u16 = 0x3B0;
+pub const PMPADDR15: u16 = 0x3BF;
+pub const MVENDORID: u16 = 0xF11;
+pub const MARCHID: u16 = 0xF12;
+pub const MIMPID: u16 = 0xF13;
+pub const MHARTID: u16 = 0xF14;
+
+// mstatus 位
+pub const MSTATUS_SIE: u64 = 1 << 1;
+pub const MSTATUS_MIE: u64 = 1 << 3;
+pub const MSTATUS_SPIE: u64 = 1 << 5;
+pub const MSTATUS_MPIE: u64 = 1 << 7;
+pub const MSTATUS_SPP: u64 = 1 << 8;
+pub const MSTATUS_MPP: u64 = 0b11 << 11;
+pub const MSTATUS_FS: u64 = 0b11 << 13;
+pub const MSTATUS_MPRV: u64 = 1 << 17;
+pub const MSTATUS_SUM: u64 = 1 << 18;
+pub const MSTATUS_MXR: u64 = 1 << 19;
+pub const MSTATUS_TVM: u64 = 1 << 20;
+pub const MSTATUS_TW: u64 = 1 << 21;
+pub const MSTATUS_TSR: u64 = 1 << 22;
+// RV64 的 UXL/SXL 只读，固定为 64 位
+const MSTATUS_UXL: u64 = 0b11 << 32;
+const MSTATUS_SXL: u64 = 0b11 << 34;
+const XL_64: u64 = (2 << 32) | (2 << 34);
+
+// mstatus.FS 状态：Off / Initial / Clean / Dirty
+const FS_INITIAL: u64 = 0b01 << 13;
+const FS_DIRTY: u64 = 0b11 << 13;
+
+// mie/mip 位
+pub const MIP_SSIP: u64 = 1 << 1;
+pub const MIP_MSIP: u64 = 1 << 3;
+pub const MIP_STIP: u64 = 1 << 5;
+pub const MIP_MTIP: u64 = 1 << 7;
+pub const MIP_SEIP: u64 = 1 << 9;
+pub const MIP_MEIP: u64 = 1 << 11;
+
+// misa 复位值: MXL=1 (32 位)，扩展 I、M、A、F、D、C 以及 S/U 模式
+const MISA_VALUE: u64 = (1 << 30)
+    | misa_ext(b'I')
+    | misa_ext(b'M')
+    | misa_ext(b'A')
+    | misa_ext(b'F')
+    | misa_ext(b'D')
+    | misa_ext(b'C')
+    | MISA_MODES;
+
+// 总是支持 Supervisor 和 User 模式
+const MISA_MODES: u64 = misa_ext(b'S') | misa_ext(b'U');
+
+const fn misa_ext(ext: u8) -> u64 {
+    1 << (ext - b'A')
+}
+
+// 可写位掩码（WARL）
+const MSTATUS_WRITE_MASK: u64 = MSTATUS_SIE
+    | MSTATUS_MIE
+    | MSTATUS_SPIE
+    | MSTATUS_MPIE
+    | MSTATUS_SPP
+    | MSTATUS_MPP
+    | MSTATUS_FS
+    | MSTATUS_MPRV
+    | MSTATUS_SUM
+    | MSTATUS_MXR
+    | MSTATUS_TVM
+    | MSTATUS_TW
+    | MSTATUS_TSR;
+// sstatus 是 mstatus 的受限视图
+const SSTATUS_WRITE_MASK: u64 =
+    MSTATUS_SIE | MSTATUS_SPIE | MSTATUS_SPP | MSTATUS_FS | MSTATUS_SUM | MSTATUS_MXR;
+const SSTATUS_READ_MASK: u64 = SSTATUS_WRITE_MASK | MSTATUS_UXL;
+const S_INTERRUPTS: u64 = MIP_SSIP | MIP_STIP | MIP_SEIP;
+const MIE_WRITE_MASK: u64 = MIP_MSIP | MIP_MTIP | MIP_MEIP | S_INTERRUPTS;
+// 环境调用 (M 模式) 不能委托
+const MEDELEG_WRITE_MASK: u64 = 0xb3ff;
+
+// 特权级，数值与 mstatus.MPP 的编码一致
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Privilege {
+    User = 0,
+    Supervisor = 1,
+    Machine = 3,
+}
+
+impl Privilege {
+    // 保留编码 0b10 不会出现，MPP 写入时已被过滤
+    pub fn from_bits(bits: u64) -> Self {
+        match bits & 0x3 {
+            0 => Privilege::User,
+            1 => Privilege::Supervisor,
+            _ => Privilege::Machine,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Privilege::User => "U",
+            Privilege::Supervisor => "S",
+            Privilege::Machine => "M",
+        }
+    }
+}
+
+const PMPCFG_NAMES: [&str; 4] = ["pmpcfg0", "pmpcfg1", "pmpcfg2", "pmpcfg3"];
+const PMPADDR_NAMES: [&str; PMP_ENTRIES] = [
+    "pmpaddr0",
+    "pmpaddr1",
+    "pmpaddr2",
+    "pmpaddr3",
+    "pmpaddr4",
+    "pmpaddr5",
+    "pmpaddr6",
+    "pmpaddr7",
+    "pmpaddr8",
+    "pmpaddr9",
+    "pmpaddr10",
+    "pmpaddr11",
+    "pmpaddr12",
+    "pmpaddr13",
+    "pmpaddr14",
+    "pmpaddr15",
+];
+
+pub fn csr_name(addr: u16) -> Option<&'static str> {
+    let name = match addr {
+        FFLAGS => "fflags",
+        FRM => "frm",
+        FCSR => "fcsr",
+        SSTATUS => "sstatus",
+        SIE => "sie",
+        STVEC => "stvec",
+        SSCRATCH => "sscratch",
+        SEPC => "sepc",
+        SCAUSE => "scause",
+        STVAL => "stval",
+        SIP => "sip",
+        SATP => "satp",
+        MSTATUS => "mstatus",
+        MISA => "misa",
+        MEDELEG => "medeleg",
+        MIDELEG => "mideleg",
+        MIE => "mie",
+        MTVEC => "mtvec",
+        MSCRATCH => "mscratch",
+        MEPC => "mepc",
+        MCAUSE => "mcause",
+        MTVAL => "mtval",
+        MIP => "mip",
+        PMPCFG0..=PMPCFG3 => PMPCFG_NAMES[(addr - PMPCFG0) as usize],
+        PMPADDR0..=PMPADDR15 => PMPADDR_NAMES[(addr - PMPADDR0) as usize],
+        MVENDORID => "mvendorid",
+        MARCHID => "marchid",
+        MIMPID => "mimpid",
+        MHARTID => "mhartid",
+        _ => return None,
+    };
+    Some(name)
+}
+
+// CSR 按 64 位存储，RV32 下只使用低 32 位
+pub struct CsrFile {
+    xlen: Xlen,
+    privilege: Privilege,
+    misa: u64,
+    mstatus: u64,
+    medeleg: u64,
+    mideleg: u64,
+    mie: u64,
+    mip: u64,
+    mtvec: u64,
+    mscratch: u64,
+    mepc: u64,
+    mcause: u64,
+    mtval: u64,
+    stvec: u64,
+    sscratch: u64,
+    sepc: u64,
+    scause: u64,
+    stval: u64,
+    satp: u64,
+    fcsr: u32, // frm[7:5] | fflags[4:0]
+    pmp: Pmp,
+}
+
+impl Default for CsrFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsrFile {
+    pub fn new() -> Self {
+        Self {
+            xlen: Xlen::Rv32,
+            privilege: Privilege::Machine,
+            misa: MISA_VALUE,
+            // 复位时 MPP 为 M 模式，未设置 MPP 的 MRET 仍返回 M 模式
+            // 复位时 FS 为 Initial，裸机程序无需先打开浮点单元
+            mstatus: MSTATUS_MPP | FS_INITIAL,
+            medeleg: 0,
+            mideleg: 0,
+            mie: 0,
+            mip: 0,
+            mtvec: 0,
+            mscratch: 0,
+            mepc: 0,
+            mcause: 0,
+            mtval: 0,
+            stvec: 0,
+            sscratch: 0,
+            sepc: 0,
+            scause: 0,
+            stval: 0,
+            satp: 0,
+            fcsr: 0,
+            pmp: Pmp::new(),
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> Result<u64, EmuError> {
+        match addr {
+            FFLAGS | FRM | FCSR if !self.fpu_enabled() => Err(EmuError::IllegalCsr { csr: addr }),
+            FFLAGS => Ok((self.fcsr & 0x1f) as u64),
+            FRM => Ok((self.fcsr >> 5) as u64),
+            FCSR => Ok(self.fcsr as u64),
+            SSTATUS => Ok(self.mstatus & (SSTATUS_READ_MASK | self.mstatus_sd())),
+            SIE => Ok(self.mie & self.mideleg),
+            STVEC => Ok(self.stvec),
+            SSCRATCH => Ok(self.sscratch),
+            SEPC => Ok(self.sepc),
+            SCAUSE => Ok(self.scause),
+            STVAL => Ok(self.stval),
+            SIP => Ok(self.mip & self.mideleg),
+            SATP => Ok(self.satp),
+            MSTATUS => Ok(self.mstatus),
+            MISA => Ok(self.misa),
+            MEDELEG => Ok(self.medeleg),
+            MIDELEG => Ok(self.mideleg),
+            MIE => Ok(self.mie),
+            MTVEC => Ok(self.mtvec),
+            MSCRATCH => Ok(self.mscratch),
+            MEPC => Ok(self.mepc),
+            MCAUSE => Ok(self.mcause),
+            MTVAL => Ok(self.mtval),
+            MIP => Ok(self.mip),
+            PMPCFG0..=PMPCFG3 => {
+                let (first, count) = self.pmpcfg_entries(addr)?;
+                Ok(self.pmp.read_cfg(first, count))
+            }
+            PMPADDR0..=PMPADDR15 => Ok(self.pmp.read_addr((addr - PMPADDR0) as usize)),
+            MVENDORID | MARCHID | MIMPID | MHARTID => Ok(0),
+            _ => Err(EmuError::IllegalCsr { csr: addr }),
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u64) -> Result<(), EmuError> {
+        // csr[11:10] == 0b11 表示只读 CSR
+        if (addr >> 10) & 0x3 == 0x3 {
+            self.read(addr)?;
+            return Err(EmuError::IllegalCsr { csr: addr });
+        }
+
+        let mask = self.xlen.mask();
+        match addr {
+            FFLAGS | FRM | FCSR if !self.fpu_enabled() => {
+                return Err(EmuError::IllegalCsr { csr: addr })
+            }
+            FFLAGS => {
+                self.fcsr = (self.fcsr & !0x1f) | (value as u32 & 0x1f);
+                self.mark_fp_dirty();
+            }
+            FRM => {
+                self.fcsr = (self.fcsr & 0x1f) | ((value as u32 & 0x7) << 5);
+                self.mark_fp_dirty();
+            }
+            FCSR => {
+                self.fcsr = value as u32 & 0xff;
+                self.mark_fp_dirty();
+            }
+            SSTATUS => self
+                .write_mstatus((self.mstatus & !SSTATUS_WRITE_MASK) | (value & SSTATUS_WRITE_MASK)),
+            // sie/sip 只能访问已委托给 S 模式的中断，sip 中只有 SSIP 可写
+            SIE => self.mie = (self.mie & !self.mideleg) | (value & self.mideleg),
+            SIP => {
+                let writable = MIP_SSIP & self.mideleg;
+                self.mip = (self.mip & !writable) | (value & writable);
+            }
+            STVEC => self.stvec = Self::write_tvec(self.stvec, value & mask),
+            SSCRATCH => self.sscratch = value & mask,
+            SEPC => self.sepc = value & mask & !0x1,
+            SCAUSE => self.scause = value & mask,
+            STVAL => self.stval = value & mask,
+            SATP => {
+                // RV32 支持 Bare 和 Sv32；RV64 只支持 Bare，写入其他模式时整个写操作无效
+                if self.xlen == Xlen::Rv32 || self.satp_mode(value) == 0 {
+                    self.satp = value & mask;
+                }
+            }
+            MSTATUS => self.write_mstatus(value),
+            MISA => (), // 不支持修改扩展，写入被忽略
+            MEDELEG => self.medeleg = value & MEDELEG_WRITE_MASK,
+            MIDELEG => self.mideleg = value & S_INTERRUPTS,
+            MIE => self.mie = value & MIE_WRITE_MASK,
+            MTVEC => self.mtvec = Self::write_tvec(self.mtvec, value & mask),
+            MSCRATCH => self.mscratch = value & mask,
+            MEPC => self.mepc = value & mask & !0x1, // IALIGN=16
+            MCAUSE => self.mcause = value & mask,
+            MTVAL => self.mtval = value & mask,
+            // M 模式软件可以挂起 S 模式中断，M 模式中断位由硬件维护
+            MIP => self.mip = (self.mip & !S_INTERRUPTS) | (value & S_INTERRUPTS),
+            PMPCFG0..=PMPCFG3 => {
+                let (first, count) = self.pmpcfg_entries(addr)?;
+                self.pmp.write_cfg(first, count, value);
+            }
+            PMPADDR0..=PMPADDR15 => {
+                self.pmp
+                    .write_addr((addr - PMPADDR0) as usize, value, self.xlen)
+            }
+            _ => return Err(EmuError::IllegalCsr { csr: addr }),
+        }
+        Ok(())
+    }
+
+    // pmpcfgN 对应的第一个 PMP 表项和表项数：RV32 每个 4 项，RV64 只有偶数编号的 pmpcfg，每个 8 项
+    fn pmpcfg_entries(&self, addr: u16) -> Result<(usize, usize), EmuError> {
+        let index = (addr - PMPCFG0) as usize;
+        match self.xlen {
+            Xlen::Rv32 => Ok((index * 4, 4)),
+            Xlen::Rv64 if index.is_multiple_of(2) => Ok((index * 4, 8)),
+            Xlen::Rv64 => Err(EmuError::IllegalCsr { csr: addr }),
+        }
+    }
+
+    pub fn pmp(&self) -> &Pmp {
+        &self.pmp
+    }
+
+    pub fn pmp_mut(&mut self) -> &mut Pmp {
+        &mut self.pmp
+    }
+
+    // csr[9:8] 是访问所需的最低特权级；mstatus.TVM=1 时 S 模式不能访问 satp
+    pub fn check_access(&self, addr: u16) -> Result<(), EmuError> {
+        if (addr >> 8) & 0x3 > self.privilege as u16 {
+            return Err(EmuError::IllegalCsr { csr: addr });
+        }
+        if addr == SATP
+            && self.privilege == Privilege::Supervisor
+            && self.mstatus & MSTATUS_TVM != 0
+        {
+            return Err(EmuError::IllegalCsr { csr: addr });
+        }
+        Ok(())
+    }
+
+    // MPP 不接受保留编码 0b10，SD 汇总 FS 是否为 Dirty
+    fn write_mstatus(&mut self, value: u64) {
+        let mut mstatus = value & MSTATUS_WRITE_MASK;
+        if mstatus & MSTATUS_MPP == 0b10 << 11 {
+            mstatus = (mstatus & !MSTATUS_MPP) | (self.mstatus & MSTATUS_MPP);
+        }
+        if !self.has_fpu() {
+            mstatus &= !MSTATUS_FS;
+        }
+        mstatus |= self.mstatus & (MSTATUS_UXL | MSTATUS_SXL);
+        if mstatus & MSTATUS_FS == FS_DIRTY {
+            mstatus |= self.mstatus_sd();
+        }
+        self.mstatus = mstatus;
+    }
+
+    // MODE 只支持 Direct(0) 和 Vectored(1)，非法值保留原模式
+    fn write_tvec(old: u64, value: u64) -> u64 {
+        let mode = if value & 0x3 <= 1 {
+            value & 0x3
+        } else {
+            old & 0x3
+        };
+        (value & !0x3) | mode
+    }
+
+    // satp.MODE：RV32 为第 31 位，RV64 为第 63..60 位
+    fn satp_mode(&self, satp: u64) -> u64 {
+        match self.xlen {
+            Xlen::Rv32 => (satp >> 31) & 0x1,
+            Xlen::Rv64 => satp >> 60,
+        }
+    }
+
+    // 由 ISA 配置设置 misa 的 MXL 和扩展位；没有 F 扩展时 FS 恒为 Off
+    pub fn set_isa(&mut self, isa: &Isa) {
+        self.xlen = isa.xlen();
+        let mxl: u64 = match self.xlen {
+            Xlen::Rv32 => 1,
+            Xlen::Rv64 => 2,
+        };
+        self.misa = (mxl << (self.xlen.bits() - 2)) | isa.misa_extensions() as u64 | MISA_MODES;
+        // SD 位于 mstatus 最高位，XLEN 改变时重新计算
+        self.mstatus &= !((1 << 31) | (1 << 63) | MSTATUS_UXL | MSTATUS_SXL);
+        if self.xlen == Xlen::Rv64 {
+            self.mstatus |= XL_64;
+        }
+        if !self.has_fpu() {
+            self.mstatus &= !MSTATUS_FS;
+        } else if self.mstatus & MSTATUS_FS == FS_DIRTY {
+            self.mstatus |= self.mstatus_sd();
+        }
+    }
+
+    // mstatus.SD 汇总扩展状态是否为 Dirty
+    fn mstatus_sd(&self) -> u64 {
+        1 << (self.xlen.bits() - 1)
+    }
+
+    fn has_fpu(&self) -> bool {
+        self.misa & misa_ext(b'F') != 0
+    }
+
+    pub fn privilege(&self) -> Privilege {
+        self.privilege
+    }
+
+    pub fn mstatus(&self) -> u64 {
+        self.mstatus
+    }
+
+    // mstatus.FS 为 Off 时浮点指令和浮点 CSR 都是非法的
+    pub fn fpu_enabled(&self) -> bool {
+        self.mstatus & MSTATUS_FS != 0
+    }
+
+    // 浮点状态被修改
+    pub fn mark_fp_dirty(&mut self) {
+        self.mstatus |= FS_DIRTY | self.mstatus_sd();
+    }
+
+    // 动态舍入模式
+    pub fn frm(&self) -> u32 {
+        self.fcsr >> 5
+    }
+
+    // 累积浮点异常标志
+    pub fn accrue_fflags(&mut self, flags: u32) {
+        self.fcsr |= flags & 0x1f;
+    }
+
+    // 陷阱是否委托给 S 模式：只有 U/S 模式下发生且在 medeleg/mideleg 中置位的陷阱
+    fn trap_delegated(&self, interrupt: bool, code: u64) -> bool {
+        let deleg = if interrupt {
+            self.mideleg
+        } else {
+            self.medeleg
+        };
+        self.privilege <= Privilege::Supervisor && (deleg >> code) & 1 != 0
+    }
+
+    // 陷阱将进入的模式（考虑委托之后）是否安装了处理程序
+    pub fn trap_handler_installed(&self, interrupt: bool, code: u64) -> bool {
+        let tvec = if self.trap_delegated(interrupt, code) {
+            self.stvec
+        } else {
+            self.mtvec
+        };
+        tvec & !0x3 != 0
+    }
+
+    // 进入陷阱：保存现场并返回处理程序地址，cause 最高位表示中断
+    // U/S 模式下发生且已在 medeleg/mideleg 中委托的陷阱交给 S 模式处理
+    pub fn trap_enter(&mut self, interrupt: bool, code: u64, epc: u64, tval: u64) -> u64 {
+        let cause = code | ((interrupt as u64) << (self.xlen.bits() - 1));
+        let tvec = if self.trap_delegated(interrupt, code) {
+            self.sepc = epc;
+            self.scause = cause;
+            self.stval = tval;
+
+            // SPIE <- SIE, SIE <- 0, SPP <- 陷阱前的特权级
+            let sie = self.mstatus & MSTATUS_SIE != 0;
+            self.mstatus &= !(MSTATUS_SIE | MSTATUS_SPIE | MSTATUS_SPP);
+            if sie {
+                self.mstatus |= MSTATUS_SPIE;
+            }
+            if self.privilege == Privilege::Supervisor {
+                self.mstatus |= MSTATUS_SPP;
+            }
+            self.privilege = Privilege::Supervisor;
+            self.stvec
+        } else {
+            self.mepc = epc;
+            self.mcause = cause;
+            self.mtval = tval;
+
+            // MPIE <- MIE, MIE <- 0, MPP <- 陷阱前的特权级
+            let mie = self.mstatus & MSTATUS_MIE != 0;
+            self.mstatus &= !(MSTATUS_MIE | MSTATUS_MPIE | MSTATUS_MPP);
+            if mie {
+                self.mstatus |= MSTATUS_MPIE;
+            }
+            self.mstatus |= (self.privilege as u64) << 11;
+            self.privilege = Privilege::Machine;
+            self.mtvec
+        };
+
+        let base = tvec & !0x3;
+        let vectored = tvec & 0x3 == 1;
+        if vectored && interrupt {
+            base.wrapping_add(4 * code) & self.xlen.mask()
+        } else {
+            base
+        }
+    }
+
+    pub fn satp(&self) -> u64 {
+        self.satp
+    }
+
+    // satp.MODE 不为 Bare 时启用分页
+    pub fn paging_enabled(&self) -> bool {
+        self.satp_mode(self.satp) != 0
+    }
+
+    // 由设备中断线更新 mip 中的挂起位
+    pub fn set_pending(&mut self, mask: u64, pending: bool) {
+        if pending {
+            self.mip |= mask;
+        } else {
+            self.mip &= !mask;
+        }
+    }
+
+    // 返回应当响应的最高优先级中断编号（MEI > MSI > MTI > SEI > SSI > STI）
+    // 低于当前特权级的中断被屏蔽，高于当前特权级的中断总是使能
+    pub fn pending_interrupt(&self) -> Option<u64> {
+        let pending = self.mip & self.mie;
+        let m_enabled = self.privilege < Privilege::Machine || self.mstatus & MSTATUS_MIE != 0;
+        let s_enabled = self.privilege < Privilege::Supervisor
+            || (self.privilege == Privilege::Supervisor && self.mstatus & MSTATUS_SIE != 0);
+
+        let mut enabled = 0;
+        if m_enabled {
+            enabled |= pending & !self.mideleg;
+        }
+        if s_enabled {
+            enabled |= pending & self.mideleg;
+        }
+        [
+            (MIP_MEIP, 11),
+            (MIP_MSIP, 3),
+            (MIP_MTIP, 7),
+            (MIP_SEIP, 9),
+            (MIP_SSIP, 1),
+            (MIP_STIP, 5),
+        ]
+        .iter()
+        .find(|(bit, _)| enabled & bit != 0)
+        .map(|&(_, code)| code)
+    }
+
+    // WFI 在任一 mie 使能的中断挂起时恢复，不受 mstatus.MIE/SIE 影响
+    pub fn wfi_wakeup(&self) -> bool {
+        self.mip & self.mie != 0
+    }
+
+    // mie 全为 0 时没有中断能唤醒 WFI
+    pub fn interrupts_enabled(&self) -> bool {
+        self.mie != 0
+    }
+
+    // MRET/SRET：恢复中断使能和特权级，返回 mepc/sepc
+    pub fn trap_return(&mut self, level: Privilege) -> u64 {
+        let (target, epc) = if level == Privilege::Machine {
+            // MIE <- MPIE, MPIE <- 1, MPP <- U
+            let mpp = Privilege::from_bits(self.mstatus >> 11);
+            let mpie = self.mstatus & MSTATUS_MPIE != 0;
+            self.mstatus &= !(MSTATUS_MIE | MSTATUS_MPP);
+            if mpie {
+                self.mstatus |= MSTATUS_MIE;
+            }
+            self.mstatus |= MSTATUS_MPIE;
+            (mpp, self.mepc)
+        } else {
+            // SIE <- SPIE, SPIE <- 1, SPP <- U
+            let spp = if self.mstatus & MSTATUS_SPP != 0 {
+                Privilege::Supervisor
+            } else {
+                Privilege::User
+            };
+            let spie = self.mstatus & MSTATUS_SPIE != 0;
+            self.mstatus &= !(MSTATUS_SIE | MSTATUS_SPP);
+            if spie {
+                self.mstatus |= MSTATUS_SIE;
+            }
+            self.mstatus |= MSTATUS_SPIE;
+            (spp, self.sepc)
+        };
+
+        // 返回到低于 M 的模式时清除 MPRV
+        if target != Privilege::Machine {
+            self.mstatus &= !MSTATUS_MPRV;
+        }
+        self.privilege = target;
+        epc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csr_warl_and_read_only() {
+        let mut csrs = CsrFile::new();
+
+        assert_eq!(
+            csrs.write(MHARTID, 1),
+            Err(EmuError::IllegalCsr { csr: MHARTID })
+        );
+        assert!(csrs.write(0x7C0, 1).is_err());
+        assert!(csrs.read(0x7C0).is_err());
+
+        csrs.write(MISA, 0).unwrap();
+        assert_eq!(csrs.read(MISA).unwrap(), MISA_VALUE);
+
+        csrs.write(MTVEC, 0x80001003).unwrap();
+        assert_eq!(csrs.read(MTVEC).unwrap(), 0x80001000);
+        csrs.write(MTVEC, 0x80002001).unwrap();
+        assert_eq!(csrs.read(MTVEC).unwrap(), 0x80002001);
+
+        csrs.write(MSTATUS, 0xffffffff).unwrap();
+        assert_eq!(csrs.read(MSTATUS).unwrap(), MSTATUS_WRITE_MASK | 1 << 31);
+
+        // fcsr 与 fflags/frm 是同一寄存器的不同视图
+        csrs.write(FCSR, 0xfff).unwrap();
+        assert_eq!(csrs.read(FCSR).unwrap(), 0xff);
+        csrs.write(FRM, 0x2).unwrap();
+        assert_eq!(csrs.read(FFLAGS).unwrap(), 0x1f);
+        assert_eq!(csrs.read(FCSR).unwrap(), 0x5f);
+
+        csrs.write(MSTATUS, 0).unwrap();
+        assert_eq!(csrs.read(MSTATUS).unwrap(), 0);
+        assert!(csrs.read(FCSR).is_err());
+
+        csrs.write(MEPC, 0x80000007).unwrap();
+        assert_eq!(csrs.read(MEPC).unwrap(), 0x80000006);
+    }
+
+    #[test]
+    fn test_supervisor_view_and_delegation() {
+        let mut csrs = CsrFile::new();
+
+        // sstatus/sie/sip 是 mstatus/mie/mip 的受限视图
+        csrs.write(SSTATUS, u64::MAX).unwrap();
+        assert_eq!(csrs.read(MSTATUS).unwrap() & MSTATUS_MIE, 0);
+        assert_ne!(csrs.read(MSTATUS).unwrap() & MSTATUS_SIE, 0);
+        csrs.write(MIDELEG, u64::MAX).unwrap();
+        assert_eq!(csrs.read(MIDELEG).unwrap(), MIP_SSIP | MIP_STIP | MIP_SEIP);
+        csrs.write(MIE, MIP_MTIP | MIP_STIP).unwrap();
+        assert_eq!(csrs.read(SIE).unwrap(), MIP_STIP);
+        csrs.write(MEDELEG, u64::MAX).unwrap();
+        assert_eq!(csrs.read(MEDELEG).unwrap() & (1 << 11), 0);
+
+        // MPP 的保留编码被忽略；satp 在 RV32 下支持 Sv32
+        csrs.write(MSTATUS, 0b10 << 11).unwrap();
+        assert_eq!(csrs.read(MSTATUS).unwrap() & MSTATUS_MPP, MSTATUS_MPP);
+        csrs.write(SATP, 0x80000001).unwrap();
+        assert!(csrs.paging_enabled());
+        csrs.write(SATP, 0).unwrap();
+
+        // M 模式下的中断不委托；S 模式下委托的 STI 进入 stvec
+        csrs.write(STVEC, 0x80001000).unwrap();
+        csrs.write(MTVEC, 0x80002000).unwrap();
+        csrs.set_pending(MIP_STIP, true);
+        assert_eq!(csrs.pending_interrupt(), None);
+        csrs.write(SSTATUS, MSTATUS_SIE).unwrap();
+        csrs.privilege = Privilege::Supervisor;
+        assert!(csrs.check_access(MSTATUS).is_err());
+        assert!(csrs.check_access(SSTATUS).is_ok());
+        assert_eq!(csrs.pending_interrupt(), Some(5));
+        assert_eq!(csrs.trap_enter(true, 5, 0x80000100, 0), 0x80001000);
+        assert_eq!(csrs.read(SCAUSE).unwrap(), 0x80000005);
+        assert_eq!(csrs.read(SSTATUS).unwrap() & MSTATUS_SPP, MSTATUS_SPP);
+
+        // S 模式下未委托的异常进入 M 模式，MPP 记录 S
+        assert_eq!(csrs.trap_enter(false, 11, 0x80000104, 0), 0x80002000);
+        assert_eq!(csrs.privilege(), Privilege::Machine);
+        assert_eq!(csrs.trap_return(Privilege::Machine), 0x80000104);
+        assert_eq!(csrs.privilege(), Privilege::Supervisor);
+        assert_eq!(csrs.trap_return(Privilege::Supervisor), 0x80000100);
+        assert_eq!(csrs.privilege(), Privilege::Supervisor);
+    }
+}
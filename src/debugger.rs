@@ -15,8 +15,23 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::rvc;
+use crate::symbols::SymbolTable;
 use std::collections::VecDeque;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+pub struct Watchpoint {
+    pub addr: u64,
+    pub len: u64,
+    pub kind: WatchKind,
+}
+
 pub struct Debugger {
     pub itrace_enabled: bool,
     pub mtrace_enabled: bool,
@@ -25,6 +40,18 @@ pub struct Debugger {
     instruction_trace: VecDeque<String>,
     memory_trace: VecDeque<String>,
     trace_limit: usize,
+    pub symbols: SymbolTable,
+    breakpoints: Vec<u64>,
+    watchpoints: Vec<Watchpoint>,
+    pub watch_hit: Option<(WatchKind, u64)>, // 最近一次触发的观察点
+    steps_remaining: u32,
+    continuing: bool,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Debugger {
@@ -37,12 +64,41 @@ impl Debugger {
             instruction_trace: VecDeque::with_capacity(16),
             memory_trace: VecDeque::with_capacity(16),
             trace_limit: 16,
+            symbols: SymbolTable::default(),
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            steps_remaining: 0,
+            continuing: false,
         }
     }
 
-    pub fn trace_instruction(&mut self, pc: u32, instruction: u32, disasm: &str) {
-        let trace = format!("0x{:08x}: 0x{:08x} {}", pc, instruction, disasm);
-        println!("[ITRACE] {}", trace);
+    // 单步模式下即使关闭跟踪输出也记录历史，供 history 命令使用
+    pub fn itrace_active(&self) -> bool {
+        self.itrace_enabled || self.single_step
+    }
+
+    pub fn mtrace_active(&self) -> bool {
+        self.mtrace_enabled || self.single_step
+    }
+
+    pub fn trace_instruction(&mut self, pc: u64, instruction: u32, disasm: &str) {
+        // 压缩指令只显示 16 位
+        let raw = if rvc::is_compressed(instruction) {
+            format!("0x{:04x}    ", instruction)
+        } else {
+            format!("0x{:08x}", instruction)
+        };
+        let trace = format!(
+            "0x{:08x}{}: {} {}",
+            pc,
+            self.symbols.describe(pc),
+            raw,
+            disasm
+        );
+        if self.itrace_enabled {
+            println!("[ITRACE] {}", trace);
+        }
         if self.instruction_trace.len() >= self.trace_limit {
             self.instruction_trace.pop_front();
         }
@@ -51,7 +107,9 @@ impl Debugger {
 
     pub fn trace_memory_read(&mut self, addr: usize, size: usize, value: u32) {
         let trace = format!("read  0x{:08x}: {} bytes = 0x{:x}", addr, size, value);
-        println!("[MTRACE] {}", trace);
+        if self.mtrace_enabled {
+            println!("[MTRACE] {}", trace);
+        }
         if self.memory_trace.len() >= self.trace_limit {
             self.memory_trace.pop_front();
         }
@@ -60,7 +118,9 @@ impl Debugger {
 
     pub fn trace_memory_write(&mut self, addr: usize, size: usize, value: u32) {
         let trace = format!("write 0x{:08x}: {} bytes = 0x{:x}", addr, size, value);
-        println!("[MTRACE] {}", trace);
+        if self.mtrace_enabled {
+            println!("[MTRACE] {}", trace);
+        }
         if self.memory_trace.len() >= self.trace_limit {
             self.memory_trace.pop_front();
         }
@@ -81,9 +141,189 @@ impl Debugger {
         }
     }
 
-    pub fn wait_for_next(&self) {
-        println!("Press Enter to continue...");
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
+    pub fn breakpoints(&self) -> &[u64] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u64) {
+        if !self.breakpoints.contains(&addr) {
+            self.breakpoints.push(addr);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u64) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|&bp| bp != addr);
+        self.breakpoints.len() != len
+    }
+
+    #[inline]
+    pub fn is_breakpoint(&self, addr: u64) -> bool {
+        !self.breakpoints.is_empty() && self.breakpoints.contains(&addr)
+    }
+
+    pub fn add_watchpoint(&mut self, addr: u64, len: u64, kind: WatchKind) {
+        self.watchpoints.push(Watchpoint { addr, len, kind });
+    }
+
+    pub fn remove_watchpoint(&mut self, addr: u64, len: u64, kind: WatchKind) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints
+            .retain(|w| !(w.addr == addr && w.len == len && w.kind == kind));
+        self.watchpoints.len() != count
+    }
+
+    // 检查一次访存是否命中观察点，命中时记录到 watch_hit
+    #[inline]
+    pub fn check_watchpoints(&mut self, addr: u64, len: u64, is_write: bool) {
+        if self.watchpoints.is_empty() {
+            return;
+        }
+        let end = addr.wrapping_add(len);
+        for w in &self.watchpoints {
+            let kind_matches = match w.kind {
+                WatchKind::Write => is_write,
+                WatchKind::Read => !is_write,
+                WatchKind::Access => true,
+            };
+            if kind_matches && addr < w.addr.wrapping_add(w.len) && w.addr < end {
+                self.watch_hit = Some((w.kind, w.addr));
+                return;
+            }
+        }
+    }
+
+    // 按编号删除断点或观察点（断点在前，观察点在后）
+    pub fn delete(&mut self, index: usize) -> bool {
+        if index < self.breakpoints.len() {
+            self.breakpoints.remove(index);
+            true
+        } else if index - self.breakpoints.len() < self.watchpoints.len() {
+            self.watchpoints.remove(index - self.breakpoints.len());
+            true
+        } else {
+            false
+        }
+    }
+
+    // 单步执行 n 条指令后停下
+    pub fn step_n(&mut self, n: u32) {
+        self.steps_remaining = n.max(1);
+        self.continuing = false;
+    }
+
+    // 继续执行直到断点或观察点
+    pub fn resume(&mut self) {
+        self.continuing = true;
+    }
+
+    // 执行完一条指令后判断是否需要停下
+    pub fn should_stop(&mut self, pc: u64) -> bool {
+        if let Some((kind, addr)) = self.watch_hit.take() {
+            println!("[DEBUG] Watchpoint ({:?}) hit at 0x{:08x}", kind, addr);
+        } else if self.is_breakpoint(pc) {
+            println!(
+                "[DEBUG] Breakpoint hit at 0x{:08x}{}",
+                pc,
+                self.symbols.describe(pc)
+            );
+        } else if self.continuing {
+            return false;
+        } else if self.steps_remaining > 1 {
+            self.steps_remaining -= 1;
+            return false;
+        }
+
+        self.continuing = false;
+        self.steps_remaining = 0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_count_and_breakpoints() {
+        let mut debugger = Debugger::new();
+        // s 3：前两条指令之后继续，第三条之后停下
+        debugger.step_n(3);
+        assert!(!debugger.should_stop(0x80000004));
+        assert!(!debugger.should_stop(0x80000008));
+        assert!(debugger.should_stop(0x8000000c));
+        // s 0 按 s 1 处理
+        debugger.step_n(0);
+        assert!(debugger.should_stop(0x80000010));
+
+        debugger.add_breakpoint(0x80000020);
+        debugger.add_breakpoint(0x80000020);
+        assert_eq!(debugger.breakpoints(), [0x80000020]);
+        debugger.resume();
+        assert!(!debugger.should_stop(0x80000014));
+        assert!(debugger.should_stop(0x80000020));
+        // 停下后恢复单步
+        assert!(debugger.should_stop(0x80000024));
+
+        // 单步计数未用完时遇到断点也停下
+        debugger.step_n(10);
+        assert!(debugger.should_stop(0x80000020));
+        assert!(debugger.remove_breakpoint(0x80000020));
+        assert!(!debugger.remove_breakpoint(0x80000020));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(0x80000100, 4, WatchKind::Write);
+        debugger.add_watchpoint(0x1_0000_0000, 8, WatchKind::Read);
+        debugger.resume();
+
+        // 类型不符或范围不重叠的访问不触发
+        debugger.check_watchpoints(0x80000100, 4, false);
+        debugger.check_watchpoints(0x800000fc, 4, true);
+        debugger.check_watchpoints(0x80000104, 4, true);
+        assert!(debugger.watch_hit.is_none());
+        assert!(!debugger.should_stop(0x80000000));
+
+        debugger.check_watchpoints(0x80000103, 1, true);
+        assert_eq!(debugger.watch_hit, Some((WatchKind::Write, 0x80000100)));
+        assert!(debugger.should_stop(0x80000004));
+        assert!(debugger.watch_hit.is_none());
+
+        // 4 GiB 以上的地址不回绕到低地址
+        debugger.check_watchpoints(0x0, 8, false);
+        assert!(debugger.watch_hit.is_none());
+        debugger.check_watchpoints(0x1_0000_0004, 4, false);
+        assert_eq!(debugger.watch_hit, Some((WatchKind::Read, 0x1_0000_0000)));
+
+        assert!(debugger.remove_watchpoint(0x80000100, 4, WatchKind::Write));
+        assert!(!debugger.remove_watchpoint(0x1_0000_0000, 8, WatchKind::Write));
+        assert_eq!(debugger.watchpoints().len(), 1);
+    }
+
+    #[test]
+    fn test_delete_numbering() {
+        // 编号：断点 #0 #1，观察点 #2 #3
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x80000000);
+        debugger.add_watchpoint(0x80000100, 4, WatchKind::Write);
+        debugger.add_breakpoint(0x80000010);
+        debugger.add_watchpoint(0x80000200, 4, WatchKind::Access);
+
+        assert!(debugger.delete(2));
+        assert_eq!(debugger.watchpoints()[0].addr, 0x80000200);
+        assert!(debugger.delete(0));
+        assert_eq!(debugger.breakpoints(), [0x80000010]);
+        // 删除断点后观察点的编号前移
+        assert!(!debugger.delete(2));
+        assert!(debugger.delete(1));
+        assert!(debugger.watchpoints().is_empty());
+        assert!(debugger.delete(0));
+        assert!(!debugger.delete(0));
     }
 }
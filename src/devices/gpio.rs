@@ -15,6 +15,9 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use super::MmioDevice;
+use crate::error::EmuError;
+
 // GPIO 寄存器偏移
 const GPIO_DIRECTION: usize = 0x0;  // 方向寄存器
 const GPIO_OUTPUT: usize = 0x4;     // 输出寄存器
@@ -26,6 +29,12 @@ pub struct Gpio {
     input: u32,      // 输入值
 }
 
+impl Default for Gpio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Gpio {
     pub fn new() -> Self {
         Self {
@@ -34,23 +43,33 @@ impl Gpio {
             input: 0,
         }
     }
+}
 
-    pub fn read(&self, offset: usize, size: usize) -> Result<u32, &'static str> {
+impl MmioDevice for Gpio {
+    fn name(&self) -> &str {
+        "gpio"
+    }
+
+    fn size(&self) -> usize {
+        0x10
+    }
+
+    fn read(&mut self, offset: usize, size: usize) -> Result<u32, EmuError> {
         if size != 4 {
-            return Err("GPIO only supports word access");
+            return Err(EmuError::device(self.name(), offset));
         }
 
         match offset {
             GPIO_DIRECTION => Ok(self.direction),
             GPIO_OUTPUT => Ok(self.output),
             GPIO_INPUT => Ok(self.input),
-            _ => Err("Invalid GPIO register offset"),
+            _ => Err(EmuError::device(self.name(), offset)),
         }
     }
 
-    pub fn write(&mut self, offset: usize, value: u32, size: usize) -> Result<(), &'static str> {
+    fn write(&mut self, offset: usize, value: u32, size: usize) -> Result<(), EmuError> {
         if size != 4 {
-            return Err("GPIO only supports word access");
+            return Err(EmuError::device(self.name(), offset));
         }
 
         match offset {
@@ -66,15 +85,9 @@ impl Gpio {
             },
             GPIO_INPUT => {
                 // 输入寄存器是只读的
-                Err("GPIO input register is read-only")
+                Err(EmuError::device(self.name(), offset))
             },
-            _ => Err("Invalid GPIO register offset"),
+            _ => Err(EmuError::device(self.name(), offset)),
         }
     }
-
-    // 模拟输入变化（例如按钮按下）
-    pub fn set_input(&mut self, value: u32) {
-        self.input = value;
-        println!("[GPIO] Input changed to 0x{:08x}", value);
-    }
 } 
\ No newline at end of file
@@ -26,55 +26,132 @@ use gpio::Gpio;
 use timer::Timer;
 use wave::Wave;
 
+use crate::error::EmuError;
+
+// 设备中断线
+pub const IRQ_TIMER: u32 = 1 << 0;
+
+// 内存映射外设：offset 相对于设备基地址，size() 是寄存器窗口的字节数
+pub trait MmioDevice {
+    fn name(&self) -> &str;
+    fn size(&self) -> usize;
+    // 访问失败时返回 EmuError::DeviceError
+    fn read(&mut self, offset: usize, size: usize) -> Result<u32, EmuError>;
+    fn write(&mut self, offset: usize, value: u32, size: usize) -> Result<(), EmuError>;
+
+    // 每条指令执行后调用一次
+    fn tick(&mut self) {}
+
+    // 当前拉高的中断线，按 IRQ_* 位组合
+    fn interrupt_lines(&self) -> u32 {
+        0
+    }
+}
+
+struct Mapping {
+    base: usize,
+    device: Box<dyn MmioDevice>,
+}
+
+impl Mapping {
+    fn contains(&self, addr: usize) -> bool {
+        addr >= self.base && addr - self.base < self.device.size()
+    }
+}
+
+// 设备总线：按基地址把访问转发给挂载的设备
 pub struct Devices {
-    uart: Uart,
-    gpio: Gpio,
-    timer: Timer,
-    wave: Wave,
+    mappings: Vec<Mapping>,
+}
+
+impl Default for Devices {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 默认外设布局：基地址和设备
+pub fn default_devices() -> Vec<(usize, Box<dyn MmioDevice>)> {
+    vec![
+        (0x02000000, Box::new(Uart::new())),
+        (0x02000100, Box::new(Gpio::new())),
+        (0x02000200, Box::new(Timer::new())),
+        (0x02000300, Box::new(Wave::new())),
+    ]
 }
 
 impl Devices {
+    // 按默认布局挂载所有外设
     pub fn new() -> Self {
-        Self {
-            uart: Uart::new(),
-            gpio: Gpio::new(),
-            timer: Timer::new(),
-            wave: Wave::new(),
+        let mut devices = Self::empty();
+        for (base, device) in default_devices() {
+            devices.register(base, device).unwrap();
         }
+        devices
     }
 
-    pub fn read(&mut self, addr: usize, size: usize) -> Result<u32, &'static str> {
-        match addr {
-            0x02000000..=0x0200000F => self.uart.read(addr & 0xF, size),
-            0x02000100..=0x0200010F => self.gpio.read(addr & 0xF, size),
-            0x02000200..=0x0200020F => self.timer.read(addr & 0xF, size),
-            0x02000300..=0x0200031F => self.wave.read(addr & 0x1F, size),
-            _ => Err("Invalid device address"),
+    pub fn empty() -> Self {
+        Self {
+            mappings: Vec::new(),
         }
     }
 
-    pub fn write(&mut self, addr: usize, value: u32, size: usize) -> Result<(), &'static str> {
-        match addr {
-            0x02000000..=0x0200000F => self.uart.write(addr & 0xF, value, size),
-            0x02000100..=0x0200010F => self.gpio.write(addr & 0xF, value, size),
-            0x02000200..=0x0200020F => self.timer.write(addr & 0xF, value, size),
-            0x02000300..=0x0200031F => self.wave.write(addr & 0x1F, value, size),
-            _ => Err("Invalid device address"),
+    // 挂载设备，地址范围不能与已挂载的设备重叠
+    pub fn register(&mut self, base: usize, device: Box<dyn MmioDevice>) -> Result<(), EmuError> {
+        let size = device.size();
+        let end = base
+            .checked_add(size)
+            .filter(|_| size != 0)
+            .ok_or(EmuError::InvalidDeviceRange { base, size })?;
+        if self
+            .mappings
+            .iter()
+            .any(|m| base < m.base + m.device.size() && m.base < end)
+        {
+            return Err(EmuError::DeviceOverlap { base, size });
         }
+        self.mappings.push(Mapping { base, device });
+        Ok(())
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        self.mappings.iter().any(|m| m.contains(addr))
+    }
+
+    // 已挂载设备的基地址和设备本身
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &dyn MmioDevice)> {
+        self.mappings.iter().map(|m| (m.base, m.device.as_ref()))
+    }
+
+    pub fn read(&mut self, addr: usize, size: usize) -> Result<u32, EmuError> {
+        let mapping = self
+            .mappings
+            .iter_mut()
+            .find(|m| m.contains(addr))
+            .ok_or(EmuError::AccessFault { addr: addr as u64 })?;
+        mapping.device.read(addr - mapping.base, size)
+    }
+
+    pub fn write(&mut self, addr: usize, value: u32, size: usize) -> Result<(), EmuError> {
+        let mapping = self
+            .mappings
+            .iter_mut()
+            .find(|m| m.contains(addr))
+            .ok_or(EmuError::AccessFault { addr: addr as u64 })?;
+        mapping.device.write(addr - mapping.base, value, size)
     }
 
     // 更新所有设备状态
     pub fn tick(&mut self) {
-        self.timer.tick();
-        self.wave.tick();
+        for mapping in self.mappings.iter_mut() {
+            mapping.device.tick();
+        }
     }
 
     // 检查是否有待处理的中断
     pub fn check_interrupts(&self) -> u32 {
-        let mut interrupts = 0;
-        if self.timer.interrupt_pending() {
-            interrupts |= 1 << 0;  // Timer 中断位
-        }
-        interrupts
+        self.mappings
+            .iter()
+            .fold(0, |irq, m| irq | m.device.interrupt_lines())
     }
-}
\ No newline at end of file
+}
@@ -15,6 +15,9 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use super::{MmioDevice, IRQ_TIMER};
+use crate::error::EmuError;
+
 // Timer 寄存器偏移
 const TIMER_COUNT: usize = 0x0;    // 计数器值
 const TIMER_CONTROL: usize = 0x4;  // 控制寄存器
@@ -37,6 +40,12 @@ pub struct Timer {
     status: u32,     // 状态寄存器
 }
 
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Timer {
     pub fn new() -> Self {
         Self {
@@ -47,9 +56,24 @@ impl Timer {
         }
     }
 
-    pub fn read(&self, offset: usize, size: usize) -> Result<u32, &'static str> {
+    // 检查是否需要触发中断
+    pub fn interrupt_pending(&self) -> bool {
+        (self.control & CONTROL_INTERRUPT != 0) && (self.status & STATUS_MATCH != 0)
+    }
+}
+
+impl MmioDevice for Timer {
+    fn name(&self) -> &str {
+        "timer"
+    }
+
+    fn size(&self) -> usize {
+        0x10
+    }
+
+    fn read(&mut self, offset: usize, size: usize) -> Result<u32, EmuError> {
         if size != 4 {
-            return Err("Timer only supports word access");
+            return Err(EmuError::device(self.name(), offset));
         }
 
         match offset {
@@ -57,13 +81,13 @@ impl Timer {
             TIMER_CONTROL => Ok(self.control),
             TIMER_COMPARE => Ok(self.compare),
             TIMER_STATUS => Ok(self.status),
-            _ => Err("Invalid Timer register offset"),
+            _ => Err(EmuError::device(self.name(), offset)),
         }
     }
 
-    pub fn write(&mut self, offset: usize, value: u32, size: usize) -> Result<(), &'static str> {
+    fn write(&mut self, offset: usize, value: u32, size: usize) -> Result<(), EmuError> {
         if size != 4 {
-            return Err("Timer only supports word access");
+            return Err(EmuError::device(self.name(), offset));
         }
 
         match offset {
@@ -91,12 +115,12 @@ impl Timer {
                 println!("[Timer] Status cleared to 0x{:08x}", self.status);
                 Ok(())
             },
-            _ => Err("Invalid Timer register offset"),
+            _ => Err(EmuError::device(self.name(), offset)),
         }
     }
 
     // 更新定时器状态（每个时钟周期调用）
-    pub fn tick(&mut self) {
+    fn tick(&mut self) {
         if self.control & CONTROL_ENABLE != 0 {
             // 每个指令周期增加1
             self.count = self.count.wrapping_add(1);
@@ -114,8 +138,11 @@ impl Timer {
         }
     }
 
-    // 检查是否需要触发中断
-    pub fn interrupt_pending(&self) -> bool {
-        (self.control & CONTROL_INTERRUPT != 0) && (self.status & STATUS_MATCH != 0)
+    fn interrupt_lines(&self) -> u32 {
+        if self.interrupt_pending() {
+            IRQ_TIMER
+        } else {
+            0
+        }
     }
 } 
\ No newline at end of file
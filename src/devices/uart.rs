@@ -15,6 +15,9 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use super::MmioDevice;
+use crate::error::EmuError;
+
 // UART 寄存器偏移
 const UART_DATA: usize = 0x0;     // 数据寄存器
 const UART_STATUS: usize = 0x4;   // 状态寄存器
@@ -32,6 +35,12 @@ pub struct Uart {
     control: u8,     // 控制寄存器
 }
 
+impl Default for Uart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Uart {
     pub fn new() -> Self {
         Self {
@@ -40,23 +49,33 @@ impl Uart {
             control: 0,
         }
     }
+}
+
+impl MmioDevice for Uart {
+    fn name(&self) -> &str {
+        "uart"
+    }
+
+    fn size(&self) -> usize {
+        0x10
+    }
 
-    pub fn read(&self, offset: usize, size: usize) -> Result<u32, &'static str> {
+    fn read(&mut self, offset: usize, size: usize) -> Result<u32, EmuError> {
         if size != 1 {
-            return Err("UART only supports byte access");
+            return Err(EmuError::device(self.name(), offset));
         }
 
         match offset {
             UART_DATA => Ok(self.data as u32),
             UART_STATUS => Ok(self.status as u32),
             UART_CONTROL => Ok(self.control as u32),
-            _ => Err("Invalid UART register offset"),
+            _ => Err(EmuError::device(self.name(), offset)),
         }
     }
 
-    pub fn write(&mut self, offset: usize, value: u32, size: usize) -> Result<(), &'static str> {
+    fn write(&mut self, offset: usize, value: u32, size: usize) -> Result<(), EmuError> {
         if size != 1 {
-            return Err("UART only supports byte access");
+            return Err(EmuError::device(self.name(), offset));
         }
 
         let value = value as u8;
@@ -75,7 +94,7 @@ impl Uart {
                 self.control = value;
                 Ok(())
             },
-            _ => Err("Invalid UART register offset"),
+            _ => Err(EmuError::device(self.name(), offset)),
         }
     }
 } 
\ No newline at end of file
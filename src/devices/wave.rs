@@ -18,6 +18,9 @@
 use std::fs::File;
 use std::io::Write;
 
+use super::MmioDevice;
+use crate::error::EmuError;
+
 // 波形发生器寄存器偏移
 const WAVE_CONTROL: usize = 0x0;    // 控制寄存器
 const WAVE_FREQUENCY: usize = 0x4;  // 频率寄存器
@@ -44,6 +47,12 @@ pub struct Wave {
     output_file: Option<File>,
 }
 
+impl Default for Wave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Wave {
     pub fn new() -> Self {
         Self {
@@ -105,10 +114,20 @@ impl Wave {
             },
         }
     }
+}
+
+impl MmioDevice for Wave {
+    fn name(&self) -> &str {
+        "wave"
+    }
+
+    fn size(&self) -> usize {
+        0x20
+    }
 
-    pub fn read(&self, offset: usize, size: usize) -> Result<u32, &'static str> {
+    fn read(&mut self, offset: usize, size: usize) -> Result<u32, EmuError> {
         if size != 4 {
-            return Err("Wave generator only supports word access");
+            return Err(EmuError::device(self.name(), offset));
         }
 
         match offset {
@@ -117,20 +136,20 @@ impl Wave {
             WAVE_AMPLITUDE => Ok(self.amplitude),
             WAVE_PHASE => Ok(self.phase),
             WAVE_DUTY => Ok(self.duty),
-            _ => Err("Invalid wave generator register offset"),
+            _ => Err(EmuError::device(self.name(), offset)),
         }
     }
 
-    pub fn write(&mut self, offset: usize, value: u32, size: usize) -> Result<(), &'static str> {
+    fn write(&mut self, offset: usize, value: u32, size: usize) -> Result<(), EmuError> {
         if size != 4 {
-            return Err("Wave generator only supports word access");
+            return Err(EmuError::device(self.name(), offset));
         }
 
         match offset {
             WAVE_CONTROL => {
                 self.control = value;
                 if self.is_enabled() && self.output_file.is_none() {
-                    self.output_file = Some(File::create("wave.txt").map_err(|_| "Failed to create wave.txt")?);
+                    self.output_file = Some(File::create("wave.txt").map_err(|_| EmuError::device(self.name(), offset))?);
                 }
                 Ok(())
             },
@@ -150,11 +169,11 @@ impl Wave {
                 self.duty = value.min(100);
                 Ok(())
             },
-            _ => Err("Invalid wave generator register offset"),
+            _ => Err(EmuError::device(self.name(), offset)),
         }
     }
 
-    pub fn tick(&mut self) {
+    fn tick(&mut self) {
         if self.is_enabled() {
             let value = self.calculate_value();  // 先计算值
             if let Some(file) = &mut self.output_file {
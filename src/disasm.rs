@@ -0,0 +1,868 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// RV32/RV64 反汇编器：把原始指令字转换为汇编文本，识别常见伪指令
+
+use crate::csr::csr_name;
+use crate::inst::{InstType, Operands};
+use crate::isa::Xlen;
+use crate::register::{ABI_NAMES, FP_ABI_NAMES};
+use crate::rvc;
+
+fn reg(index: usize) -> &'static str {
+    ABI_NAMES[index]
+}
+
+fn freg(index: usize) -> &'static str {
+    FP_ABI_NAMES[index]
+}
+
+fn csr(addr: u16) -> String {
+    match csr_name(addr) {
+        Some(name) => name.to_string(),
+        None => format!("0x{:03x}", addr),
+    }
+}
+
+fn format_inst(mnemonic: &str, operands: &str) -> String {
+    if operands.is_empty() {
+        mnemonic.to_string()
+    } else {
+        format!("{} {}", mnemonic, operands)
+    }
+}
+
+// pc 用于计算分支和跳转的目标地址；压缩指令按展开后的形式显示
+pub fn disassemble(inst: u32, pc: u64, xlen: Xlen) -> String {
+    if rvc::is_compressed(inst) {
+        return match rvc::expand(inst as u16, xlen) {
+            Ok(expanded) => disassemble(expanded, pc, xlen),
+            Err(_) => format!("unknown 0x{:04x}", inst & 0xffff),
+        };
+    }
+
+    let opcode = inst & 0x7f;
+    let rv64 = xlen == Xlen::Rv64;
+
+    match opcode {
+        0x33 => disasm_r_type(inst, xlen),
+        0x13 => disasm_i_type_alu(inst, xlen),
+        0x3b if rv64 => disasm_r_type_word(inst),
+        0x1b if rv64 => disasm_i_type_word(inst),
+        0x03 => disasm_load(inst, xlen),
+        0x23 => disasm_store(inst, xlen),
+        0x63 => disasm_branch(inst, pc),
+        0x67 => disasm_jalr(inst),
+        0x6f => disasm_jal(inst, pc),
+        0x37 | 0x17 => {
+            let ops = Operands::decode(inst, InstType::U);
+            let mnemonic = if opcode == 0x37 { "lui" } else { "auipc" };
+            format_inst(
+                mnemonic,
+                &format!("{}, 0x{:x}", reg(ops.rd), (ops.imm as u32) >> 12),
+            )
+        }
+        0x0f if (inst >> 12) & 0x7 == 0 => disasm_fence(inst),
+        0x0f if (inst >> 12) & 0x7 == 1 => "fence.i".to_string(),
+        0x73 => disasm_system(inst),
+        0x2f => disasm_atomic(inst, xlen),
+        0x07 | 0x27 => disasm_fp_mem(inst),
+        0x43 | 0x47 | 0x4b | 0x4f => disasm_fp_fused(inst),
+        0x53 => disasm_fp_op(inst, xlen),
+        _ => unknown(inst),
+    }
+}
+
+fn unknown(inst: u32) -> String {
+    format!("unknown 0x{:08x}", inst)
+}
+
+fn disasm_r_type(inst: u32, xlen: Xlen) -> String {
+    let ops = Operands::decode(inst, InstType::R);
+    let funct3 = (inst >> 12) & 0x7;
+    let funct7 = (inst >> 25) & 0x7f;
+
+    let mnemonic = match (funct3, funct7) {
+        (0x0, 0x00) => "add",
+        (0x0, 0x20) => "sub",
+        (0x1, 0x00) => "sll",
+        (0x2, 0x00) => "slt",
+        (0x3, 0x00) => "sltu",
+        (0x4, 0x00) => "xor",
+        (0x5, 0x00) => "srl",
+        (0x5, 0x20) => "sra",
+        (0x6, 0x00) => "or",
+        (0x7, 0x00) => "and",
+        (0x0, 0x01) => "mul",
+        (0x1, 0x01) => "mulh",
+        (0x2, 0x01) => "mulhsu",
+        (0x3, 0x01) => "mulhu",
+        (0x4, 0x01) => "div",
+        (0x5, 0x01) => "divu",
+        (0x6, 0x01) => "rem",
+        (0x7, 0x01) => "remu",
+        (0x2, 0x10) => "sh1add",
+        (0x4, 0x10) => "sh2add",
+        (0x6, 0x10) => "sh3add",
+        (0x7, 0x20) => "andn",
+        (0x6, 0x20) => "orn",
+        (0x4, 0x20) => "xnor",
+        (0x4, 0x05) => "min",
+        (0x5, 0x05) => "minu",
+        (0x6, 0x05) => "max",
+        (0x7, 0x05) => "maxu",
+        (0x4, 0x04) if ops.rs2 == 0 && xlen == Xlen::Rv32 => {
+            return format_inst("zext.h", &format!("{}, {}", reg(ops.rd), reg(ops.rs1)));
+        }
+        (0x1, 0x30) => "rol",
+        (0x5, 0x30) => "ror",
+        (0x1, 0x05) => "clmul",
+        (0x2, 0x05) => "clmulr",
+        (0x3, 0x05) => "clmulh",
+        (0x1, 0x24) => "bclr",
+        (0x5, 0x24) => "bext",
+        (0x1, 0x34) => "binv",
+        (0x1, 0x14) => "bset",
+        _ => return unknown(inst),
+    };
+
+    let (rd, rs1, rs2) = (reg(ops.rd), reg(ops.rs1), reg(ops.rs2));
+    match mnemonic {
+        "sub" if ops.rs1 == 0 => format_inst("neg", &format!("{}, {}", rd, rs2)),
+        "sltu" if ops.rs1 == 0 => format_inst("snez", &format!("{}, {}", rd, rs2)),
+        "slt" if ops.rs2 == 0 => format_inst("sltz", &format!("{}, {}", rd, rs1)),
+        "slt" if ops.rs1 == 0 => format_inst("sgtz", &format!("{}, {}", rd, rs2)),
+        _ => format_inst(mnemonic, &format!("{}, {}, {}", rd, rs1, rs2)),
+    }
+}
+
+fn disasm_i_type_alu(inst: u32, xlen: Xlen) -> String {
+    let ops = Operands::decode(inst, InstType::I);
+    let funct3 = (inst >> 12) & 0x7;
+    let funct7 = (inst >> 25) & 0x7f;
+    let (rd, rs1, imm) = (reg(ops.rd), reg(ops.rs1), ops.imm);
+    // RV64 的移位量为 6 位
+    let (funct, shamt) = match xlen {
+        Xlen::Rv32 => (funct7, imm & 0x1f),
+        Xlen::Rv64 => (funct7 & !0x1, imm & 0x3f),
+    };
+    let rev8 = match xlen {
+        Xlen::Rv32 => 0x698,
+        Xlen::Rv64 => 0x6b8,
+    };
+
+    let mnemonic = match funct3 {
+        0x0 => {
+            if ops.rd == 0 && ops.rs1 == 0 && imm == 0 {
+                return "nop".to_string();
+            } else if ops.rs1 == 0 {
+                return format_inst("li", &format!("{}, {}", rd, imm));
+            } else if imm == 0 {
+                return format_inst("mv", &format!("{}, {}", rd, rs1));
+            }
+            "addi"
+        }
+        0x1 => {
+            let mnemonic = match (funct7, ops.rs2) {
+                (0x30, 0) => return format_inst("clz", &format!("{}, {}", rd, rs1)),
+                (0x30, 1) => return format_inst("ctz", &format!("{}, {}", rd, rs1)),
+                (0x30, 2) => return format_inst("cpop", &format!("{}, {}", rd, rs1)),
+                (0x30, 4) => return format_inst("sext.b", &format!("{}, {}", rd, rs1)),
+                (0x30, 5) => return format_inst("sext.h", &format!("{}, {}", rd, rs1)),
+                _ => match funct {
+                    0x00 => "slli",
+                    0x14 => "bseti",
+                    0x24 => "bclri",
+                    0x34 => "binvi",
+                    _ => return unknown(inst),
+                },
+            };
+            return format_inst(mnemonic, &format!("{}, {}, {}", rd, rs1, shamt));
+        }
+        0x2 => "slti",
+        0x3 => {
+            if imm == 1 {
+                return format_inst("seqz", &format!("{}, {}", rd, rs1));
+            }
+            "sltiu"
+        }
+        0x4 => {
+            if imm == -1 {
+                return format_inst("not", &format!("{}, {}", rd, rs1));
+            }
+            "xori"
+        }
+        0x5 => {
+            let mnemonic = match imm & 0xfff {
+                0x287 => return format_inst("orc.b", &format!("{}, {}", rd, rs1)),
+                imm12 if imm12 == rev8 => {
+                    return format_inst("rev8", &format!("{}, {}", rd, rs1));
+                }
+                _ => match funct {
+                    0x00 => "srli",
+                    0x20 => "srai",
+                    0x30 => "rori",
+                    0x24 => "bexti",
+                    _ => return unknown(inst),
+                },
+            };
+            return format_inst(mnemonic, &format!("{}, {}, {}", rd, rs1, shamt));
+        }
+        0x6 => "ori",
+        0x7 => "andi",
+        _ => unreachable!(),
+    };
+
+    format_inst(mnemonic, &format!("{}, {}, {}", rd, rs1, imm))
+}
+
+fn disasm_r_type_word(inst: u32) -> String {
+    let ops = Operands::decode(inst, InstType::R);
+    let funct3 = (inst >> 12) & 0x7;
+    let funct7 = (inst >> 25) & 0x7f;
+    let (rd, rs1, rs2) = (reg(ops.rd), reg(ops.rs1), reg(ops.rs2));
+
+    let mnemonic = match (funct3, funct7) {
+        (0x0, 0x00) => "addw",
+        (0x0, 0x20) if ops.rs1 == 0 => return format_inst("negw", &format!("{}, {}", rd, rs2)),
+        (0x0, 0x20) => "subw",
+        (0x1, 0x00) => "sllw",
+        (0x5, 0x00) => "srlw",
+        (0x5, 0x20) => "sraw",
+        (0x0, 0x01) => "mulw",
+        (0x4, 0x01) => "divw",
+        (0x5, 0x01) => "divuw",
+        (0x6, 0x01) => "remw",
+        (0x7, 0x01) => "remuw",
+        (0x0, 0x04) if ops.rs2 == 0 => return format_inst("zext.w", &format!("{}, {}", rd, rs1)),
+        (0x0, 0x04) => "add.uw",
+        (0x2, 0x10) => "sh1add.uw",
+        (0x4, 0x10) => "sh2add.uw",
+        (0x6, 0x10) => "sh3add.uw",
+        (0x4, 0x04) if ops.rs2 == 0 => return format_inst("zext.h", &format!("{}, {}", rd, rs1)),
+        (0x1, 0x30) => "rolw",
+        (0x5, 0x30) => "rorw",
+        _ => return unknown(inst),
+    };
+
+    format_inst(mnemonic, &format!("{}, {}, {}", rd, rs1, rs2))
+}
+
+fn disasm_i_type_word(inst: u32) -> String {
+    let ops = Operands::decode(inst, InstType::I);
+    let funct3 = (inst >> 12) & 0x7;
+    let funct7 = (inst >> 25) & 0x7f;
+    let (rd, rs1, imm) = (reg(ops.rd), reg(ops.rs1), ops.imm);
+
+    let mnemonic = match (funct3, funct7, ops.rs2) {
+        (0x0, _, _) if imm == 0 => return format_inst("sext.w", &format!("{}, {}", rd, rs1)),
+        (0x0, _, _) => return format_inst("addiw", &format!("{}, {}, {}", rd, rs1, imm)),
+        (0x1, 0x00, _) => "slliw",
+        (0x1, 0x30, 0) => return format_inst("clzw", &format!("{}, {}", rd, rs1)),
+        (0x1, 0x30, 1) => return format_inst("ctzw", &format!("{}, {}", rd, rs1)),
+        (0x1, 0x30, 2) => return format_inst("cpopw", &format!("{}, {}", rd, rs1)),
+        (0x1, 0x04 | 0x05, _) => {
+            return format_inst("slli.uw", &format!("{}, {}, {}", rd, rs1, imm & 0x3f));
+        }
+        (0x5, 0x00, _) => "srliw",
+        (0x5, 0x20, _) => "sraiw",
+        (0x5, 0x30, _) => "roriw",
+        _ => return unknown(inst),
+    };
+
+    format_inst(mnemonic, &format!("{}, {}, {}", rd, rs1, imm & 0x1f))
+}
+
+fn disasm_load(inst: u32, xlen: Xlen) -> String {
+    let ops = Operands::decode(inst, InstType::I);
+    let funct3 = (inst >> 12) & 0x7;
+
+    let mnemonic = match funct3 {
+        0x0 => "lb",
+        0x1 => "lh",
+        0x2 => "lw",
+        0x4 => "lbu",
+        0x5 => "lhu",
+        0x3 if xlen == Xlen::Rv64 => "ld",
+        0x6 if xlen == Xlen::Rv64 => "lwu",
+        _ => return unknown(inst),
+    };
+
+    format_inst(
+        mnemonic,
+        &format!("{}, {}({})", reg(ops.rd), ops.imm, reg(ops.rs1)),
+    )
+}
+
+fn disasm_store(inst: u32, xlen: Xlen) -> String {
+    let ops = Operands::decode(inst, InstType::S);
+    let funct3 = (inst >> 12) & 0x7;
+
+    let mnemonic = match funct3 {
+        0x0 => "sb",
+        0x1 => "sh",
+        0x2 => "sw",
+        0x3 if xlen == Xlen::Rv64 => "sd",
+        _ => return unknown(inst),
+    };
+
+    format_inst(
+        mnemonic,
+        &format!("{}, {}({})", reg(ops.rs2), ops.imm, reg(ops.rs1)),
+    )
+}
+
+fn disasm_branch(inst: u32, pc: u64) -> String {
+    let ops = Operands::decode(inst, InstType::B);
+    let funct3 = (inst >> 12) & 0x7;
+    let target = pc.wrapping_add(ops.imm as u64);
+    let (rs1, rs2) = (reg(ops.rs1), reg(ops.rs2));
+
+    let mnemonic = match funct3 {
+        0x0 => "beq",
+        0x1 => "bne",
+        0x4 => "blt",
+        0x5 => "bge",
+        0x6 => "bltu",
+        0x7 => "bgeu",
+        _ => return unknown(inst),
+    };
+
+    // 与 zero 比较的伪指令
+    let pseudo = match (mnemonic, ops.rs1, ops.rs2) {
+        ("beq", _, 0) => Some(("beqz", rs1)),
+        ("bne", _, 0) => Some(("bnez", rs1)),
+        ("bge", 0, _) => Some(("blez", rs2)),
+        ("bge", _, 0) => Some(("bgez", rs1)),
+        ("blt", _, 0) => Some(("bltz", rs1)),
+        ("blt", 0, _) => Some(("bgtz", rs2)),
+        _ => None,
+    };
+
+    match pseudo {
+        Some((mnemonic, rs)) => format_inst(mnemonic, &format!("{}, 0x{:x}", rs, target)),
+        None => format_inst(mnemonic, &format!("{}, {}, 0x{:x}", rs1, rs2, target)),
+    }
+}
+
+fn disasm_jalr(inst: u32) -> String {
+    let ops = Operands::decode(inst, InstType::I);
+    if (inst >> 12) & 0x7 != 0 {
+        return unknown(inst);
+    }
+
+    match (ops.rd, ops.rs1, ops.imm) {
+        (0, 1, 0) => "ret".to_string(),
+        (0, rs1, 0) => format_inst("jr", reg(rs1)),
+        (1, rs1, 0) => format_inst("jalr", reg(rs1)),
+        (rd, rs1, imm) => format_inst("jalr", &format!("{}, {}({})", reg(rd), imm, reg(rs1))),
+    }
+}
+
+fn disasm_jal(inst: u32, pc: u64) -> String {
+    let ops = Operands::decode(inst, InstType::J);
+    let target = pc.wrapping_add(ops.imm as u64);
+
+    match ops.rd {
+        0 => format_inst("j", &format!("0x{:x}", target)),
+        1 => format_inst("jal", &format!("0x{:x}", target)),
+        rd => format_inst("jal", &format!("{}, 0x{:x}", reg(rd), target)),
+    }
+}
+
+fn disasm_atomic(inst: u32, xlen: Xlen) -> String {
+    let ops = Operands::decode(inst, InstType::R);
+    let width = match (inst >> 12) & 0x7 {
+        0x2 => "w",
+        0x3 if xlen == Xlen::Rv64 => "d",
+        _ => return unknown(inst),
+    };
+
+    let name = match inst >> 27 {
+        0x02 if ops.rs2 == 0 => "lr",
+        0x03 => "sc",
+        0x01 => "amoswap",
+        0x00 => "amoadd",
+        0x04 => "amoxor",
+        0x0c => "amoand",
+        0x08 => "amoor",
+        0x10 => "amomin",
+        0x14 => "amomax",
+        0x18 => "amominu",
+        0x1c => "amomaxu",
+        _ => return unknown(inst),
+    };
+    let ordering = match (inst >> 25) & 0x3 {
+        0b10 => ".aq",
+        0b01 => ".rl",
+        0b11 => ".aqrl",
+        _ => "",
+    };
+    let mnemonic = format!("{}.{}{}", name, width, ordering);
+
+    if name == "lr" {
+        format_inst(&mnemonic, &format!("{}, ({})", reg(ops.rd), reg(ops.rs1)))
+    } else {
+        format_inst(
+            &mnemonic,
+            &format!("{}, {}, ({})", reg(ops.rd), reg(ops.rs2), reg(ops.rs1)),
+        )
+    }
+}
+
+// 动态舍入模式省略不写，保留编码返回 None
+fn rounding_mode(inst: u32) -> Option<&'static str> {
+    match (inst >> 12) & 0x7 {
+        0 => Some(", rne"),
+        1 => Some(", rtz"),
+        2 => Some(", rdn"),
+        3 => Some(", rup"),
+        4 => Some(", rmm"),
+        7 => Some(""),
+        _ => None,
+    }
+}
+
+fn fp_suffix(inst: u32) -> Option<&'static str> {
+    match (inst >> 25) & 0x3 {
+        0 => Some("s"),
+        1 => Some("d"),
+        _ => None,
+    }
+}
+
+fn disasm_fp_mem(inst: u32) -> String {
+    let is_store = inst & 0x7f == 0x27;
+    let ops = Operands::decode(inst, if is_store { InstType::S } else { InstType::I });
+    let mnemonic = match ((inst >> 12) & 0x7, is_store) {
+        (0x2, false) => "flw",
+        (0x3, false) => "fld",
+        (0x2, true) => "fsw",
+        (0x3, true) => "fsd",
+        _ => return unknown(inst),
+    };
+    let value = if is_store { ops.rs2 } else { ops.rd };
+
+    format_inst(
+        mnemonic,
+        &format!("{}, {}({})", freg(value), ops.imm, reg(ops.rs1)),
+    )
+}
+
+fn disasm_fp_fused(inst: u32) -> String {
+    let ops = Operands::decode(inst, InstType::R);
+    let rs3 = (inst >> 27) as usize;
+    let (Some(suffix), Some(rm)) = (fp_suffix(inst), rounding_mode(inst)) else {
+        return unknown(inst);
+    };
+    let name = match inst & 0x7f {
+        0x43 => "fmadd",
+        0x47 => "fmsub",
+        0x4b => "fnmsub",
+        _ => "fnmadd",
+    };
+
+    format_inst(
+        &format!("{}.{}", name, suffix),
+        &format!(
+            "{}, {}, {}, {}{}",
+            freg(ops.rd),
+            freg(ops.rs1),
+            freg(ops.rs2),
+            freg(rs3),
+            rm
+        ),
+    )
+}
+
+fn disasm_fp_op(inst: u32, xlen: Xlen) -> String {
+    let ops = Operands::decode(inst, InstType::R);
+    let funct3 = (inst >> 12) & 0x7;
+    let Some(s) = fp_suffix(inst) else {
+        return unknown(inst);
+    };
+    let (rd, rs1, rs2) = (ops.rd, ops.rs1, ops.rs2);
+    let rm = rounding_mode(inst);
+
+    // 三个浮点寄存器操作数
+    let fp3 = |name: &str| {
+        format_inst(
+            &format!("{}.{}", name, s),
+            &format!("{}, {}, {}", freg(rd), freg(rs1), freg(rs2)),
+        )
+    };
+    // 整数目的寄存器的比较
+    let cmp = |name: &str| {
+        format_inst(
+            &format!("{}.{}", name, s),
+            &format!("{}, {}, {}", reg(rd), freg(rs1), freg(rs2)),
+        )
+    };
+
+    match (inst >> 27, funct3, rs2) {
+        (0x00..=0x03, _, _) => {
+            let Some(rm) = rm else {
+                return unknown(inst);
+            };
+            let name = ["fadd", "fsub", "fmul", "fdiv"][(inst >> 27) as usize];
+            format_inst(
+                &format!("{}.{}", name, s),
+                &format!("{}, {}, {}{}", freg(rd), freg(rs1), freg(rs2), rm),
+            )
+        }
+        (0x0b, _, 0) => match rm {
+            Some(rm) => format_inst(
+                &format!("fsqrt.{}", s),
+                &format!("{}, {}{}", freg(rd), freg(rs1), rm),
+            ),
+            None => unknown(inst),
+        },
+        // 符号注入的伪指令：fmv / fneg / fabs
+        (0x04, 0x0, _) if rs1 == rs2 => format_inst(
+            &format!("fmv.{}", s),
+            &format!("{}, {}", freg(rd), freg(rs1)),
+        ),
+        (0x04, 0x1, _) if rs1 == rs2 => format_inst(
+            &format!("fneg.{}", s),
+            &format!("{}, {}", freg(rd), freg(rs1)),
+        ),
+        (0x04, 0x2, _) if rs1 == rs2 => format_inst(
+            &format!("fabs.{}", s),
+            &format!("{}, {}", freg(rd), freg(rs1)),
+        ),
+        (0x04, 0x0, _) => fp3("fsgnj"),
+        (0x04, 0x1, _) => fp3("fsgnjn"),
+        (0x04, 0x2, _) => fp3("fsgnjx"),
+        (0x05, 0x0, _) => fp3("fmin"),
+        (0x05, 0x1, _) => fp3("fmax"),
+        (0x08, _, 1) if s == "s" => match rm {
+            Some(rm) => format_inst("fcvt.s.d", &format!("{}, {}{}", freg(rd), freg(rs1), rm)),
+            None => unknown(inst),
+        },
+        // 单精度到双精度以及整数到双精度的转换总是精确的，不显示舍入模式
+        (0x08, _, 0) if s == "d" => {
+            format_inst("fcvt.d.s", &format!("{}, {}", freg(rd), freg(rs1)))
+        }
+        (0x18, _, 0..=3) if rs2 < 2 || xlen == Xlen::Rv64 => match rm {
+            Some(rm) => {
+                let int = ["w", "wu", "l", "lu"][rs2];
+                format_inst(
+                    &format!("fcvt.{}.{}", int, s),
+                    &format!("{}, {}{}", reg(rd), freg(rs1), rm),
+                )
+            }
+            None => unknown(inst),
+        },
+        (0x1a, _, 0..=3) if rs2 < 2 || xlen == Xlen::Rv64 => {
+            let int = ["w", "wu", "l", "lu"][rs2];
+            let rm = match rm {
+                Some(_) if s == "d" && rs2 < 2 => "",
+                Some(rm) => rm,
+                None => return unknown(inst),
+            };
+            format_inst(
+                &format!("fcvt.{}.{}", s, int),
+                &format!("{}, {}{}", freg(rd), reg(rs1), rm),
+            )
+        }
+        (0x1c, 0x0, 0) if s == "s" => {
+            format_inst("fmv.x.w", &format!("{}, {}", reg(rd), freg(rs1)))
+        }
+        (0x1c, 0x0, 0) if xlen == Xlen::Rv64 => {
+            format_inst("fmv.x.d", &format!("{}, {}", reg(rd), freg(rs1)))
+        }
+        (0x1c, 0x1, 0) => format_inst(
+            &format!("fclass.{}", s),
+            &format!("{}, {}", reg(rd), freg(rs1)),
+        ),
+        (0x1e, 0x0, 0) if s == "s" => {
+            format_inst("fmv.w.x", &format!("{}, {}", freg(rd), reg(rs1)))
+        }
+        (0x1e, 0x0, 0) if xlen == Xlen::Rv64 => {
+            format_inst("fmv.d.x", &format!("{}, {}", freg(rd), reg(rs1)))
+        }
+        (0x14, 0x2, _) => cmp("feq"),
+        (0x14, 0x1, _) => cmp("flt"),
+        (0x14, 0x0, _) => cmp("fle"),
+        _ => unknown(inst),
+    }
+}
+
+// pred/succ 都是 iorw 时省略操作数
+fn disasm_fence(inst: u32) -> String {
+    if inst == 0x8330000f {
+        return "fence.tso".to_string();
+    }
+    let set = |bits: u32| -> String {
+        "iorw"
+            .chars()
+            .enumerate()
+            .filter(|(i, _)| bits & (0x8 >> i) != 0)
+            .map(|(_, c)| c)
+            .collect()
+    };
+    let (pred, succ) = ((inst >> 24) & 0xf, (inst >> 20) & 0xf);
+    if pred == 0xf && succ == 0xf {
+        "fence".to_string()
+    } else {
+        format_inst("fence", &format!("{}, {}", set(pred), set(succ)))
+    }
+}
+
+fn disasm_system(inst: u32) -> String {
+    let funct3 = (inst >> 12) & 0x7;
+    let rd = ((inst >> 7) & 0x1f) as usize;
+    let rs1 = ((inst >> 15) & 0x1f) as usize;
+    let csr_addr = (inst >> 20) as u16;
+
+    if funct3 == 0 {
+        return match inst {
+            0x00000073 => "ecall".to_string(),
+            0x00100073 => "ebreak".to_string(),
+            0x30200073 => "mret".to_string(),
+            0x10200073 => "sret".to_string(),
+            0x10500073 => "wfi".to_string(),
+            _ if inst & 0xfe007fff == 0x12000073 => {
+                let rs2 = ((inst >> 20) & 0x1f) as usize;
+                match (rs1, rs2) {
+                    (0, 0) => "sfence.vma".to_string(),
+                    (_, 0) => format_inst("sfence.vma", reg(rs1)),
+                    _ => format_inst("sfence.vma", &format!("{}, {}", reg(rs1), reg(rs2))),
+                }
+            }
+            _ => unknown(inst),
+        };
+    }
+
+    let name = csr(csr_addr);
+    let imm_form = funct3 & 0x4 != 0;
+    let src = if imm_form {
+        rs1.to_string()
+    } else {
+        reg(rs1).to_string()
+    };
+
+    // csrr/csrw/csrs/csrc 及其立即数形式
+    match (funct3 & 0x3, rd, rs1) {
+        (0x2, _, 0) if !imm_form => format_inst("csrr", &format!("{}, {}", reg(rd), name)),
+        (op, 0, _) => {
+            let mnemonic = match op {
+                0x1 => "csrw",
+                0x2 => "csrs",
+                0x3 => "csrc",
+                _ => return unknown(inst),
+            };
+            let mnemonic = if imm_form {
+                format!("{}i", mnemonic)
+            } else {
+                mnemonic.to_string()
+            };
+            format_inst(&mnemonic, &format!("{}, {}", name, src))
+        }
+        (op, _, _) => {
+            let mnemonic = match (op, imm_form) {
+                (0x1, false) => "csrrw",
+                (0x2, false) => "csrrs",
+                (0x3, false) => "csrrc",
+                (0x1, true) => "csrrwi",
+                (0x2, true) => "csrrsi",
+                (0x3, true) => "csrrci",
+                _ => return unknown(inst),
+            };
+            format_inst(mnemonic, &format!("{}, {}, {}", reg(rd), name, src))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        let cases = [
+            (0x00c58533, "add a0, a1, a2"),
+            (0x40c00533, "neg a0, a2"),
+            (0x40c58533, "sub a0, a1, a2"),
+            (0x00b03533, "snez a0, a1"),
+            (0x0005a533, "sltz a0, a1"),
+            (0x027302b3, "mul t0, t1, t2"),
+            (0x033974b3, "remu s1, s2, s3"),
+            (0x00000013, "nop"),
+            (0xffb00513, "li a0, -5"),
+            (0x00058513, "mv a0, a1"),
+            (0xff010113, "addi sp, sp, -16"),
+            (0x00351513, "slli a0, a0, 3"),
+            (0x41f55513, "srai a0, a0, 31"),
+            (0x0015d593, "srli a1, a1, 1"),
+            (0x0015b513, "seqz a0, a1"),
+            (0xfff5c513, "not a0, a1"),
+            (0x0075c513, "xori a0, a1, 7"),
+            (0xffc10503, "lb a0, -4(sp)"),
+            (0x0025d503, "lhu a0, 2(a1)"),
+            (0x00112623, "sw ra, 12(sp)"),
+            (0x00050023, "sb zero, 0(a0)"),
+            (0x12345537, "lui a0, 0x12345"),
+            (0xfffff517, "auipc a0, 0xfffff"),
+            (0x0ff0000f, "fence"),
+            (0x0330000f, "fence rw, rw"),
+            (0x8330000f, "fence.tso"),
+            (0x0000100f, "fence.i"),
+            (0x000500e7, "jalr a0"),
+            (0x00050067, "jr a0"),
+            (0x00008067, "ret"),
+            (0x004502e7, "jalr t0, 4(a0)"),
+            (0x00000073, "ecall"),
+            (0x00100073, "ebreak"),
+            (0x30200073, "mret"),
+            (0x10200073, "sret"),
+            (0x10500073, "wfi"),
+            (0x12000073, "sfence.vma"),
+            (0x12050073, "sfence.vma a0"),
+            (0x12b50073, "sfence.vma a0, a1"),
+            (0x34202573, "csrr a0, mcause"),
+            (0x30529073, "csrw mtvec, t0"),
+            (0x30046073, "csrsi mstatus, 8"),
+            (0x34051573, "csrrw a0, mscratch, a0"),
+            (0x3042f5f3, "csrrci a1, mie, 5"),
+            (0x34463073, "csrc mip, a2"),
+            (0x1005a52f, "lr.w a0, (a1)"),
+            (0x1ac5a52f, "sc.w.rl a0, a2, (a1)"),
+            (0x066122af, "amoadd.w.aqrl t0, t1, (sp)"),
+            (0xe0b6252f, "amomaxu.w a0, a1, (a2)"),
+            (0x00452507, "flw fa0, 4(a0)"),
+            (0xfea12c27, "fsw fa0, -8(sp)"),
+            (0x01013407, "fld fs0, 16(sp)"),
+            (0x0085b427, "fsd fs0, 8(a1)"),
+            (0x00c5f553, "fadd.s fa0, fa1, fa2"),
+            (0x00c59553, "fadd.s fa0, fa1, fa2, rtz"),
+            (0x0a208053, "fsub.d ft0, ft1, ft2, rne"),
+            (0x5a05b553, "fsqrt.d fa0, fa1, rup"),
+            (0x68c5f543, "fmadd.s fa0, fa1, fa2, fa3"),
+            (0x6ac5a547, "fmsub.d fa0, fa1, fa2, fa3, rdn"),
+            (0x6ac5f54f, "fnmadd.d fa0, fa1, fa2, fa3"),
+            (0x20b58553, "fmv.s fa0, fa1"),
+            (0x22b59553, "fneg.d fa0, fa1"),
+            (0x20b5a553, "fabs.s fa0, fa1"),
+            (0x22c5a553, "fsgnjx.d fa0, fa1, fa2"),
+            (0x2ac59553, "fmax.d fa0, fa1, fa2"),
+            (0xc0051553, "fcvt.w.s a0, fa0, rtz"),
+            (0xc2154553, "fcvt.wu.d a0, fa0, rmm"),
+            (0xd0057553, "fcvt.s.w fa0, a0"),
+            (0xd2150553, "fcvt.d.wu fa0, a0"),
+            (0x4015f553, "fcvt.s.d fa0, fa1"),
+            (0x42058553, "fcvt.d.s fa0, fa1"),
+            (0xe0050553, "fmv.x.w a0, fa0"),
+            (0xf0050553, "fmv.w.x fa0, a0"),
+            (0xa0b52553, "feq.s a0, fa0, fa1"),
+            (0xa2b51553, "flt.d a0, fa0, fa1"),
+            (0xe2051553, "fclass.d a0, fa0"),
+            (0x00302573, "csrr a0, fcsr"),
+            (0x20c5a533, "sh1add a0, a1, a2"),
+            (0x20c5e533, "sh3add a0, a1, a2"),
+            (0x40c5f533, "andn a0, a1, a2"),
+            (0x40c5c533, "xnor a0, a1, a2"),
+            (0x60059513, "clz a0, a1"),
+            (0x60259513, "cpop a0, a1"),
+            (0x0ac5e533, "max a0, a1, a2"),
+            (0x0ac5d533, "minu a0, a1, a2"),
+            (0x60459513, "sext.b a0, a1"),
+            (0x0805c533, "zext.h a0, a1"),
+            (0x60c59533, "rol a0, a1, a2"),
+            (0x6075d513, "rori a0, a1, 7"),
+            (0x2875d513, "orc.b a0, a1"),
+            (0x6985d513, "rev8 a0, a1"),
+            (0x0ac5b533, "clmulh a0, a1, a2"),
+            (0x0ac5a533, "clmulr a0, a1, a2"),
+            (0x48359513, "bclri a0, a1, 3"),
+            (0x49f5d513, "bexti a0, a1, 31"),
+            (0x68c59533, "binv a0, a1, a2"),
+            (0x28059513, "bseti a0, a1, 0"),
+        ];
+        for (inst, expected) in cases {
+            assert_eq!(
+                disassemble(inst, 0x80000000, Xlen::Rv32),
+                expected,
+                "0x{:08x}",
+                inst
+            );
+        }
+    }
+
+    #[test]
+    fn test_disassemble_rv64() {
+        let cases = [
+            (0xfff5851b, "addiw a0, a1, -1"),
+            (0x0005851b, "sext.w a0, a1"),
+            (0x02859513, "slli a0, a1, 40"),
+            (0x43f5d513, "srai a0, a1, 63"),
+            (0x0035951b, "slliw a0, a1, 3"),
+            (0x40c5d53b, "sraw a0, a1, a2"),
+            (0x40b0053b, "negw a0, a1"),
+            (0x02c5853b, "mulw a0, a1, a2"),
+            (0x02c5f53b, "remuw a0, a1, a2"),
+            (0x00813503, "ld a0, 8(sp)"),
+            (0x0045e503, "lwu a0, 4(a1)"),
+            (0x00113c23, "sd ra, 24(sp)"),
+            (0x1005b52f, "lr.d a0, (a1)"),
+            (0x08c5b52f, "amoswap.d a0, a2, (a1)"),
+            (0x08c5853b, "add.uw a0, a1, a2"),
+            (0x0805853b, "zext.w a0, a1"),
+            (0x20c5c53b, "sh2add.uw a0, a1, a2"),
+            (0x0a15951b, "slli.uw a0, a1, 33"),
+            (0x6005951b, "clzw a0, a1"),
+            (0x0805c53b, "zext.h a0, a1"),
+            (0x6b85d513, "rev8 a0, a1"),
+            (0x6055d51b, "roriw a0, a1, 5"),
+            (0xc2251553, "fcvt.l.d a0, fa0, rtz"),
+            (0xd2357553, "fcvt.d.lu fa0, a0"),
+            (0xe2050553, "fmv.x.d a0, fa0"),
+        ];
+        for (inst, expected) in cases {
+            assert_eq!(
+                disassemble(inst, 0x80000000, Xlen::Rv64),
+                expected,
+                "0x{:08x}",
+                inst
+            );
+        }
+    }
+
+    #[test]
+    fn test_disassemble_pc_relative() {
+        let pc = 0x80000100;
+        let cases = [
+            (0x00b50463, "beq a0, a1, 0x80000108"),
+            (0xfe050ee3, "beqz a0, 0x800000fc"),
+            (0x00059863, "bnez a1, 0x80000110"),
+            (0x00c05463, "blez a2, 0x80000108"),
+            (0x00c04463, "bgtz a2, 0x80000108"),
+            (0xfeb56ce3, "bltu a0, a1, 0x800000f8"),
+            (0x00c0006f, "j 0x8000010c"),
+            (0xff1ff0ef, "jal 0x800000f0"),
+            (0x008002ef, "jal t0, 0x80000108"),
+        ];
+        for (inst, expected) in cases {
+            assert_eq!(
+                disassemble(inst, pc, Xlen::Rv32),
+                expected,
+                "0x{:08x}",
+                inst
+            );
+        }
+    }
+}
@@ -0,0 +1,346 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// ELF32/ELF64 小端 RISC-V 文件解析（仅依赖 std）
+
+use crate::isa::Xlen;
+use crate::symbols::{Symbol, SymbolTable};
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EM_RISCV: u16 = 0xF3;
+
+// 两种 ELF 类别的结构大小和字段偏移
+struct Layout {
+    word: usize, // 地址/偏移字段的字节数
+    header_size: usize,
+    phoff: usize,
+    shoff: usize,
+    phentsize: usize,
+    phnum: usize,
+    shentsize: usize,
+    shnum: usize,
+    ph_size: usize,
+    ph_offset: usize,
+    ph_paddr: usize,
+    ph_filesz: usize,
+    ph_memsz: usize,
+    sh_size: usize,
+    sh_offset: usize,
+    sh_link: usize,
+    sym_size: usize,
+    sym_value: usize,
+    sym_info: usize,
+    sym_shndx: usize,
+}
+
+const ELF32_LAYOUT: Layout = Layout {
+    word: 4,
+    header_size: 52,
+    phoff: 28,
+    shoff: 32,
+    phentsize: 42,
+    phnum: 44,
+    shentsize: 46,
+    shnum: 48,
+    ph_size: 32,
+    ph_offset: 4,
+    ph_paddr: 12,
+    ph_filesz: 16,
+    ph_memsz: 20,
+    sh_size: 40,
+    sh_offset: 16,
+    sh_link: 24,
+    sym_size: 16,
+    sym_value: 4,
+    sym_info: 12,
+    sym_shndx: 14,
+};
+
+const ELF64_LAYOUT: Layout = Layout {
+    word: 8,
+    header_size: 64,
+    phoff: 32,
+    shoff: 40,
+    phentsize: 54,
+    phnum: 56,
+    shentsize: 58,
+    shnum: 60,
+    ph_size: 56,
+    ph_offset: 8,
+    ph_paddr: 24,
+    ph_filesz: 32,
+    ph_memsz: 40,
+    sh_size: 64,
+    sh_offset: 24,
+    sh_link: 40,
+    sym_size: 24,
+    sym_value: 8,
+    sym_info: 4,
+    sym_shndx: 6,
+};
+
+// 段类型
+const PT_LOAD: u32 = 1;
+
+// 节类型
+const SHT_SYMTAB: u32 = 2;
+
+// 符号类型
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xfff1;
+
+pub struct Segment {
+    pub paddr: u32,
+    pub offset: u32,
+    pub filesz: u32,
+    pub memsz: u32,
+}
+
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+    layout: &'static Layout,
+    pub xlen: Xlen,
+    pub entry: u64,
+    pub segments: Vec<Segment>,
+}
+
+// 是否以 ELF 魔数开头
+pub fn is_elf(data: &[u8]) -> bool {
+    data.len() >= 4 && data[..4] == ELF_MAGIC
+}
+
+const TRUNCATED: &str = "ELF file truncated";
+
+// 文件中的偏移和大小不可信：表项 base + index * size + field 溢出时按文件截断处理
+fn offset_of(base: usize, index: usize, size: usize, field: usize) -> Result<usize, &'static str> {
+    index
+        .checked_mul(size)
+        .and_then(|o| o.checked_add(base))
+        .and_then(|o| o.checked_add(field))
+        .ok_or(TRUNCATED)
+}
+
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> Result<[u8; N], &'static str> {
+    offset
+        .checked_add(N)
+        .and_then(|end| data.get(offset..end))
+        .map(|b| b.try_into().unwrap())
+        .ok_or(TRUNCATED)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, &'static str> {
+    read_bytes(data, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, &'static str> {
+    read_bytes(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, &'static str> {
+    read_bytes(data, offset).map(u64::from_le_bytes)
+}
+
+// 物理内存只有 32 位地址空间，ELF64 的地址和大小必须能放进 u32
+fn to_u32(value: u64) -> Result<u32, &'static str> {
+    u32::try_from(value).map_err(|_| "ELF segment exceeds 32-bit physical address space")
+}
+
+impl<'a> ElfFile<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, &'static str> {
+        if data.len() < ELF32_LAYOUT.header_size || !is_elf(data) {
+            return Err("Not an ELF file");
+        }
+        let (layout, xlen) = match data[4] {
+            ELFCLASS32 => (&ELF32_LAYOUT, Xlen::Rv32),
+            ELFCLASS64 => (&ELF64_LAYOUT, Xlen::Rv64),
+            _ => return Err("Unsupported ELF class"),
+        };
+        if data.len() < layout.header_size {
+            return Err("Not an ELF file");
+        }
+        if data[5] != ELFDATA2LSB {
+            return Err("Only little-endian ELF is supported");
+        }
+        if read_u16(data, 18)? != EM_RISCV {
+            return Err("ELF machine is not RISC-V");
+        }
+
+        let mut elf = Self {
+            data,
+            layout,
+            xlen,
+            entry: 0,
+            segments: Vec::new(),
+        };
+        elf.entry = elf.read_word(24)?;
+        let phoff = elf.read_word(layout.phoff)? as usize;
+        let phentsize = read_u16(data, layout.phentsize)? as usize;
+        let phnum = read_u16(data, layout.phnum)? as usize;
+
+        if phnum > 0 && phentsize < layout.ph_size {
+            return Err("Invalid ELF program header size");
+        }
+
+        for i in 0..phnum {
+            let field = |field| offset_of(phoff, i, phentsize, field);
+            if read_u32(data, field(0)?)? != PT_LOAD {
+                continue;
+            }
+
+            let segment = Segment {
+                offset: to_u32(elf.read_word(field(layout.ph_offset)?)?)?,
+                paddr: to_u32(elf.read_word(field(layout.ph_paddr)?)?)?,
+                filesz: to_u32(elf.read_word(field(layout.ph_filesz)?)?)?,
+                memsz: to_u32(elf.read_word(field(layout.ph_memsz)?)?)?,
+            };
+            if segment.filesz > segment.memsz {
+                return Err("ELF segment file size exceeds memory size");
+            }
+            if segment.offset as usize + segment.filesz as usize > data.len() {
+                return Err("ELF segment extends past end of file");
+            }
+            elf.segments.push(segment);
+        }
+
+        Ok(elf)
+    }
+
+    // 按 ELF 类别读取 4 或 8 字节的地址/偏移字段
+    fn read_word(&self, offset: usize) -> Result<u64, &'static str> {
+        match self.layout.word {
+            4 => read_u32(self.data, offset).map(u64::from),
+            _ => read_u64(self.data, offset),
+        }
+    }
+
+    // 解析 .symtab/.strtab，没有符号表时返回空表
+    pub fn symbols(&self) -> Result<SymbolTable, &'static str> {
+        let data = self.data;
+        let layout = self.layout;
+        let shoff = self.read_word(layout.shoff)? as usize;
+        let shentsize = read_u16(data, layout.shentsize)? as usize;
+        let shnum = read_u16(data, layout.shnum)? as usize;
+
+        if shnum == 0 {
+            return Ok(SymbolTable::default());
+        }
+        if shentsize < layout.sh_size {
+            return Err("Invalid ELF section header size");
+        }
+
+        // 返回节在文件中的 (offset, size, link)
+        let section = |index: usize| -> Result<(usize, usize, usize), &'static str> {
+            let field = |field| offset_of(shoff, index, shentsize, field);
+            Ok((
+                self.read_word(field(layout.sh_offset)?)? as usize,
+                self.read_word(field(layout.sh_offset + layout.word)?)? as usize,
+                read_u32(data, field(layout.sh_link)?)? as usize,
+            ))
+        };
+
+        let mut symbols = Vec::new();
+        for i in 0..shnum {
+            if read_u32(data, offset_of(shoff, i, shentsize, 4)?)? != SHT_SYMTAB {
+                continue;
+            }
+            let (sym_off, sym_size, strtab_index) = section(i)?;
+            let (str_off, str_size, _) = section(strtab_index)?;
+            let strtab = str_off
+                .checked_add(str_size)
+                .and_then(|end| data.get(str_off..end))
+                .ok_or("ELF string table extends past end of file")?;
+            let sym_end = sym_off.checked_add(sym_size).ok_or(TRUNCATED)?;
+
+            for sym in (sym_off..sym_end).step_by(layout.sym_size) {
+                let field = |field| offset_of(sym, 0, 0, field);
+                let name_off = read_u32(data, sym)? as usize;
+                let value = self.read_word(field(layout.sym_value)?)?;
+                let size = self.read_word(field(layout.sym_value + layout.word)?)?;
+                let info = read_bytes::<1>(data, field(layout.sym_info)?)?[0];
+                let shndx = read_u16(data, field(layout.sym_shndx)?)?;
+
+                // 只保留已定义的函数、数据对象和普通标号
+                if !matches!(info & 0xf, STT_NOTYPE | STT_OBJECT | STT_FUNC)
+                    || shndx == SHN_UNDEF
+                    || shndx == SHN_ABS
+                {
+                    continue;
+                }
+                let name = strtab
+                    .get(name_off..)
+                    .and_then(|s| s.split(|&b| b == 0).next())
+                    .ok_or("Invalid ELF symbol name")?;
+                // 跳过空名字和 $x/$d 之类的映射符号，以及 .L 局部标号
+                if name.is_empty() || name[0] == b'$' || name.starts_with(b".L") {
+                    continue;
+                }
+
+                symbols.push(Symbol {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    addr: value,
+                    size,
+                });
+            }
+        }
+
+        Ok(SymbolTable::new(symbols))
+    }
+
+    // 段在文件中的内容（不含 BSS 部分）
+    pub fn segment_data(&self, segment: &Segment) -> &'a [u8] {
+        let start = segment.offset as usize;
+        &self.data[start..start + segment.filesz as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 只有 ELF64 头部的文件，程序头和节头的位置由参数给出
+    fn elf64_header(phoff: u64, shoff: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 64];
+        data[..4].copy_from_slice(&ELF_MAGIC);
+        data[4] = ELFCLASS64;
+        data[5] = ELFDATA2LSB;
+        data[18..20].copy_from_slice(&EM_RISCV.to_le_bytes());
+        data[32..40].copy_from_slice(&phoff.to_le_bytes());
+        data[40..48].copy_from_slice(&shoff.to_le_bytes());
+        data[54..56].copy_from_slice(&56u16.to_le_bytes()); // e_phentsize
+        data[56..58].copy_from_slice(&1u16.to_le_bytes()); // e_phnum
+        data[58..60].copy_from_slice(&64u16.to_le_bytes()); // e_shentsize
+        data[60..62].copy_from_slice(&1u16.to_le_bytes()); // e_shnum
+        data
+    }
+
+    #[test]
+    fn test_overflowing_offsets() {
+        let data = elf64_header(u64::MAX, 0);
+        assert_eq!(ElfFile::parse(&data).err(), Some("ELF file truncated"));
+
+        let mut data = elf64_header(0, u64::MAX - 8);
+        data[56..58].copy_from_slice(&0u16.to_le_bytes());
+        let elf = ElfFile::parse(&data).unwrap();
+        assert_eq!(elf.symbols().err(), Some("ELF file truncated"));
+    }
+}
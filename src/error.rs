@@ -0,0 +1,90 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// 模拟器返回给主机的错误，携带出错时的上下文，嵌入方可以按变体处理
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmuError {
+    // 解码器不知道指令地址，单独解码时 pc 为 0
+    IllegalInstruction { pc: u64, word: u32 },
+    MisalignedAccess { addr: u64, size: usize },
+    // 不存在、只读、特权级不足或被 TVM 拦截的 CSR 访问
+    IllegalCsr { csr: u16 },
+    AccessFault { addr: u64 },
+    PageFault { addr: u64 },
+    // offset 相对于设备基地址
+    DeviceError { device: String, offset: usize },
+    // 设备大小为零或地址范围溢出
+    InvalidDeviceRange { base: usize, size: usize },
+    // 与已挂载的设备或 RAM/ROM 区域重叠
+    DeviceOverlap { base: usize, size: usize },
+    Breakpoint { pc: u64 },
+    // 关闭了主机系统调用且没有安装陷阱处理程序
+    UnhandledEcall { pc: u64 },
+    Exit { code: u32 },
+    DebuggerQuit,
+}
+
+impl EmuError {
+    pub(crate) fn illegal(word: u32) -> Self {
+        EmuError::IllegalInstruction { pc: 0, word }
+    }
+
+    pub fn device(device: &str, offset: usize) -> Self {
+        EmuError::DeviceError {
+            device: device.to_string(),
+            offset,
+        }
+    }
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmuError::IllegalInstruction { pc, word } => {
+                write!(f, "Illegal instruction 0x{:08x} at 0x{:08x}", word, pc)
+            }
+            EmuError::MisalignedAccess { addr, size } => {
+                write!(f, "Misaligned {}-byte access at 0x{:08x}", size, addr)
+            }
+            EmuError::IllegalCsr { csr } => write!(f, "Illegal access to CSR 0x{:03x}", csr),
+            EmuError::AccessFault { addr } => write!(f, "Access fault at 0x{:08x}", addr),
+            EmuError::PageFault { addr } => write!(f, "Page fault at 0x{:08x}", addr),
+            EmuError::DeviceError { device, offset } => {
+                write!(f, "Device error: {} at offset 0x{:x}", device, offset)
+            }
+            EmuError::InvalidDeviceRange { base, size } => {
+                write!(f, "Invalid device range 0x{:08x} (size 0x{:x})", base, size)
+            }
+            EmuError::DeviceOverlap { base, size } => write!(
+                f,
+                "Device range 0x{:08x} (size 0x{:x}) overlaps another device",
+                base, size
+            ),
+            EmuError::Breakpoint { pc } => write!(f, "Breakpoint at 0x{:08x}", pc),
+            EmuError::UnhandledEcall { pc } => {
+                write!(f, "ECALL at 0x{:08x} with no trap handler installed", pc)
+            }
+            EmuError::Exit { code } => write!(f, "Program exit with code {}", code),
+            EmuError::DebuggerQuit => write!(f, "Debugger quit"),
+        }
+    }
+}
+
+impl std::error::Error for EmuError {}
@@ -0,0 +1,773 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// F/D 扩展的浮点运算
+//
+// 主机浮点运算只支持就近舍入，且不报告异常标志，因此这里用整数运算实现：
+// 先把操作数拆成 符号 / 指数 / 尾数，得到精确结果（或带粘滞位的结果），
+// 再按舍入模式统一舍入并打包，同时产生 fflags。
+// 操作数和结果都以原始位模式传递，单精度数位于低 32 位。
+
+// fflags 异常标志
+pub const FFLAGS_NX: u32 = 1 << 0; // 不精确
+pub const FFLAGS_UF: u32 = 1 << 1; // 下溢
+pub const FFLAGS_OF: u32 = 1 << 2; // 上溢
+pub const FFLAGS_DZ: u32 = 1 << 3; // 除以零
+pub const FFLAGS_NV: u32 = 1 << 4; // 无效操作
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FpFormat {
+    Single,
+    Double,
+}
+
+impl FpFormat {
+    fn exp_bits(self) -> u32 {
+        match self {
+            FpFormat::Single => 8,
+            FpFormat::Double => 11,
+        }
+    }
+
+    fn frac_bits(self) -> u32 {
+        match self {
+            FpFormat::Single => 23,
+            FpFormat::Double => 52,
+        }
+    }
+
+    fn bias(self) -> i32 {
+        (1 << (self.exp_bits() - 1)) - 1
+    }
+
+    fn exp_max(self) -> u64 {
+        (1 << self.exp_bits()) - 1
+    }
+
+    fn frac_mask(self) -> u64 {
+        (1 << self.frac_bits()) - 1
+    }
+
+    fn sign_bit(self) -> u64 {
+        1 << (self.exp_bits() + self.frac_bits())
+    }
+
+    // RISC-V 规定所有 NaN 结果都使用规范 NaN，不传播载荷
+    pub fn canonical_nan(self) -> u64 {
+        (self.exp_max() << self.frac_bits()) | (1 << (self.frac_bits() - 1))
+    }
+
+    fn zero(self, sign: bool) -> u64 {
+        if sign {
+            self.sign_bit()
+        } else {
+            0
+        }
+    }
+
+    fn infinity(self, sign: bool) -> u64 {
+        self.zero(sign) | (self.exp_max() << self.frac_bits())
+    }
+
+    fn max_finite(self, sign: bool) -> u64 {
+        self.zero(sign) | ((self.exp_max() - 1) << self.frac_bits()) | self.frac_mask()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RoundingMode {
+    Rne, // 就近舍入，向偶数
+    Rtz, // 向零舍入
+    Rdn, // 向下舍入
+    Rup, // 向上舍入
+    Rmm, // 就近舍入，远离零
+}
+
+impl RoundingMode {
+    // 指令 rm 字段或 frm 的编码，5/6 保留，7 (DYN) 由调用方解析
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0 => Some(RoundingMode::Rne),
+            1 => Some(RoundingMode::Rtz),
+            2 => Some(RoundingMode::Rdn),
+            3 => Some(RoundingMode::Rup),
+            4 => Some(RoundingMode::Rmm),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Class {
+    Zero,
+    Finite,
+    Inf,
+    QuietNan,
+    SignalingNan,
+}
+
+// 拆分后的浮点数，有限值为 mant * 2^exp
+#[derive(Debug, Copy, Clone)]
+struct Unpacked {
+    class: Class,
+    sign: bool,
+    exp: i32,
+    mant: u128,
+}
+
+impl Unpacked {
+    fn is_nan(&self) -> bool {
+        matches!(self.class, Class::QuietNan | Class::SignalingNan)
+    }
+}
+
+fn unpack(fmt: FpFormat, bits: u64) -> Unpacked {
+    let sign = bits & fmt.sign_bit() != 0;
+    let exp = (bits >> fmt.frac_bits()) & fmt.exp_max();
+    let frac = bits & fmt.frac_mask();
+    let min_exp = 1 - fmt.bias() - fmt.frac_bits() as i32;
+
+    let (class, exp, mant) = if exp == fmt.exp_max() {
+        if frac == 0 {
+            (Class::Inf, 0, 0)
+        } else if frac & (1 << (fmt.frac_bits() - 1)) != 0 {
+            (Class::QuietNan, 0, 0)
+        } else {
+            (Class::SignalingNan, 0, 0)
+        }
+    } else if exp == 0 {
+        if frac == 0 {
+            (Class::Zero, 0, 0)
+        } else {
+            (Class::Finite, min_exp, frac as u128) // 非规格化数
+        }
+    } else {
+        let mant = frac | (1 << fmt.frac_bits());
+        (Class::Finite, exp as i32 - 1 + min_exp, mant as u128)
+    };
+
+    Unpacked {
+        class,
+        sign,
+        exp,
+        mant,
+    }
+}
+
+// 右移并把移出的位合并到最低位（粘滞位）
+fn shift_right_jam(value: u128, shift: u32) -> u128 {
+    if shift == 0 {
+        value
+    } else if shift >= 128 {
+        (value != 0) as u128
+    } else {
+        (value >> shift) | ((value & ((1 << shift) - 1)) != 0) as u128
+    }
+}
+
+// 把尾数的最高位移到第 msb 位
+fn normalize(exp: i32, mant: u128, msb: u32) -> (i32, u128) {
+    let top = 127 - mant.leading_zeros();
+    if top < msb {
+        (exp - (msb - top) as i32, mant << (msb - top))
+    } else {
+        (exp + (top - msb) as i32, shift_right_jam(mant, top - msb))
+    }
+}
+
+// 把 mant * 2^exp 舍入为 2^q 的整数倍，返回 (倍数, 是否不精确)
+fn round_at(sign: bool, exp: i32, mant: u128, q: i32, rm: RoundingMode) -> (u128, bool) {
+    // 多保留两位：舍入位和粘滞位
+    let shift = q - exp - 2;
+    let bits = if shift > 0 {
+        shift_right_jam(mant, shift as u32)
+    } else {
+        mant << (-shift) as u32
+    };
+    let round = bits & 0x3;
+    let m = bits >> 2;
+    let inexact = round != 0;
+
+    let increment = match rm {
+        RoundingMode::Rne => round > 2 || (round == 2 && m & 1 == 1),
+        RoundingMode::Rtz => false,
+        RoundingMode::Rdn => inexact && sign,
+        RoundingMode::Rup => inexact && !sign,
+        RoundingMode::Rmm => round >= 2,
+    };
+    (m + increment as u128, inexact)
+}
+
+// 按舍入模式把 (-1)^sign * mant * 2^exp 打包为目标格式
+fn round_pack(fmt: FpFormat, sign: bool, exp: i32, mant: u128, rm: RoundingMode) -> (u64, u32) {
+    if mant == 0 {
+        return (fmt.zero(sign), 0);
+    }
+
+    let precision = fmt.frac_bits() as i32 + 1;
+    let emin = 1 - fmt.bias();
+    // 最高位的指数，以及结果最低位的指数（非规格化数精度受限）
+    let top = exp + (127 - mant.leading_zeros()) as i32;
+    let mut q = (top - (precision - 1)).max(emin - (precision - 1));
+
+    let (mut m, inexact) = round_at(sign, exp, mant, q, rm);
+    if m >> precision != 0 {
+        // 舍入进位导致尾数溢出一位
+        m >>= 1;
+        q += 1;
+    }
+
+    let mut flags = if inexact { FFLAGS_NX } else { 0 };
+    // RISC-V 在舍入后检测下溢：按无限指数范围舍入后仍小于最小规格化数
+    if inexact && top < emin {
+        let (unbounded, _) = round_at(sign, exp, mant, top - (precision - 1), rm);
+        if top < emin - 1 || unbounded >> precision == 0 {
+            flags |= FFLAGS_UF;
+        }
+    }
+
+    if m >> (precision - 1) == 0 {
+        // 非规格化数
+        return (fmt.zero(sign) | m as u64, flags);
+    }
+
+    let biased = q + (precision - 1) + fmt.bias();
+    if biased >= fmt.exp_max() as i32 {
+        let to_infinity = match rm {
+            RoundingMode::Rne | RoundingMode::Rmm => true,
+            RoundingMode::Rtz => false,
+            RoundingMode::Rdn => sign,
+            RoundingMode::Rup => !sign,
+        };
+        let bits = if to_infinity {
+            fmt.infinity(sign)
+        } else {
+            fmt.max_finite(sign)
+        };
+        return (bits, flags | FFLAGS_OF | FFLAGS_NX);
+    }
+
+    let bits = fmt.zero(sign) | ((biased as u64) << fmt.frac_bits()) | (m as u64 & fmt.frac_mask());
+    (bits, flags)
+}
+
+// 存在 NaN 操作数时的结果：规范 NaN，遇到信号 NaN 时置 NV
+fn propagate_nan(fmt: FpFormat, operands: &[Unpacked]) -> Option<(u64, u32)> {
+    if !operands.iter().any(Unpacked::is_nan) {
+        return None;
+    }
+    let flags = if operands.iter().any(|v| v.class == Class::SignalingNan) {
+        FFLAGS_NV
+    } else {
+        0
+    };
+    Some((fmt.canonical_nan(), flags))
+}
+
+fn invalid(fmt: FpFormat) -> (u64, u32) {
+    (fmt.canonical_nan(), FFLAGS_NV)
+}
+
+// 两个非零有限值相加
+fn add_finite(fmt: FpFormat, a: Unpacked, b: Unpacked, rm: RoundingMode) -> (u64, u32) {
+    // 统一规格化到第 125 位，留出进位空间，对齐时移出的位只影响粘滞位
+    let (exp_a, mant_a) = normalize(a.exp, a.mant, 125);
+    let (exp_b, mant_b) = normalize(b.exp, b.mant, 125);
+
+    let ((sign, exp, big), small) = if (exp_a, mant_a) >= (exp_b, mant_b) {
+        (
+            (a.sign, exp_a, mant_a),
+            shift_right_jam(mant_b, (exp_a - exp_b) as u32),
+        )
+    } else {
+        (
+            (b.sign, exp_b, mant_b),
+            shift_right_jam(mant_a, (exp_b - exp_a) as u32),
+        )
+    };
+
+    if a.sign == b.sign {
+        return round_pack(fmt, sign, exp, big + small, rm);
+    }
+    let diff = big - small;
+    if diff == 0 {
+        // x - x 的结果为 +0，向下舍入时为 -0
+        return (fmt.zero(rm == RoundingMode::Rdn), 0);
+    }
+    round_pack(fmt, sign, exp, diff, rm)
+}
+
+pub fn add(fmt: FpFormat, a: u64, b: u64, rm: RoundingMode) -> (u64, u32) {
+    let (va, vb) = (unpack(fmt, a), unpack(fmt, b));
+    if let Some(nan) = propagate_nan(fmt, &[va, vb]) {
+        return nan;
+    }
+
+    match (va.class, vb.class) {
+        (Class::Inf, Class::Inf) if va.sign != vb.sign => invalid(fmt),
+        (Class::Inf, _) => (fmt.infinity(va.sign), 0),
+        (_, Class::Inf) => (fmt.infinity(vb.sign), 0),
+        (Class::Zero, Class::Zero) => {
+            let sign = if va.sign == vb.sign {
+                va.sign
+            } else {
+                rm == RoundingMode::Rdn
+            };
+            (fmt.zero(sign), 0)
+        }
+        (Class::Zero, _) => (b, 0),
+        (_, Class::Zero) => (a, 0),
+        _ => add_finite(fmt, va, vb, rm),
+    }
+}
+
+pub fn sub(fmt: FpFormat, a: u64, b: u64, rm: RoundingMode) -> (u64, u32) {
+    add(fmt, a, b ^ fmt.sign_bit(), rm)
+}
+
+pub fn mul(fmt: FpFormat, a: u64, b: u64, rm: RoundingMode) -> (u64, u32) {
+    let (va, vb) = (unpack(fmt, a), unpack(fmt, b));
+    if let Some(nan) = propagate_nan(fmt, &[va, vb]) {
+        return nan;
+    }
+
+    let sign = va.sign ^ vb.sign;
+    match (va.class, vb.class) {
+        (Class::Inf, Class::Zero) | (Class::Zero, Class::Inf) => invalid(fmt),
+        (Class::Inf, _) | (_, Class::Inf) => (fmt.infinity(sign), 0),
+        (Class::Zero, _) | (_, Class::Zero) => (fmt.zero(sign), 0),
+        _ => round_pack(fmt, sign, va.exp + vb.exp, va.mant * vb.mant, rm),
+    }
+}
+
+// 融合乘加：(-1)^negate_product * a * b + (-1)^negate_addend * c，只舍入一次
+pub fn fused_mul_add(
+    fmt: FpFormat,
+    a: u64,
+    b: u64,
+    c: u64,
+    negate_product: bool,
+    negate_addend: bool,
+    rm: RoundingMode,
+) -> (u64, u32) {
+    let (va, vb, vc) = (unpack(fmt, a), unpack(fmt, b), unpack(fmt, c));
+
+    // 即使加数是静默 NaN，∞ × 0 也必须置 NV
+    let inf_times_zero = matches!(
+        (va.class, vb.class),
+        (Class::Inf, Class::Zero) | (Class::Zero, Class::Inf)
+    );
+    if inf_times_zero {
+        return invalid(fmt);
+    }
+    if let Some(nan) = propagate_nan(fmt, &[va, vb, vc]) {
+        return nan;
+    }
+
+    let product_sign = va.sign ^ vb.sign ^ negate_product;
+    let addend_sign = vc.sign ^ negate_addend;
+    let product_inf = va.class == Class::Inf || vb.class == Class::Inf;
+    let product_zero = va.class == Class::Zero || vb.class == Class::Zero;
+
+    if product_inf {
+        if vc.class == Class::Inf && addend_sign != product_sign {
+            return invalid(fmt);
+        }
+        return (fmt.infinity(product_sign), 0);
+    }
+    if vc.class == Class::Inf {
+        return (fmt.infinity(addend_sign), 0);
+    }
+    if product_zero {
+        if vc.class == Class::Zero {
+            let sign = if product_sign == addend_sign {
+                product_sign
+            } else {
+                rm == RoundingMode::Rdn
+            };
+            return (fmt.zero(sign), 0);
+        }
+        let c = if negate_addend { c ^ fmt.sign_bit() } else { c };
+        return (c, 0);
+    }
+
+    // 乘积是精确的，与加数相加后只舍入一次
+    let product = Unpacked {
+        class: Class::Finite,
+        sign: product_sign,
+        exp: va.exp + vb.exp,
+        mant: va.mant * vb.mant,
+    };
+    if vc.class == Class::Zero {
+        return round_pack(fmt, product.sign, product.exp, product.mant, rm);
+    }
+    let addend = Unpacked {
+        sign: addend_sign,
+        ..vc
+    };
+    add_finite(fmt, product, addend, rm)
+}
+
+pub fn div(fmt: FpFormat, a: u64, b: u64, rm: RoundingMode) -> (u64, u32) {
+    let (va, vb) = (unpack(fmt, a), unpack(fmt, b));
+    if let Some(nan) = propagate_nan(fmt, &[va, vb]) {
+        return nan;
+    }
+
+    let sign = va.sign ^ vb.sign;
+    match (va.class, vb.class) {
+        (Class::Inf, Class::Inf) | (Class::Zero, Class::Zero) => invalid(fmt),
+        (Class::Inf, _) => (fmt.infinity(sign), 0),
+        (_, Class::Inf) => (fmt.zero(sign), 0),
+        (_, Class::Zero) => (fmt.infinity(sign), FFLAGS_DZ),
+        (Class::Zero, _) => (fmt.zero(sign), 0),
+        _ => {
+            // 商至少有 63 位有效位，余数并入粘滞位
+            let (exp_a, mant_a) = normalize(va.exp, va.mant, 125);
+            let (exp_b, mant_b) = normalize(vb.exp, vb.mant, 62);
+            let quotient = mant_a / mant_b;
+            let sticky = (mant_a % mant_b != 0) as u128;
+            round_pack(fmt, sign, exp_a - exp_b, quotient | sticky, rm)
+        }
+    }
+}
+
+pub fn sqrt(fmt: FpFormat, a: u64, rm: RoundingMode) -> (u64, u32) {
+    let va = unpack(fmt, a);
+    if let Some(nan) = propagate_nan(fmt, &[va]) {
+        return nan;
+    }
+
+    match va.class {
+        Class::Zero => (a, 0), // sqrt(-0) = -0
+        _ if va.sign => invalid(fmt),
+        Class::Inf => (a, 0),
+        _ => {
+            // 指数调整为偶数后对尾数开整数平方根
+            let (mut exp, mut mant) = normalize(va.exp, va.mant, 124);
+            if exp % 2 != 0 {
+                exp -= 1;
+                mant <<= 1;
+            }
+            let root = mant.isqrt();
+            let sticky = (root * root != mant) as u128;
+            round_pack(fmt, false, exp / 2, root | sticky, rm)
+        }
+    }
+}
+
+// IEEE 754-2019 minimumNumber/maximumNumber，-0 小于 +0
+pub fn min_max(fmt: FpFormat, a: u64, b: u64, max: bool) -> (u64, u32) {
+    let (va, vb) = (unpack(fmt, a), unpack(fmt, b));
+    let flags = if va.class == Class::SignalingNan || vb.class == Class::SignalingNan {
+        FFLAGS_NV
+    } else {
+        0
+    };
+
+    let result = match (va.is_nan(), vb.is_nan()) {
+        (true, true) => fmt.canonical_nan(),
+        (true, false) => b,
+        (false, true) => a,
+        _ => {
+            let a_less = total_key(fmt, a) < total_key(fmt, b);
+            if a_less != max {
+                a
+            } else {
+                b
+            }
+        }
+    };
+    (result, flags)
+}
+
+// 非 NaN 值的排序键，-0 排在 +0 之前
+fn total_key(fmt: FpFormat, bits: u64) -> i128 {
+    let magnitude = (bits & !fmt.sign_bit()) as i128;
+    if bits & fmt.sign_bit() != 0 {
+        -magnitude - 1
+    } else {
+        magnitude
+    }
+}
+
+// 非 NaN 值的比较键，-0 等于 +0
+fn compare_key(fmt: FpFormat, bits: u64) -> i128 {
+    let magnitude = (bits & !fmt.sign_bit()) as i128;
+    if bits & fmt.sign_bit() != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+// FEQ 是静默比较，只有信号 NaN 置 NV
+pub fn eq(fmt: FpFormat, a: u64, b: u64) -> (bool, u32) {
+    let (va, vb) = (unpack(fmt, a), unpack(fmt, b));
+    if let Some((_, flags)) = propagate_nan(fmt, &[va, vb]) {
+        return (false, flags);
+    }
+    (compare_key(fmt, a) == compare_key(fmt, b), 0)
+}
+
+// FLT/FLE 是信号比较，任何 NaN 都置 NV
+pub fn lt(fmt: FpFormat, a: u64, b: u64) -> (bool, u32) {
+    let (va, vb) = (unpack(fmt, a), unpack(fmt, b));
+    if va.is_nan() || vb.is_nan() {
+        return (false, FFLAGS_NV);
+    }
+    (compare_key(fmt, a) < compare_key(fmt, b), 0)
+}
+
+pub fn le(fmt: FpFormat, a: u64, b: u64) -> (bool, u32) {
+    let (va, vb) = (unpack(fmt, a), unpack(fmt, b));
+    if va.is_nan() || vb.is_nan() {
+        return (false, FFLAGS_NV);
+    }
+    (compare_key(fmt, a) <= compare_key(fmt, b), 0)
+}
+
+// FCLASS：返回 10 位独热分类掩码
+pub fn classify(fmt: FpFormat, a: u64) -> u32 {
+    let v = unpack(fmt, a);
+    let subnormal = (a >> fmt.frac_bits()) & fmt.exp_max() == 0;
+    let bit = match (v.class, v.sign) {
+        (Class::Inf, true) => 0,
+        (Class::Finite, true) if !subnormal => 1,
+        (Class::Finite, true) => 2,
+        (Class::Zero, true) => 3,
+        (Class::Zero, false) => 4,
+        (Class::Finite, false) if subnormal => 5,
+        (Class::Finite, false) => 6,
+        (Class::Inf, false) => 7,
+        (Class::SignalingNan, _) => 8,
+        (Class::QuietNan, _) => 9,
+    };
+    1 << bit
+}
+
+// 符号注入：FSGNJ / FSGNJN / FSGNJX
+pub fn sign_inject(fmt: FpFormat, a: u64, b: u64, negate: bool, xor: bool) -> u64 {
+    let sign_bit = fmt.sign_bit();
+    let sign = if xor {
+        (a ^ b) & sign_bit
+    } else if negate {
+        !b & sign_bit
+    } else {
+        b & sign_bit
+    };
+    (a & !sign_bit) | sign
+}
+
+// 浮点格式之间的转换（FCVT.S.D / FCVT.D.S）
+pub fn convert(from: FpFormat, to: FpFormat, a: u64, rm: RoundingMode) -> (u64, u32) {
+    let v = unpack(from, a);
+    if let Some(nan) = propagate_nan(to, &[v]) {
+        return nan;
+    }
+    match v.class {
+        Class::Zero => (to.zero(v.sign), 0),
+        Class::Inf => (to.infinity(v.sign), 0),
+        _ => round_pack(to, v.sign, v.exp, v.mant, rm),
+    }
+}
+
+// 浮点数转换为 width 位整数，越界和 NaN 时饱和并置 NV
+// 32 位结果按 RISC-V 约定符号扩展到 64 位
+pub fn to_int(fmt: FpFormat, a: u64, signed: bool, width: u32, rm: RoundingMode) -> (u64, u32) {
+    let v = unpack(fmt, a);
+    let (min, max): (i128, i128) = if signed {
+        (-(1 << (width - 1)), (1 << (width - 1)) - 1)
+    } else {
+        (0, (1 << width) - 1)
+    };
+
+    let (value, inexact) = match v.class {
+        Class::QuietNan | Class::SignalingNan => (max + 1, false),
+        Class::Inf if v.sign => (min - 1, false),
+        Class::Inf => (max + 1, false),
+        Class::Zero => (0, false),
+        Class::Finite => {
+            let (magnitude, inexact) = if v.exp >= 0 {
+                // 尾数不超过 53 位，左移超过 70 位必然越界
+                (v.mant << v.exp.min(70), false)
+            } else {
+                round_at(v.sign, v.exp, v.mant, 0, rm)
+            };
+            let magnitude = magnitude as i128;
+            (if v.sign { -magnitude } else { magnitude }, inexact)
+        }
+    };
+
+    let (value, flags) = if value > max {
+        (max, FFLAGS_NV)
+    } else if value < min {
+        (min, FFLAGS_NV)
+    } else if inexact {
+        (value, FFLAGS_NX)
+    } else {
+        (value, 0)
+    };
+
+    let bits = if width == 32 {
+        value as i32 as i64 as u64
+    } else {
+        value as u64
+    };
+    (bits, flags)
+}
+
+// width 位整数转换为浮点数
+pub fn from_int(
+    fmt: FpFormat,
+    value: u64,
+    signed: bool,
+    width: u32,
+    rm: RoundingMode,
+) -> (u64, u32) {
+    let value = if width == 32 {
+        value & 0xffffffff
+    } else {
+        value
+    };
+    let (sign, magnitude) = if signed {
+        let value = ((value << (64 - width)) as i64) >> (64 - width);
+        (value < 0, value.unsigned_abs())
+    } else {
+        (false, value)
+    };
+    round_pack(fmt, sign, 0, magnitude as u128, rm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const S: FpFormat = FpFormat::Single;
+    const D: FpFormat = FpFormat::Double;
+
+    fn f(value: f32) -> u64 {
+        value.to_bits() as u64
+    }
+
+    fn d(value: f64) -> u64 {
+        value.to_bits()
+    }
+
+    #[test]
+    fn test_arithmetic_matches_host() {
+        let rne = RoundingMode::Rne;
+        assert_eq!(add(S, f(1.5), f(2.25), rne), (f(3.75), 0));
+        assert_eq!(sub(D, d(1.0), d(1.0), rne), (d(0.0), 0));
+        assert_eq!(mul(S, f(3.0), f(-0.5), rne), (f(-1.5), 0));
+        assert_eq!(div(D, d(1.0), d(3.0), rne), (d(1.0 / 3.0), FFLAGS_NX));
+        assert_eq!(sqrt(D, d(2.0), rne), (d(2f64.sqrt()), FFLAGS_NX));
+        assert_eq!(sqrt(S, f(-0.0), rne), (f(-0.0), 0));
+        assert_eq!(
+            fused_mul_add(D, d(0.1), d(10.0), d(-1.0), false, false, rne).0,
+            d(0.1f64.mul_add(10.0, -1.0))
+        );
+        assert_eq!(convert(D, S, d(0.1), rne), (f(0.1), FFLAGS_NX));
+        assert_eq!(convert(S, D, f(0.1), rne), (d(0.1f32 as f64), 0));
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        let third = |rm| div(S, f(1.0), f(3.0), rm).0;
+        // 1/3 的单精度就近舍入结果向上进位
+        assert_eq!(third(RoundingMode::Rne), f(1.0 / 3.0));
+        assert_eq!(third(RoundingMode::Rup), f(1.0 / 3.0));
+        assert_eq!(third(RoundingMode::Rtz), f(1.0 / 3.0) - 1);
+        assert_eq!(third(RoundingMode::Rdn), f(1.0 / 3.0) - 1);
+        assert_eq!(div(S, f(-1.0), f(3.0), RoundingMode::Rdn).0, f(-1.0 / 3.0));
+        assert_eq!(
+            div(S, f(-1.0), f(3.0), RoundingMode::Rup).0,
+            f(-1.0 / 3.0) - 1
+        );
+
+        // 2.5 的舍入：向偶数为 2，远离零为 3
+        assert_eq!(
+            to_int(S, f(2.5), true, 32, RoundingMode::Rne),
+            (2, FFLAGS_NX)
+        );
+        assert_eq!(
+            to_int(S, f(2.5), true, 32, RoundingMode::Rmm),
+            (3, FFLAGS_NX)
+        );
+        assert_eq!(
+            to_int(S, f(-2.5), true, 32, RoundingMode::Rdn).0,
+            (-3i64) as u64
+        );
+        assert_eq!(
+            to_int(S, f(-2.5), true, 32, RoundingMode::Rtz).0,
+            (-2i64) as u64
+        );
+
+        // 上溢：向零舍入得到最大有限值
+        let (bits, flags) = mul(S, f(f32::MAX), f(2.0), RoundingMode::Rtz);
+        assert_eq!(bits, f(f32::MAX));
+        assert_eq!(flags, FFLAGS_OF | FFLAGS_NX);
+        assert_eq!(
+            mul(S, f(f32::MAX), f(2.0), RoundingMode::Rne).0,
+            f(f32::INFINITY)
+        );
+    }
+
+    #[test]
+    fn test_exception_flags() {
+        let rne = RoundingMode::Rne;
+        assert_eq!(div(S, f(1.0), f(0.0), rne), (f(f32::INFINITY), FFLAGS_DZ));
+        assert_eq!(div(S, f(0.0), f(0.0), rne), (S.canonical_nan(), FFLAGS_NV));
+        assert_eq!(sqrt(D, d(-1.0), rne), (D.canonical_nan(), FFLAGS_NV));
+        let (_, flags) = mul(S, f(f32::MIN_POSITIVE), f(0.5), rne);
+        assert_eq!(flags, 0); // 精确的非规格化结果不算下溢
+        let (_, flags) = mul(S, f(f32::MIN_POSITIVE), f(0.3), rne);
+        assert_eq!(flags, FFLAGS_UF | FFLAGS_NX);
+
+        // 信号 NaN 置 NV，静默 NaN 只对 FLT/FLE 置 NV
+        let snan = 0x7f800001;
+        assert_eq!(add(S, snan, f(1.0), rne), (S.canonical_nan(), FFLAGS_NV));
+        assert_eq!(eq(S, S.canonical_nan(), f(1.0)), (false, 0));
+        assert_eq!(lt(S, S.canonical_nan(), f(1.0)), (false, FFLAGS_NV));
+        assert_eq!(eq(S, f(0.0), f(-0.0)), (true, 0));
+
+        // 越界转换饱和
+        assert_eq!(to_int(S, f(3e9), true, 32, rne), (0x7fffffff, FFLAGS_NV));
+        assert_eq!(to_int(S, f(-1.0), false, 32, rne), (0, FFLAGS_NV));
+        assert_eq!(to_int(S, f(-0.25), false, 32, rne), (0, FFLAGS_NX));
+        assert_eq!(to_int(D, D.canonical_nan(), false, 32, rne).0, u64::MAX);
+        assert_eq!(from_int(S, 0xffffffff, true, 32, rne), (f(-1.0), 0));
+        assert_eq!(
+            from_int(S, 0xffffffff, false, 32, rne),
+            (f(4294967296.0), FFLAGS_NX)
+        );
+    }
+
+    #[test]
+    fn test_min_max_and_classify() {
+        assert_eq!(min_max(S, f(-0.0), f(0.0), false), (f(-0.0), 0));
+        assert_eq!(min_max(S, f(-0.0), f(0.0), true), (f(0.0), 0));
+        assert_eq!(min_max(D, D.canonical_nan(), d(2.0), false), (d(2.0), 0));
+        assert_eq!(classify(S, f(f32::NEG_INFINITY)), 1 << 0);
+        assert_eq!(classify(S, f(-1e-40)), 1 << 2);
+        assert_eq!(classify(D, d(1.0)), 1 << 6);
+        assert_eq!(classify(S, 0x7f800001), 1 << 8);
+        assert_eq!(classify(S, S.canonical_nan()), 1 << 9);
+        assert_eq!(sign_inject(S, f(1.0), f(-2.0), false, false), f(-1.0));
+        assert_eq!(sign_inject(S, f(-1.0), f(-2.0), false, true), f(1.0));
+    }
+}
@@ -0,0 +1,566 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// GDB 远程串行协议（RSP）服务端，供 riscv32-unknown-elf-gdb 通过 TCP 连接调试
+
+use crate::cpu::Cpu;
+use crate::debugger::WatchKind;
+use crate::error::EmuError;
+use crate::isa::Xlen;
+use crate::register::ABI_NAMES;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// g/G 包中的寄存器个数：x0-x31 和 pc
+const NUM_REGS: usize = 33;
+const PC_REGNUM: usize = 32;
+
+// 连续执行时每隔多少条指令检查一次 Ctrl-C
+const INTERRUPT_CHECK_INTERVAL: u32 = 4096;
+
+// 信号编号
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+pub struct GdbStub {
+    stream: TcpStream,
+    no_ack: bool,
+}
+
+fn target_xml(xlen: Xlen) -> String {
+    let bits = xlen.bits();
+    let mut xml = format!(
+        "<?xml version=\"1.0\"?>\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\">\
+         <architecture>riscv:rv{}</architecture>\
+         <feature name=\"org.gnu.gdb.riscv.cpu\">",
+        bits
+    );
+    for (i, name) in ABI_NAMES.iter().enumerate() {
+        let reg_type = match i {
+            1 => "code_ptr",
+            2 | 8 => "data_ptr",
+            _ => "int",
+        };
+        xml.push_str(&format!(
+            "<reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>",
+            name, bits, reg_type, i
+        ));
+    }
+    xml.push_str(&format!(
+        "<reg name=\"pc\" bitsize=\"{}\" type=\"code_ptr\" regnum=\"{}\"/>",
+        bits, PC_REGNUM
+    ));
+    xml.push_str("</feature></target>");
+    xml
+}
+
+// 寄存器按 XLEN 宽度的小端字节序编码
+fn encode_reg(value: u64, xlen: Xlen) -> String {
+    value.to_le_bytes()[..xlen.bits() as usize / 8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn decode_reg(hex: &str, xlen: Xlen) -> Option<u64> {
+    let bytes = decode_hex(hex)?;
+    if bytes.len() != xlen.bits() as usize / 8 {
+        return None;
+    }
+    Some(bytes.iter().rev().fold(0, |acc, &b| acc << 8 | b as u64))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// 解析 "addr,len" 形式的参数
+fn parse_addr_len(args: &str) -> Option<(u64, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((
+        u64::from_str_radix(addr, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+impl GdbStub {
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("[GDB] Waiting for connection on 127.0.0.1:{}", port);
+        let (stream, addr) = listener.accept()?;
+        println!("[GDB] Connected from {}", addr);
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            no_ack: false,
+        })
+    }
+
+    // 处理调试会话直到 GDB 断开或程序退出
+    pub fn run(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            let reply = match packet.as_bytes().first() {
+                Some(b'c') => self.resume(cpu, false)?,
+                Some(b's') => self.resume(cpu, true)?,
+                Some(b'k') => {
+                    println!("[GDB] Kill request");
+                    return Ok(());
+                }
+                Some(b'D') => {
+                    self.send_packet("OK")?;
+                    println!("[GDB] Detached, continuing execution");
+                    while cpu.step().is_ok() {}
+                    return Ok(());
+                }
+                _ => self.handle_packet(cpu, &packet),
+            };
+            self.send_packet(&reply)?;
+
+            // 程序已退出，结束会话
+            if reply.starts_with('W') {
+                return Ok(());
+            }
+        }
+        println!("[GDB] Connection closed");
+        Ok(())
+    }
+
+    fn handle_packet(&mut self, cpu: &mut Cpu, packet: &str) -> String {
+        // 空包或首字节不是 ASCII 的包不是已知命令，回复空包
+        let (Some(cmd), Some(args)) = (packet.get(..1), packet.get(1..)) else {
+            return String::new();
+        };
+        let xlen = cpu.xlen();
+        // 每个寄存器在 g/G 包中占的十六进制字符数
+        let width = xlen.bits() as usize / 4;
+        match cmd {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => {
+                let mut regs: String = (0..32)
+                    .map(|i| encode_reg(cpu.read_register(i), xlen))
+                    .collect();
+                regs.push_str(&encode_reg(cpu.pc(), xlen));
+                regs
+            }
+            "G" => {
+                if args.len() < NUM_REGS * width {
+                    return "E01".to_string();
+                }
+                for i in 0..NUM_REGS {
+                    let hex = args.get(i * width..(i + 1) * width);
+                    let Some(value) = hex.and_then(|hex| decode_reg(hex, xlen)) else {
+                        return "E01".to_string();
+                    };
+                    if !self.write_register(cpu, i, value) {
+                        return "E01".to_string();
+                    }
+                }
+                "OK".to_string()
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(i) if i < 32 => encode_reg(cpu.read_register(i), xlen),
+                Ok(PC_REGNUM) => encode_reg(cpu.pc(), xlen),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(reg, value)| {
+                    Some((
+                        usize::from_str_radix(reg, 16).ok()?,
+                        decode_reg(value, xlen)?,
+                    ))
+                });
+                match parsed {
+                    Some((i, value)) if self.write_register(cpu, i, value) => "OK".to_string(),
+                    _ => "E01".to_string(),
+                }
+            }
+            "m" => match parse_addr_len(args) {
+                Some((addr, len)) => Self::read_memory(cpu, addr, len),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let parsed = args
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_addr_len(range)?, decode_hex(data)?)));
+                match parsed {
+                    Some(((addr, len), data)) if data.len() == len => {
+                        Self::write_memory(cpu, addr, &data)
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "Z" | "z" => self.handle_breakpoint(cpu, cmd == "Z", args),
+            "q" | "Q" => self.handle_query(packet, xlen),
+            "H" => "OK".to_string(),
+            "T" => "OK".to_string(), // 只有一个线程
+            _ => String::new(),      // 不支持的命令回复空包
+        }
+    }
+
+    fn handle_query(&mut self, packet: &str, xlen: Xlen) -> String {
+        if packet.starts_with("qSupported") {
+            "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".to_string()
+        } else if packet == "QStartNoAckMode" {
+            // 先用 ack 模式回复 OK，之后再关闭
+            self.no_ack = true;
+            "OK".to_string()
+        } else if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let xml = target_xml(xlen);
+            match parse_addr_len(args) {
+                Some((offset, len)) => {
+                    let offset = (offset as usize).min(xml.len());
+                    let end = (offset + len).min(xml.len());
+                    let prefix = if end == xml.len() { 'l' } else { 'm' };
+                    format!("{}{}", prefix, &xml[offset..end])
+                }
+                None => "E01".to_string(),
+            }
+        } else if packet == "qAttached" {
+            "1".to_string()
+        } else if packet == "qC" {
+            "QC1".to_string()
+        } else if packet == "qfThreadInfo" {
+            "m1".to_string()
+        } else if packet == "qsThreadInfo" {
+            "l".to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    fn handle_breakpoint(&mut self, cpu: &mut Cpu, insert: bool, args: &str) -> String {
+        let mut parts = args.split(',');
+        let (Some(kind), Some(addr), Some(len)) = (parts.next(), parts.next(), parts.next()) else {
+            return "E01".to_string();
+        };
+        let (Ok(addr), Ok(len)) = (u64::from_str_radix(addr, 16), u64::from_str_radix(len, 16))
+        else {
+            return "E01".to_string();
+        };
+
+        let debugger = cpu.debugger_mut();
+        let watch_kind = match kind {
+            // 软件断点和硬件断点都在取指前检查 PC，不修改内存
+            "0" | "1" => {
+                if insert {
+                    debugger.add_breakpoint(addr);
+                } else {
+                    debugger.remove_breakpoint(addr);
+                }
+                return "OK".to_string();
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return String::new(),
+        };
+        if insert {
+            debugger.add_watchpoint(addr, len, watch_kind);
+        } else {
+            debugger.remove_watchpoint(addr, len, watch_kind);
+        }
+        "OK".to_string()
+    }
+
+    fn write_register(&self, cpu: &mut Cpu, index: usize, value: u64) -> bool {
+        match index {
+            0..=31 => {
+                cpu.write_register(index, value);
+                true
+            }
+            PC_REGNUM => cpu.set_pc(value).is_ok(),
+            _ => false,
+        }
+    }
+
+    // 对齐的字优先按 4 字节读取，以支持只允许字访问的设备寄存器
+    fn read_memory(cpu: &mut Cpu, addr: u64, len: usize) -> String {
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            let cur = addr.wrapping_add(data.len() as u64);
+            let result = if cur.is_multiple_of(4) && len - data.len() >= 4 {
+                cpu.debug_read(cur, 4).map(|v| v.to_le_bytes().to_vec())
+            } else {
+                cpu.debug_read(cur, 1).map(|v| vec![v as u8])
+            };
+            match result {
+                Ok(bytes) => data.extend_from_slice(&bytes),
+                Err(_) => break,
+            }
+        }
+
+        if data.is_empty() && len > 0 {
+            return "E14".to_string();
+        }
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn write_memory(cpu: &mut Cpu, addr: u64, data: &[u8]) -> String {
+        let mut offset = 0;
+        while offset < data.len() {
+            let cur = addr.wrapping_add(offset as u64);
+            let result = if cur.is_multiple_of(4) && data.len() - offset >= 4 {
+                let word = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+                cpu.debug_write(cur, word, 4).map(|_| 4)
+            } else {
+                cpu.debug_write(cur, data[offset] as u32, 1).map(|_| 1)
+            };
+            match result {
+                Ok(n) => offset += n,
+                Err(_) => return "E14".to_string(),
+            }
+        }
+        "OK".to_string()
+    }
+
+    // 继续执行或单步，返回停止应答
+    fn resume(&mut self, cpu: &mut Cpu, single: bool) -> io::Result<String> {
+        let mut count = 0u32;
+        loop {
+            let result = cpu.step();
+
+            if let Some(code) = cpu.exit_code() {
+                return Ok(format!("W{:02x}", code & 0xff));
+            }
+            if let Err(e) = result {
+                let signal = match e {
+                    EmuError::Breakpoint { .. } => SIGTRAP,
+                    EmuError::IllegalInstruction { .. } => SIGILL,
+                    _ => SIGSEGV,
+                };
+                return Ok(format!("S{:02x}", signal));
+            }
+            if let Some((kind, addr)) = cpu.debugger_mut().watch_hit.take() {
+                let name = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                return Ok(format!("T{:02x}{}:{:x};", SIGTRAP, name, addr));
+            }
+            if single || cpu.debugger().is_breakpoint(cpu.pc()) {
+                return Ok(format!("S{:02x}", SIGTRAP));
+            }
+
+            count += 1;
+            if count.is_multiple_of(INTERRUPT_CHECK_INTERVAL) && self.interrupt_requested()? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+        }
+    }
+
+    // 非阻塞地检查 GDB 是否发送了 Ctrl-C (0x03)
+    fn interrupt_requested(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0u8; 1];
+        let result = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(1) => Ok(byte[0] == 0x03),
+            Ok(_) => Ok(false),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0u8; 1];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    // 读取一个 $data#cs 包，连接关闭时返回 None
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // 跳过 ack 和包之间的 Ctrl-C
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => (),
+                Some(_) => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(b) => data.push(b),
+                }
+            }
+            let mut checksum = [0u8; 2];
+            self.stream.read_exact(&mut checksum)?;
+
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+            let actual = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+            if !self.no_ack {
+                if expected != Some(actual) {
+                    self.stream.write_all(b"-")?;
+                    continue;
+                }
+                self.stream.write_all(b"+")?;
+            }
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        let packet = format!("${}#{:02x}", data, checksum);
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+            self.stream.flush()?;
+            if self.no_ack {
+                return Ok(());
+            }
+            // 等待 ack，收到 '-' 时重发
+            match self.read_byte()? {
+                Some(b'-') => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 通过本机回环连接创建会话，测试只调用包处理函数，不收发数据
+    fn loopback() -> GdbStub {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        GdbStub {
+            stream,
+            no_ack: false,
+        }
+    }
+
+    fn load_words(words: &[u32]) -> Cpu {
+        let mut cpu = Cpu::new(0x03000000);
+        cpu.set_itrace(false);
+        cpu.set_mtrace(false);
+        cpu.set_regtrace(false);
+        for (i, &word) in words.iter().enumerate() {
+            cpu.debug_write(0x80000000 + 4 * i as u64, word, 4).unwrap();
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_register_encoding() {
+        assert_eq!(encode_reg(0x80000004, Xlen::Rv32), "04000080");
+        assert_eq!(encode_reg(0x1_0000_0002, Xlen::Rv64), "0200000001000000");
+        assert_eq!(decode_reg("04000080", Xlen::Rv32), Some(0x80000004));
+        assert_eq!(
+            decode_reg("0200000001000000", Xlen::Rv64),
+            Some(0x1_0000_0002)
+        );
+        assert_eq!(decode_reg("040000", Xlen::Rv32), None);
+        assert_eq!(decode_reg("0400008", Xlen::Rv32), None);
+        assert_eq!(decode_reg("zz000080", Xlen::Rv32), None);
+
+        assert_eq!(parse_addr_len("80000000,4"), Some((0x80000000, 4)));
+        assert_eq!(parse_addr_len("100000000,10"), Some((0x1_0000_0000, 16)));
+        assert_eq!(parse_addr_len("80000000"), None);
+        assert_eq!(parse_addr_len("xyz,4"), None);
+    }
+
+    #[test]
+    fn test_target_xml_paging() {
+        let mut stub = loopback();
+        let xml = target_xml(Xlen::Rv32);
+        let mut received = String::new();
+        loop {
+            let query = format!("qXfer:features:read:target.xml:{:x},100", received.len());
+            let reply = stub.handle_query(&query, Xlen::Rv32);
+            let (prefix, chunk) = reply.split_at(1);
+            assert!(chunk.len() <= 0x100);
+            received.push_str(chunk);
+            if prefix == "l" {
+                break;
+            }
+            assert_eq!(prefix, "m");
+        }
+        assert_eq!(received, xml);
+        assert!(xml.contains("<architecture>riscv:rv32</architecture>"));
+
+        let past_end = format!("qXfer:features:read:target.xml:{:x},10", xml.len() + 5);
+        assert_eq!(stub.handle_query(&past_end, Xlen::Rv32), "l");
+        assert_eq!(
+            stub.handle_query("qXfer:features:read:target.xml:", Xlen::Rv32),
+            "E01"
+        );
+    }
+
+    #[test]
+    fn test_register_and_watchpoint_packets() {
+        let mut stub = loopback();
+        let mut cpu = load_words(&[
+            0x00500513, // li a0, 5
+            0xfff00593, // li a1, -1
+        ]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+
+        let regs = stub.handle_packet(&mut cpu, "g");
+        assert_eq!(regs.len(), NUM_REGS * 8);
+        assert_eq!(&regs[10 * 8..11 * 8], "05000000");
+        assert_eq!(&regs[32 * 8..], "08000080");
+        assert_eq!(stub.handle_packet(&mut cpu, "pa"), "05000000");
+        assert_eq!(stub.handle_packet(&mut cpu, "pb"), "ffffffff");
+        assert_eq!(stub.handle_packet(&mut cpu, "p20"), "08000080");
+        assert_eq!(stub.handle_packet(&mut cpu, "p21"), "E01");
+
+        assert_eq!(stub.handle_packet(&mut cpu, "Z2,80000100,4"), "OK");
+        let watchpoints = cpu.debugger().watchpoints();
+        assert_eq!(watchpoints.len(), 1);
+        assert_eq!(watchpoints[0].addr, 0x80000100);
+        assert_eq!(watchpoints[0].len, 4);
+        assert_eq!(watchpoints[0].kind, WatchKind::Write);
+        assert_eq!(stub.handle_packet(&mut cpu, "z2,80000100,4"), "OK");
+        assert!(cpu.debugger().watchpoints().is_empty());
+        assert_eq!(stub.handle_packet(&mut cpu, "Z2,80000100"), "E01");
+    }
+
+    #[test]
+    fn test_malformed_packets() {
+        let mut stub = loopback();
+        let mut cpu = load_words(&[0x00000013]);
+        assert_eq!(stub.handle_packet(&mut cpu, ""), "");
+        // 非 ASCII 字节经 from_utf8_lossy 变成多字节的 U+FFFD
+        assert_eq!(stub.handle_packet(&mut cpu, "\u{fffd}00"), "");
+        let mut packet = "G".to_string();
+        packet.push_str(&"\u{fffd}".repeat(NUM_REGS * 8));
+        assert_eq!(stub.handle_packet(&mut cpu, &packet), "E01");
+        assert_eq!(stub.handle_packet(&mut cpu, "m\u{fffd},4"), "E01");
+    }
+}
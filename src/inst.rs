@@ -15,6 +15,11 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::csr::Privilege;
+use crate::error::EmuError;
+use crate::fpu::FpFormat;
+use crate::isa::{Extension, Xlen};
+
 #[derive(Debug, Copy, Clone)]
 pub enum InstType {
     R,
@@ -61,11 +66,19 @@ pub enum Operation {
         rd: usize,
         offset: i32,
     },
+    // rd = pc + offset
+    Auipc {
+        rd: usize,
+        offset: i32,
+    },
+    // 单核且没有缓存，FENCE 不需要任何操作
+    Fence,
     Load {
         rd: usize,
         rs1: usize,
         offset: i32,
         size: usize,
+        signed: bool, // 读取的值是否符号扩展到 XLEN
     },
     Store {
         rs1: usize,
@@ -74,6 +87,61 @@ pub enum Operation {
         size: usize,
     },
     SystemCall(SystemCallType),
+    Csr {
+        rd: usize,
+        rs1: usize, // 立即数形式中为 uimm
+        csr: u16,
+        op: CsrOp,
+    },
+    Atomic {
+        rd: usize,
+        rs1: usize,
+        rs2: usize,
+        size: usize, // .W 为 4，.D 为 8
+        op: AmoOp,
+    },
+    FpLoad {
+        rd: usize,
+        rs1: usize,
+        offset: i32,
+        fmt: FpFormat,
+    },
+    FpStore {
+        rs1: usize,
+        rs2: usize,
+        offset: i32,
+        fmt: FpFormat,
+    },
+    Fp {
+        rd: usize,
+        rs1: usize,
+        rs2: usize,
+        rs3: usize,
+        fmt: FpFormat,
+        rm: u32, // 7 表示使用 frm 中的动态舍入模式
+        op: FpOp,
+    },
+}
+
+impl Operation {
+    // 指令所属的扩展，基础指令集和 Zicsr 返回 None
+    pub fn extension(&self) -> Option<Extension> {
+        match self {
+            Operation::RegRegOp { op, .. } | Operation::RegImmOp { op, .. } => op.extension(),
+            Operation::Atomic { .. } => Some(Extension::A),
+            // FCVT.S.D 的 fmt 为 S，但需要 D 扩展
+            Operation::Fp {
+                op: FpOp::CvtFmt, ..
+            } => Some(Extension::D),
+            Operation::FpLoad { fmt, .. }
+            | Operation::FpStore { fmt, .. }
+            | Operation::Fp { fmt, .. } => match fmt {
+                FpFormat::Single => Some(Extension::F),
+                FpFormat::Double => Some(Extension::D),
+            },
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -97,6 +165,133 @@ pub enum RegOp {
     Slli,
     Srli,
     Srai, // I-type
+    Mul,
+    Mulh,
+    Mulhsu,
+    Mulhu,
+    Div,
+    Divu,
+    Rem,
+    Remu, // M extension
+    Sh1add,
+    Sh2add,
+    Sh3add, // Zba
+    Andn,
+    Orn,
+    Xnor,
+    Clz,
+    Ctz,
+    Cpop,
+    Max,
+    Maxu,
+    Min,
+    Minu,
+    SextB,
+    SextH,
+    ZextH,
+    Rol,
+    Ror,
+    Rori,
+    OrcB,
+    Rev8, // Zbb
+    Clmul,
+    Clmulh,
+    Clmulr, // Zbc
+    Bclr,
+    Bclri,
+    Bext,
+    Bexti,
+    Binv,
+    Binvi,
+    Bset,
+    Bseti, // Zbs
+    Addiw,
+    Slliw,
+    Srliw,
+    Sraiw,
+    Addw,
+    Subw,
+    Sllw,
+    Srlw,
+    Sraw, // RV64I
+    Mulw,
+    Divw,
+    Divuw,
+    Remw,
+    Remuw, // RV64M
+    AddUw,
+    Sh1addUw,
+    Sh2addUw,
+    Sh3addUw,
+    SlliUw, // RV64 Zba
+    Clzw,
+    Ctzw,
+    Cpopw,
+    Rolw,
+    Rorw,
+    Roriw, // RV64 Zbb
+}
+
+impl RegOp {
+    fn extension(self) -> Option<Extension> {
+        match self {
+            RegOp::Mul
+            | RegOp::Mulh
+            | RegOp::Mulhsu
+            | RegOp::Mulhu
+            | RegOp::Div
+            | RegOp::Divu
+            | RegOp::Rem
+            | RegOp::Remu
+            | RegOp::Mulw
+            | RegOp::Divw
+            | RegOp::Divuw
+            | RegOp::Remw
+            | RegOp::Remuw => Some(Extension::M),
+            RegOp::Sh1add
+            | RegOp::Sh2add
+            | RegOp::Sh3add
+            | RegOp::AddUw
+            | RegOp::Sh1addUw
+            | RegOp::Sh2addUw
+            | RegOp::Sh3addUw
+            | RegOp::SlliUw => Some(Extension::Zba),
+            RegOp::Andn
+            | RegOp::Orn
+            | RegOp::Xnor
+            | RegOp::Clz
+            | RegOp::Ctz
+            | RegOp::Cpop
+            | RegOp::Max
+            | RegOp::Maxu
+            | RegOp::Min
+            | RegOp::Minu
+            | RegOp::SextB
+            | RegOp::SextH
+            | RegOp::ZextH
+            | RegOp::Rol
+            | RegOp::Ror
+            | RegOp::Rori
+            | RegOp::OrcB
+            | RegOp::Rev8
+            | RegOp::Clzw
+            | RegOp::Ctzw
+            | RegOp::Cpopw
+            | RegOp::Rolw
+            | RegOp::Rorw
+            | RegOp::Roriw => Some(Extension::Zbb),
+            RegOp::Clmul | RegOp::Clmulh | RegOp::Clmulr => Some(Extension::Zbc),
+            RegOp::Bclr
+            | RegOp::Bclri
+            | RegOp::Bext
+            | RegOp::Bexti
+            | RegOp::Binv
+            | RegOp::Binvi
+            | RegOp::Bset
+            | RegOp::Bseti => Some(Extension::Zbs),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -109,10 +304,72 @@ pub enum BranchOp {
     Geu, // B-type
 }
 
+#[derive(Debug, Copy, Clone)]
+pub enum CsrOp {
+    Rw,
+    Rs,
+    Rc,
+    Rwi,
+    Rsi,
+    Rci, // Zicsr
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum AmoOp {
+    Lr,
+    Sc,
+    Swap,
+    Add,
+    Xor,
+    And,
+    Or,
+    Min,
+    Max,
+    Minu,
+    Maxu, // A extension
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum FpOp {
+    Madd,
+    Msub,
+    Nmsub,
+    Nmadd, // 融合乘加
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Sqrt,
+    Sgnj,
+    Sgnjn,
+    Sgnjx,
+    Min,
+    Max,
+    CvtFmt, // S 与 D 互转，fmt 为目标格式
+    CvtW,
+    CvtWu,
+    CvtFromW,
+    CvtFromWu,
+    CvtL,
+    CvtLu,
+    CvtFromL,
+    CvtFromLu, // RV64 的 64 位整数转换
+    MvToInt,
+    MvFromInt,
+    Eq,
+    Lt,
+    Le,
+    Class, // F/D extension
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum SystemCallType {
     Ecall,
     Ebreak,
+    Mret,
+    Sret,
+    Wfi,
+    SfenceVma { rs1: usize, rs2: usize },
 }
 
 impl Operands {
@@ -154,7 +411,7 @@ pub struct DecodedInst {
 
 #[derive(Debug)]
 pub enum NextPc {
-    Plus4,
+    Next, // 顺序执行：PC + 指令长度
     Jump(i32),
     JumpReg {
         rd: usize,
@@ -167,6 +424,7 @@ pub enum NextPc {
         rs2: usize,
         offset: i32,
     },
+    TrapReturn(Privilege), // MRET/SRET
 }
 
 // -----------------------------------------------------------------------------
@@ -193,25 +451,35 @@ impl BitPat {
 // Decoding Logic
 // -----------------------------------------------------------------------------
 
-pub fn decode_instruction(inst: u32) -> Result<DecodedInst, &'static str> {
+// 同一编码在 RV32 和 RV64 下的含义可能不同（移位量宽度、rev8、zext.h），
+// RV64 专有的指令在 RV32 下按非法指令处理
+pub fn decode_instruction(inst: u32, xlen: Xlen) -> Result<DecodedInst, EmuError> {
     let opcode = inst & 0x7f;
 
     match opcode {
-        0x33 => decode_r_type(inst),
-        0x13 => decode_i_type_alu(inst),
-        0x03 => decode_load(inst),
-        0x23 => decode_store(inst),
+        0x33 => decode_r_type(inst, xlen),
+        0x13 => decode_i_type_alu(inst, xlen),
+        0x3b if xlen == Xlen::Rv64 => decode_r_type_word(inst),
+        0x1b if xlen == Xlen::Rv64 => decode_i_type_word(inst),
+        0x03 => decode_load(inst, xlen),
+        0x23 => decode_store(inst, xlen),
         0x63 => decode_branch(inst),
         0x67 => decode_jalr(inst),
         0x6f => decode_jal(inst),
         0x37 => decode_lui(inst),
         0x17 => decode_auipc(inst),
+        0x0f => decode_fence(inst),
         0x73 => decode_system(inst),
-        _ => Err("Unknown opcode"),
+        0x2f => decode_atomic(inst, xlen),
+        0x07 => decode_fp_load(inst),
+        0x27 => decode_fp_store(inst),
+        0x43 | 0x47 | 0x4b | 0x4f => decode_fp_fused(inst),
+        0x53 => decode_fp_op(inst, xlen),
+        _ => Err(EmuError::illegal(inst)),
     }
 }
 
-fn decode_r_type(inst: u32) -> Result<DecodedInst, &'static str> {
+fn decode_r_type(inst: u32, xlen: Xlen) -> Result<DecodedInst, EmuError> {
     let ops = Operands::decode(inst, InstType::R);
     let funct3 = (inst >> 12) & 0x7;
     let funct7 = (inst >> 25) & 0x7f;
@@ -227,7 +495,41 @@ fn decode_r_type(inst: u32) -> Result<DecodedInst, &'static str> {
         (0x5, 0x20) => RegOp::Sra,
         (0x6, 0x00) => RegOp::Or,
         (0x7, 0x00) => RegOp::And,
-        _ => return Err("Invalid funct3/funct7 for R-type"),
+        // M 扩展：funct7 = 0x01
+        (0x0, 0x01) => RegOp::Mul,
+        (0x1, 0x01) => RegOp::Mulh,
+        (0x2, 0x01) => RegOp::Mulhsu,
+        (0x3, 0x01) => RegOp::Mulhu,
+        (0x4, 0x01) => RegOp::Div,
+        (0x5, 0x01) => RegOp::Divu,
+        (0x6, 0x01) => RegOp::Rem,
+        (0x7, 0x01) => RegOp::Remu,
+        // Zba
+        (0x2, 0x10) => RegOp::Sh1add,
+        (0x4, 0x10) => RegOp::Sh2add,
+        (0x6, 0x10) => RegOp::Sh3add,
+        // Zbb
+        (0x7, 0x20) => RegOp::Andn,
+        (0x6, 0x20) => RegOp::Orn,
+        (0x4, 0x20) => RegOp::Xnor,
+        (0x4, 0x05) => RegOp::Min,
+        (0x5, 0x05) => RegOp::Minu,
+        (0x6, 0x05) => RegOp::Max,
+        (0x7, 0x05) => RegOp::Maxu,
+        // RV64 的 zext.h 位于 OP-32
+        (0x4, 0x04) if ops.rs2 == 0 && xlen == Xlen::Rv32 => RegOp::ZextH,
+        (0x1, 0x30) => RegOp::Rol,
+        (0x5, 0x30) => RegOp::Ror,
+        // Zbc
+        (0x1, 0x05) => RegOp::Clmul,
+        (0x2, 0x05) => RegOp::Clmulr,
+        (0x3, 0x05) => RegOp::Clmulh,
+        // Zbs
+        (0x1, 0x24) => RegOp::Bclr,
+        (0x5, 0x24) => RegOp::Bext,
+        (0x1, 0x34) => RegOp::Binv,
+        (0x1, 0x14) => RegOp::Bset,
+        _ => return Err(EmuError::illegal(inst)),
     };
 
     Ok(DecodedInst {
@@ -237,31 +539,61 @@ fn decode_r_type(inst: u32) -> Result<DecodedInst, &'static str> {
             rs2: ops.rs2,
             op,
         },
-        next_pc: NextPc::Plus4,
+        next_pc: NextPc::Next,
     })
 }
 
-fn decode_i_type_alu(inst: u32) -> Result<DecodedInst, &'static str> {
+// 移位类指令的功能码：RV32 为 imm[11:5]，RV64 为 imm[11:6]（左移一位与 RV32 的编码对齐）
+fn shift_funct(imm12: u32, xlen: Xlen) -> u32 {
+    match xlen {
+        Xlen::Rv32 => imm12 >> 5,
+        Xlen::Rv64 => (imm12 >> 6) << 1,
+    }
+}
+
+fn decode_i_type_alu(inst: u32, xlen: Xlen) -> Result<DecodedInst, EmuError> {
     let ops = Operands::decode(inst, InstType::I);
     let funct3 = (inst >> 12) & 0x7;
     let funct7 = (inst >> 25) & 0x7f;
 
+    let imm12 = (inst >> 20) & 0xfff;
+    let rev8 = match xlen {
+        Xlen::Rv32 => 0x698,
+        Xlen::Rv64 => 0x6b8,
+    };
+
     let op = match funct3 {
         0x0 => RegOp::Addi,
-        0x1 => RegOp::Slli,
+        0x1 => match (funct7, ops.rs2) {
+            // Zbb 单操作数指令，rs2 字段区分操作
+            (0x30, 0x0) => RegOp::Clz,
+            (0x30, 0x1) => RegOp::Ctz,
+            (0x30, 0x2) => RegOp::Cpop,
+            (0x30, 0x4) => RegOp::SextB,
+            (0x30, 0x5) => RegOp::SextH,
+            _ => match shift_funct(imm12, xlen) {
+                0x00 => RegOp::Slli,
+                0x14 => RegOp::Bseti,
+                0x24 => RegOp::Bclri,
+                0x34 => RegOp::Binvi,
+                _ => return Err(EmuError::illegal(inst)),
+            },
+        },
         0x2 => RegOp::Slti,
         0x3 => RegOp::Sltiu,
         0x4 => RegOp::Xori,
-        0x5 => {
-            if funct7 == 0x20 {
-                RegOp::Srai
-            } else {
-                RegOp::Srli
-            }
-        }
+        0x5 if imm12 == 0x287 => RegOp::OrcB,
+        0x5 if imm12 == rev8 => RegOp::Rev8,
+        0x5 => match shift_funct(imm12, xlen) {
+            0x00 => RegOp::Srli,
+            0x20 => RegOp::Srai,
+            0x30 => RegOp::Rori,
+            0x24 => RegOp::Bexti,
+            _ => return Err(EmuError::illegal(inst)),
+        },
         0x6 => RegOp::Ori,
         0x7 => RegOp::Andi,
-        _ => return Err("Invalid funct3 for I-type ALU"),
+        _ => return Err(EmuError::illegal(inst)),
     };
 
     Ok(DecodedInst {
@@ -271,21 +603,92 @@ fn decode_i_type_alu(inst: u32) -> Result<DecodedInst, &'static str> {
             imm: ops.imm,
             op,
         },
-        next_pc: NextPc::Plus4,
+        next_pc: NextPc::Next,
     })
 }
 
-fn decode_load(inst: u32) -> Result<DecodedInst, &'static str> {
+// RV64 OP-32：在低 32 位上运算并把结果符号扩展
+fn decode_r_type_word(inst: u32) -> Result<DecodedInst, EmuError> {
+    let ops = Operands::decode(inst, InstType::R);
+    let funct3 = (inst >> 12) & 0x7;
+    let funct7 = (inst >> 25) & 0x7f;
+
+    let op = match (funct3, funct7) {
+        (0x0, 0x00) => RegOp::Addw,
+        (0x0, 0x20) => RegOp::Subw,
+        (0x1, 0x00) => RegOp::Sllw,
+        (0x5, 0x00) => RegOp::Srlw,
+        (0x5, 0x20) => RegOp::Sraw,
+        (0x0, 0x01) => RegOp::Mulw,
+        (0x4, 0x01) => RegOp::Divw,
+        (0x5, 0x01) => RegOp::Divuw,
+        (0x6, 0x01) => RegOp::Remw,
+        (0x7, 0x01) => RegOp::Remuw,
+        (0x0, 0x04) => RegOp::AddUw,
+        (0x2, 0x10) => RegOp::Sh1addUw,
+        (0x4, 0x10) => RegOp::Sh2addUw,
+        (0x6, 0x10) => RegOp::Sh3addUw,
+        (0x4, 0x04) if ops.rs2 == 0 => RegOp::ZextH,
+        (0x1, 0x30) => RegOp::Rolw,
+        (0x5, 0x30) => RegOp::Rorw,
+        _ => return Err(EmuError::illegal(inst)),
+    };
+
+    Ok(DecodedInst {
+        op: Operation::RegRegOp {
+            rd: ops.rd,
+            rs1: ops.rs1,
+            rs2: ops.rs2,
+            op,
+        },
+        next_pc: NextPc::Next,
+    })
+}
+
+// RV64 OP-IMM-32：字移位的移位量只有 5 位
+fn decode_i_type_word(inst: u32) -> Result<DecodedInst, EmuError> {
     let ops = Operands::decode(inst, InstType::I);
     let funct3 = (inst >> 12) & 0x7;
+    let funct7 = (inst >> 25) & 0x7f;
 
-    let size = match funct3 {
-        0x0 => 1, // LB
-        0x1 => 2, // LH
-        0x2 => 4, // LW
-        0x4 => 1, // LBU
-        0x5 => 2, // LHU
-        _ => return Err("Invalid funct3 for load"),
+    let op = match (funct3, funct7, ops.rs2) {
+        (0x0, _, _) => RegOp::Addiw,
+        (0x1, 0x00, _) => RegOp::Slliw,
+        (0x1, 0x30, 0x0) => RegOp::Clzw,
+        (0x1, 0x30, 0x1) => RegOp::Ctzw,
+        (0x1, 0x30, 0x2) => RegOp::Cpopw,
+        // slli.uw 的移位量为 6 位
+        (0x1, 0x04 | 0x05, _) => RegOp::SlliUw,
+        (0x5, 0x00, _) => RegOp::Srliw,
+        (0x5, 0x20, _) => RegOp::Sraiw,
+        (0x5, 0x30, _) => RegOp::Roriw,
+        _ => return Err(EmuError::illegal(inst)),
+    };
+
+    Ok(DecodedInst {
+        op: Operation::RegImmOp {
+            rd: ops.rd,
+            rs1: ops.rs1,
+            imm: ops.imm,
+            op,
+        },
+        next_pc: NextPc::Next,
+    })
+}
+
+fn decode_load(inst: u32, xlen: Xlen) -> Result<DecodedInst, EmuError> {
+    let ops = Operands::decode(inst, InstType::I);
+    let funct3 = (inst >> 12) & 0x7;
+
+    let (size, signed) = match funct3 {
+        0x0 => (1, true),                        // LB
+        0x1 => (2, true),                        // LH
+        0x2 => (4, true),                        // LW
+        0x4 => (1, false),                       // LBU
+        0x5 => (2, false),                       // LHU
+        0x3 if xlen == Xlen::Rv64 => (8, false), // LD
+        0x6 if xlen == Xlen::Rv64 => (4, false), // LWU
+        _ => return Err(EmuError::illegal(inst)),
     };
 
     Ok(DecodedInst {
@@ -294,20 +697,22 @@ fn decode_load(inst: u32) -> Result<DecodedInst, &'static str> {
             rs1: ops.rs1,
             offset: ops.imm,
             size,
+            signed,
         },
-        next_pc: NextPc::Plus4,
+        next_pc: NextPc::Next,
     })
 }
 
-fn decode_store(inst: u32) -> Result<DecodedInst, &'static str> {
+fn decode_store(inst: u32, xlen: Xlen) -> Result<DecodedInst, EmuError> {
     let ops = Operands::decode(inst, InstType::S);
     let funct3 = (inst >> 12) & 0x7;
 
     let size = match funct3 {
-        0x0 => 1, // SB
-        0x1 => 2, // SH
-        0x2 => 4, // SW
-        _ => return Err("Invalid funct3 for store"),
+        0x0 => 1,                       // SB
+        0x1 => 2,                       // SH
+        0x2 => 4,                       // SW
+        0x3 if xlen == Xlen::Rv64 => 8, // SD
+        _ => return Err(EmuError::illegal(inst)),
     };
 
     Ok(DecodedInst {
@@ -317,11 +722,11 @@ fn decode_store(inst: u32) -> Result<DecodedInst, &'static str> {
             offset: ops.imm,
             size,
         },
-        next_pc: NextPc::Plus4,
+        next_pc: NextPc::Next,
     })
 }
 
-fn decode_branch(inst: u32) -> Result<DecodedInst, &'static str> {
+fn decode_branch(inst: u32) -> Result<DecodedInst, EmuError> {
     let ops = Operands::decode(inst, InstType::B);
     let funct3 = (inst >> 12) & 0x7;
 
@@ -332,7 +737,7 @@ fn decode_branch(inst: u32) -> Result<DecodedInst, &'static str> {
         0x5 => BranchOp::Ge,
         0x6 => BranchOp::Ltu,
         0x7 => BranchOp::Geu,
-        _ => return Err("Invalid funct3 for branch"),
+        _ => return Err(EmuError::illegal(inst)),
     };
 
     Ok(DecodedInst {
@@ -351,12 +756,12 @@ fn decode_branch(inst: u32) -> Result<DecodedInst, &'static str> {
     })
 }
 
-fn decode_jalr(inst: u32) -> Result<DecodedInst, &'static str> {
+fn decode_jalr(inst: u32) -> Result<DecodedInst, EmuError> {
     let ops = Operands::decode(inst, InstType::I);
     let funct3 = (inst >> 12) & 0x7;
 
     if funct3 != 0 {
-        return Err("Invalid funct3 for JALR");
+        return Err(EmuError::illegal(inst));
     }
 
     Ok(DecodedInst {
@@ -372,7 +777,7 @@ fn decode_jalr(inst: u32) -> Result<DecodedInst, &'static str> {
     })
 }
 
-fn decode_jal(inst: u32) -> Result<DecodedInst, &'static str> {
+fn decode_jal(inst: u32) -> Result<DecodedInst, EmuError> {
     let ops = Operands::decode(inst, InstType::J);
     Ok(DecodedInst {
         op: Operation::Jump {
@@ -383,45 +788,299 @@ fn decode_jal(inst: u32) -> Result<DecodedInst, &'static str> {
     })
 }
 
-fn decode_lui(inst: u32) -> Result<DecodedInst, &'static str> {
+fn decode_lui(inst: u32) -> Result<DecodedInst, EmuError> {
     let ops = Operands::decode(inst, InstType::U);
     Ok(DecodedInst {
         op: Operation::RegWrite {
             rd: ops.rd,
             value: (ops.imm as u32) & 0xfffff000,
         },
-        next_pc: NextPc::Plus4,
+        next_pc: NextPc::Next,
     })
 }
 
-fn decode_auipc(inst: u32) -> Result<DecodedInst, &'static str> {
+fn decode_auipc(inst: u32) -> Result<DecodedInst, EmuError> {
     let ops = Operands::decode(inst, InstType::U);
     Ok(DecodedInst {
-        op: Operation::RegWrite {
+        op: Operation::Auipc {
             rd: ops.rd,
-            value: ops.imm as u32,
+            offset: ops.imm,
         },
-        next_pc: NextPc::Plus4,
+        next_pc: NextPc::Next,
+    })
+}
+
+// FENCE/FENCE.TSO：pred/succ 和 fm 字段不影响执行
+// FENCE.I（Zifencei）：没有指令缓存，同样按空操作执行
+fn decode_fence(inst: u32) -> Result<DecodedInst, EmuError> {
+    if (inst >> 12) & 0x7 > 1 {
+        return Err(EmuError::illegal(inst));
+    }
+    Ok(DecodedInst {
+        op: Operation::Fence,
+        next_pc: NextPc::Next,
     })
 }
 
-fn decode_system(inst: u32) -> Result<DecodedInst, &'static str> {
+fn decode_system(inst: u32) -> Result<DecodedInst, EmuError> {
+    let funct3 = (inst >> 12) & 0x7;
+    if funct3 != 0 {
+        return decode_csr(inst);
+    }
+
     // Ecall: 000000000000_00000_000_00000_1110011 -> 0x00000073
     const ECALL_PAT: BitPat = BitPat::new(0xFFFFFFFF, 0x00000073);
     // Ebreak: 000000000001_00000_000_00000_1110011 -> 0x00100073
     const EBREAK_PAT: BitPat = BitPat::new(0xFFFFFFFF, 0x00100073);
+    // Mret: 001100000010_00000_000_00000_1110011 -> 0x30200073
+    const MRET_PAT: BitPat = BitPat::new(0xFFFFFFFF, 0x30200073);
+    // Sret: 000100000010_00000_000_00000_1110011 -> 0x10200073
+    const SRET_PAT: BitPat = BitPat::new(0xFFFFFFFF, 0x10200073);
+    // Wfi: 000100000101_00000_000_00000_1110011 -> 0x10500073
+    const WFI_PAT: BitPat = BitPat::new(0xFFFFFFFF, 0x10500073);
+    // SfenceVma: 0001001_rs2_rs1_000_00000_1110011
+    const SFENCE_VMA_PAT: BitPat = BitPat::new(0xFE007FFF, 0x12000073);
 
     if ECALL_PAT.matches(inst) {
         Ok(DecodedInst {
             op: Operation::SystemCall(SystemCallType::Ecall),
-            next_pc: NextPc::Plus4,
+            next_pc: NextPc::Next,
         })
     } else if EBREAK_PAT.matches(inst) {
         Ok(DecodedInst {
             op: Operation::SystemCall(SystemCallType::Ebreak),
-            next_pc: NextPc::Plus4,
+            next_pc: NextPc::Next,
+        })
+    } else if MRET_PAT.matches(inst) {
+        Ok(DecodedInst {
+            op: Operation::SystemCall(SystemCallType::Mret),
+            next_pc: NextPc::TrapReturn(Privilege::Machine),
+        })
+    } else if SRET_PAT.matches(inst) {
+        Ok(DecodedInst {
+            op: Operation::SystemCall(SystemCallType::Sret),
+            next_pc: NextPc::TrapReturn(Privilege::Supervisor),
+        })
+    } else if WFI_PAT.matches(inst) {
+        Ok(DecodedInst {
+            op: Operation::SystemCall(SystemCallType::Wfi),
+            next_pc: NextPc::Next,
+        })
+    } else if SFENCE_VMA_PAT.matches(inst) {
+        let ops = Operands::decode(inst, InstType::R);
+        Ok(DecodedInst {
+            op: Operation::SystemCall(SystemCallType::SfenceVma {
+                rs1: ops.rs1,
+                rs2: ops.rs2,
+            }),
+            next_pc: NextPc::Next,
         })
     } else {
-        Err("Invalid system instruction")
+        Err(EmuError::illegal(inst))
+    }
+}
+
+fn decode_atomic(inst: u32, xlen: Xlen) -> Result<DecodedInst, EmuError> {
+    let ops = Operands::decode(inst, InstType::R);
+    let funct3 = (inst >> 12) & 0x7;
+    let funct5 = inst >> 27; // aq/rl 位在单核模拟中无需处理
+
+    let size = match funct3 {
+        0x2 => 4,
+        0x3 if xlen == Xlen::Rv64 => 8,
+        _ => return Err(EmuError::illegal(inst)),
+    };
+
+    let op = match funct5 {
+        0x02 if ops.rs2 == 0 => AmoOp::Lr,
+        0x03 => AmoOp::Sc,
+        0x01 => AmoOp::Swap,
+        0x00 => AmoOp::Add,
+        0x04 => AmoOp::Xor,
+        0x0c => AmoOp::And,
+        0x08 => AmoOp::Or,
+        0x10 => AmoOp::Min,
+        0x14 => AmoOp::Max,
+        0x18 => AmoOp::Minu,
+        0x1c => AmoOp::Maxu,
+        _ => return Err(EmuError::illegal(inst)),
+    };
+
+    Ok(DecodedInst {
+        op: Operation::Atomic {
+            rd: ops.rd,
+            rs1: ops.rs1,
+            rs2: ops.rs2,
+            size,
+            op,
+        },
+        next_pc: NextPc::Next,
+    })
+}
+
+fn decode_csr(inst: u32) -> Result<DecodedInst, EmuError> {
+    let ops = Operands::decode(inst, InstType::I);
+    let funct3 = (inst >> 12) & 0x7;
+    let csr = (inst >> 20) as u16;
+
+    let op = match funct3 {
+        0x1 => CsrOp::Rw,
+        0x2 => CsrOp::Rs,
+        0x3 => CsrOp::Rc,
+        0x5 => CsrOp::Rwi,
+        0x6 => CsrOp::Rsi,
+        0x7 => CsrOp::Rci,
+        _ => return Err(EmuError::illegal(inst)),
+    };
+
+    Ok(DecodedInst {
+        op: Operation::Csr {
+            rd: ops.rd,
+            rs1: ops.rs1,
+            csr,
+            op,
+        },
+        next_pc: NextPc::Next,
+    })
+}
+
+// fmt 字段：0 为单精度，1 为双精度（2/3 为 H/Q，不支持）
+fn fp_format(inst: u32) -> Result<FpFormat, EmuError> {
+    match (inst >> 25) & 0x3 {
+        0 => Ok(FpFormat::Single),
+        1 => Ok(FpFormat::Double),
+        _ => Err(EmuError::illegal(inst)),
     }
 }
+
+// 舍入模式 101 和 110 保留
+fn fp_rounding_mode(inst: u32) -> Result<u32, EmuError> {
+    match (inst >> 12) & 0x7 {
+        rm @ (0..=4 | 7) => Ok(rm),
+        _ => Err(EmuError::illegal(inst)),
+    }
+}
+
+fn decode_fp_load(inst: u32) -> Result<DecodedInst, EmuError> {
+    let ops = Operands::decode(inst, InstType::I);
+    let funct3 = (inst >> 12) & 0x7;
+
+    let fmt = match funct3 {
+        0x2 => FpFormat::Single, // FLW
+        0x3 => FpFormat::Double, // FLD
+        _ => return Err(EmuError::illegal(inst)),
+    };
+
+    Ok(DecodedInst {
+        op: Operation::FpLoad {
+            rd: ops.rd,
+            rs1: ops.rs1,
+            offset: ops.imm,
+            fmt,
+        },
+        next_pc: NextPc::Next,
+    })
+}
+
+fn decode_fp_store(inst: u32) -> Result<DecodedInst, EmuError> {
+    let ops = Operands::decode(inst, InstType::S);
+    let funct3 = (inst >> 12) & 0x7;
+
+    let fmt = match funct3 {
+        0x2 => FpFormat::Single, // FSW
+        0x3 => FpFormat::Double, // FSD
+        _ => return Err(EmuError::illegal(inst)),
+    };
+
+    Ok(DecodedInst {
+        op: Operation::FpStore {
+            rs1: ops.rs1,
+            rs2: ops.rs2,
+            offset: ops.imm,
+            fmt,
+        },
+        next_pc: NextPc::Next,
+    })
+}
+
+fn decode_fp_fused(inst: u32) -> Result<DecodedInst, EmuError> {
+    let ops = Operands::decode(inst, InstType::R);
+    let rs3 = (inst >> 27) as usize;
+    let fmt = fp_format(inst)?;
+    let rm = fp_rounding_mode(inst)?;
+
+    let op = match inst & 0x7f {
+        0x43 => FpOp::Madd,
+        0x47 => FpOp::Msub,
+        0x4b => FpOp::Nmsub,
+        _ => FpOp::Nmadd,
+    };
+
+    Ok(DecodedInst {
+        op: Operation::Fp {
+            rd: ops.rd,
+            rs1: ops.rs1,
+            rs2: ops.rs2,
+            rs3,
+            fmt,
+            rm,
+            op,
+        },
+        next_pc: NextPc::Next,
+    })
+}
+
+fn decode_fp_op(inst: u32, xlen: Xlen) -> Result<DecodedInst, EmuError> {
+    let ops = Operands::decode(inst, InstType::R);
+    let funct3 = (inst >> 12) & 0x7;
+    let funct5 = inst >> 27;
+    let fmt = fp_format(inst)?;
+    let rv64 = xlen == Xlen::Rv64;
+
+    // 第二个元素表示 funct3 是否为舍入模式
+    let (op, rounding) = match (funct5, funct3, ops.rs2) {
+        (0x00, _, _) => (FpOp::Add, true),
+        (0x01, _, _) => (FpOp::Sub, true),
+        (0x02, _, _) => (FpOp::Mul, true),
+        (0x03, _, _) => (FpOp::Div, true),
+        (0x0b, _, 0) => (FpOp::Sqrt, true),
+        (0x04, 0x0, _) => (FpOp::Sgnj, false),
+        (0x04, 0x1, _) => (FpOp::Sgnjn, false),
+        (0x04, 0x2, _) => (FpOp::Sgnjx, false),
+        (0x05, 0x0, _) => (FpOp::Min, false),
+        (0x05, 0x1, _) => (FpOp::Max, false),
+        // FCVT.S.D 的 rs2 为源格式 D，FCVT.D.S 的 rs2 为源格式 S
+        (0x08, _, 1) if fmt == FpFormat::Single => (FpOp::CvtFmt, true),
+        (0x08, _, 0) if fmt == FpFormat::Double => (FpOp::CvtFmt, true),
+        (0x18, _, 0) => (FpOp::CvtW, true),
+        (0x18, _, 1) => (FpOp::CvtWu, true),
+        (0x1a, _, 0) => (FpOp::CvtFromW, true),
+        (0x1a, _, 1) => (FpOp::CvtFromWu, true),
+        (0x18, _, 2) if rv64 => (FpOp::CvtL, true),
+        (0x18, _, 3) if rv64 => (FpOp::CvtLu, true),
+        (0x1a, _, 2) if rv64 => (FpOp::CvtFromL, true),
+        (0x1a, _, 3) if rv64 => (FpOp::CvtFromLu, true),
+        // RV32 只有单精度的 FMV.X.W / FMV.W.X，FMV.X.D / FMV.D.X 需要 RV64
+        (0x1c, 0x0, 0) if fmt == FpFormat::Single || rv64 => (FpOp::MvToInt, false),
+        (0x1c, 0x1, 0) => (FpOp::Class, false),
+        (0x1e, 0x0, 0) if fmt == FpFormat::Single || rv64 => (FpOp::MvFromInt, false),
+        (0x14, 0x2, _) => (FpOp::Eq, false),
+        (0x14, 0x1, _) => (FpOp::Lt, false),
+        (0x14, 0x0, _) => (FpOp::Le, false),
+        _ => return Err(EmuError::illegal(inst)),
+    };
+    let rm = if rounding { fp_rounding_mode(inst)? } else { 0 };
+
+    Ok(DecodedInst {
+        op: Operation::Fp {
+            rd: ops.rd,
+            rs1: ops.rs1,
+            rs2: ops.rs2,
+            rs3: 0,
+            fmt,
+            rm,
+            op,
+        },
+        next_pc: NextPc::Next,
+    })
+}
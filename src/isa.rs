@@ -0,0 +1,206 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fmt;
+
+// ISA 字符串配置：未启用的扩展的指令按非法指令处理
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Extension {
+    M,
+    A,
+    F,
+    D,
+    C,
+    Zba,
+    Zbb,
+    Zbc,
+    Zbs,
+}
+
+impl Extension {
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+// 单字母扩展及其在 misa 中的字母，按规范顺序排列
+const LETTERS: [(char, Extension); 5] = [
+    ('m', Extension::M),
+    ('a', Extension::A),
+    ('f', Extension::F),
+    ('d', Extension::D),
+    ('c', Extension::C),
+];
+
+// 多字母扩展，Zicsr 和 Zifencei 总是支持
+const MULTI_LETTER: [(&str, Extension); 4] = [
+    ("zba", Extension::Zba),
+    ("zbb", Extension::Zbb),
+    ("zbc", Extension::Zbc),
+    ("zbs", Extension::Zbs),
+];
+
+// 整数寄存器宽度，由 ISA 字符串的 rv32/rv64 前缀选择
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Xlen {
+    Rv32,
+    Rv64,
+}
+
+impl Xlen {
+    pub fn bits(self) -> u32 {
+        match self {
+            Xlen::Rv32 => 32,
+            Xlen::Rv64 => 64,
+        }
+    }
+
+    // 把运算结果截断到 XLEN 位
+    pub fn mask(self) -> u64 {
+        match self {
+            Xlen::Rv32 => 0xffffffff,
+            Xlen::Rv64 => u64::MAX,
+        }
+    }
+}
+
+// 默认启用模拟器支持的全部扩展
+pub const DEFAULT_ISA: &str = "rv32imafdc_zicsr_zba_zbb_zbc_zbs";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Isa {
+    xlen: Xlen,
+    extensions: u32,
+}
+
+impl Default for Isa {
+    fn default() -> Self {
+        Self::parse(DEFAULT_ISA).unwrap()
+    }
+}
+
+impl Isa {
+    // 解析形如 rv32imac_zba_zbb 或 rv64gc 的 ISA 字符串，g 等价于 imafd_zicsr_zifencei
+    pub fn parse(isa: &str) -> Result<Self, &'static str> {
+        let isa = isa.to_ascii_lowercase();
+        let (xlen, rest) = if let Some(rest) = isa.strip_prefix("rv32") {
+            (Xlen::Rv32, rest)
+        } else if let Some(rest) = isa.strip_prefix("rv64") {
+            (Xlen::Rv64, rest)
+        } else {
+            return Err("ISA string must start with rv32 or rv64");
+        };
+        let mut parts = rest.split('_');
+        let mut letters = parts.next().unwrap_or("").chars();
+
+        let mut extensions = match letters.next() {
+            Some('i') => 0,
+            Some('g') => {
+                Extension::M.bit() | Extension::A.bit() | Extension::F.bit() | Extension::D.bit()
+            }
+            _ => return Err("Base ISA must be i or g"),
+        };
+        for letter in letters {
+            let (_, ext) = LETTERS
+                .iter()
+                .find(|(c, _)| *c == letter)
+                .ok_or("Unsupported ISA extension")?;
+            extensions |= ext.bit();
+        }
+        for part in parts {
+            if part == "zicsr" || part == "zifencei" {
+                continue;
+            }
+            let (_, ext) = MULTI_LETTER
+                .iter()
+                .find(|(name, _)| *name == part)
+                .ok_or("Unsupported ISA extension")?;
+            extensions |= ext.bit();
+        }
+
+        let isa = Self { xlen, extensions };
+        if isa.has(Extension::D) && !isa.has(Extension::F) {
+            return Err("D extension requires F");
+        }
+        Ok(isa)
+    }
+
+    pub fn xlen(&self) -> Xlen {
+        self.xlen
+    }
+
+    pub fn has(&self, ext: Extension) -> bool {
+        self.extensions & ext.bit() != 0
+    }
+
+    // misa 的扩展位（I 总是存在）
+    pub fn misa_extensions(&self) -> u32 {
+        LETTERS
+            .iter()
+            .filter(|(_, ext)| self.has(*ext))
+            .fold(1 << (b'I' - b'A'), |bits, (c, _)| {
+                bits | 1 << (c.to_ascii_uppercase() as u8 - b'A')
+            })
+    }
+}
+
+impl fmt::Display for Isa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rv{}i", self.xlen.bits())?;
+        for (c, ext) in LETTERS.iter() {
+            if self.has(*ext) {
+                write!(f, "{}", c)?;
+            }
+        }
+        write!(f, "_zicsr")?;
+        for (name, ext) in MULTI_LETTER.iter() {
+            if self.has(*ext) {
+                write!(f, "_{}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_isa_string() {
+        let isa = Isa::parse("rv32i_zba_zbb_zbs").unwrap();
+        assert!(isa.has(Extension::Zba) && isa.has(Extension::Zbs));
+        assert!(Isa::parse("rv32i_zicsr_zifencei").is_ok());
+        assert!(!isa.has(Extension::Zbc) && !isa.has(Extension::M));
+        assert_eq!(isa.to_string(), "rv32i_zicsr_zba_zbb_zbs");
+
+        let isa = Isa::parse("RV32GC").unwrap();
+        assert_eq!(isa.to_string(), "rv32imafdc_zicsr");
+        assert_eq!(isa.misa_extensions(), 0x112d);
+        assert_eq!(Isa::default().to_string(), DEFAULT_ISA);
+
+        let isa = Isa::parse("rv64imac").unwrap();
+        assert_eq!(isa.xlen(), Xlen::Rv64);
+        assert_eq!(isa.to_string(), "rv64imac_zicsr");
+
+        assert!(Isa::parse("rv128i").is_err());
+        assert!(Isa::parse("rv32e").is_err());
+        assert!(Isa::parse("rv32id").is_err());
+        assert!(Isa::parse("rv32i_zbx").is_err());
+    }
+}
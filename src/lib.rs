@@ -16,10 +16,24 @@
  */
 
 pub mod cpu;
+pub mod csr;
 pub mod memory;
+pub mod monitor;
 pub mod loader;
 pub mod tools;
 pub mod debugger;
+pub mod elf;
+pub mod gdbstub;
 pub mod inst;
 pub mod register;
-pub mod devices;
\ No newline at end of file
+pub mod rvc;
+pub mod symbols;
+pub mod trap;
+pub mod devices;
+pub mod disasm;
+pub mod fpu;
+pub mod isa;
+pub mod mmu;
+pub mod pmp;
+pub mod error;
+pub mod syscall;
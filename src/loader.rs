@@ -15,43 +15,241 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::elf::{self, ElfFile};
+use crate::isa::Xlen;
+use crate::memory::Memory;
+use crate::symbols::SymbolTable;
 use std::fs::File;
 use std::io::Read;
 
+// 原始二进制文件的加载地址和入口点
+const RAW_LOAD_ADDR: u32 = 0x80000000;
+
+pub struct LoadedProgram {
+    pub entry: u64,
+    pub symbols: SymbolTable, // 原始二进制文件没有符号
+    pub end: u64,             // 程序映像（含 BSS）的结束地址，brk 从这里开始
+}
+
 pub struct Loader;
 
+impl Default for Loader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Loader {
     pub fn new() -> Self {
         Self
     }
 
-    pub fn load_program(&self, memory: &mut crate::memory::Memory, filename: &str) -> std::io::Result<()> {
+    // ELF32/ELF64 必须与 xlen 一致，否则在写入任何段之前返回错误
+    pub fn load_program(
+        &self,
+        memory: &mut Memory,
+        filename: &str,
+        xlen: Xlen,
+    ) -> std::io::Result<LoadedProgram> {
         let mut file = File::open(filename)?;
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer)?;
-        
+
         println!("Loading program, size: {} bytes", buffer.len());
-        println!("First few bytes: {:02x} {:02x} {:02x} {:02x}", 
-            buffer.get(0).unwrap_or(&0), 
+        println!(
+            "First few bytes: {:02x} {:02x} {:02x} {:02x}",
+            buffer.first().unwrap_or(&0),
             buffer.get(1).unwrap_or(&0),
             buffer.get(2).unwrap_or(&0),
-            buffer.get(3).unwrap_or(&0));
-        
+            buffer.get(3).unwrap_or(&0)
+        );
+
+        if elf::is_elf(&buffer) {
+            self.load_elf(memory, &buffer, xlen)
+        } else {
+            self.load_raw(memory, &buffer)
+        }
+    }
+
+    fn load_elf(
+        &self,
+        memory: &mut Memory,
+        buffer: &[u8],
+        xlen: Xlen,
+    ) -> std::io::Result<LoadedProgram> {
+        let elf = ElfFile::parse(buffer)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        if elf.xlen != xlen {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "ELF class does not match XLEN, use --isa to select rv32 or rv64",
+            ));
+        }
+
+        println!("Loading ELF program, entry point: 0x{:08x}", elf.entry);
+        for segment in &elf.segments {
+            println!(
+                "Loading segment at 0x{:08x}: filesz=0x{:x}, memsz=0x{:x}",
+                segment.paddr, segment.filesz, segment.memsz
+            );
+
+            memory
+                .write_bytes(segment.paddr as usize, elf.segment_data(segment))
+                .map_err(std::io::Error::other)?;
+
+            // BSS 部分清零
+            if segment.memsz > segment.filesz {
+                let bss_addr = segment.paddr.wrapping_add(segment.filesz);
+                let zeros = vec![0u8; (segment.memsz - segment.filesz) as usize];
+                memory
+                    .write_bytes(bss_addr as usize, &zeros)
+                    .map_err(std::io::Error::other)?;
+            }
+        }
+        println!("Program loaded successfully");
+
+        // 符号表只用于调试输出，解析失败不影响运行
+        let symbols = match elf.symbols() {
+            Ok(symbols) => {
+                println!("Loaded {} symbols", symbols.len());
+                symbols
+            }
+            Err(e) => {
+                println!("Failed to load symbols: {}", e);
+                SymbolTable::default()
+            }
+        };
+
+        let end = elf
+            .segments
+            .iter()
+            .map(|s| s.paddr as u64 + s.memsz as u64)
+            .max()
+            .unwrap_or(elf.entry);
+
+        Ok(LoadedProgram {
+            entry: elf.entry,
+            symbols,
+            end,
+        })
+    }
+
+    fn load_raw(&self, memory: &mut Memory, buffer: &[u8]) -> std::io::Result<LoadedProgram> {
         // 从 0x80000000 开始加载程序
-        println!("Attempting to load program at 0x80000000");
-        match memory.write_bytes(0x80000000, &buffer) {
+        println!("Attempting to load program at 0x{:08x}", RAW_LOAD_ADDR);
+        match memory.write_bytes(RAW_LOAD_ADDR as usize, buffer) {
             Ok(_) => println!("Program loaded successfully"),
             Err(e) => {
                 println!("Failed to load program: {}", e);
-                return Err(std::io::Error::new(std::io::ErrorKind::Other, e));
+                return Err(std::io::Error::other(e));
             }
         }
-        
+
+        // 程序入口点固定为加载地址
+        Ok(LoadedProgram {
+            entry: RAW_LOAD_ADDR as u64,
+            symbols: SymbolTable::default(),
+            end: RAW_LOAD_ADDR as u64 + buffer.len() as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 构造一个只含一个 PT_LOAD 段的最小 ELF 文件
+    fn build_elf(entry: u32, paddr: u32, code: &[u8], memsz: u32) -> Vec<u8> {
+        let mut elf = vec![0u8; 52 + 32];
+        elf[..4].copy_from_slice(&[0x7f, b'E', b'L', b'F']);
+        elf[4] = 1; // ELFCLASS32
+        elf[5] = 1; // ELFDATA2LSB
+        elf[6] = 1; // EV_CURRENT
+        elf[16..18].copy_from_slice(&2u16.to_le_bytes()); // ET_EXEC
+        elf[18..20].copy_from_slice(&0xF3u16.to_le_bytes()); // EM_RISCV
+        elf[24..28].copy_from_slice(&entry.to_le_bytes());
+        elf[28..32].copy_from_slice(&52u32.to_le_bytes()); // e_phoff
+        elf[42..44].copy_from_slice(&32u16.to_le_bytes()); // e_phentsize
+        elf[44..46].copy_from_slice(&1u16.to_le_bytes()); // e_phnum
+
+        let ph = 52;
+        elf[ph..ph + 4].copy_from_slice(&1u32.to_le_bytes()); // PT_LOAD
+        elf[ph + 4..ph + 8].copy_from_slice(&84u32.to_le_bytes()); // p_offset
+        elf[ph + 8..ph + 12].copy_from_slice(&0x1000u32.to_le_bytes()); // p_vaddr
+        elf[ph + 12..ph + 16].copy_from_slice(&paddr.to_le_bytes()); // p_paddr
+        elf[ph + 16..ph + 20].copy_from_slice(&(code.len() as u32).to_le_bytes());
+        elf[ph + 20..ph + 24].copy_from_slice(&memsz.to_le_bytes());
+        elf.extend_from_slice(code);
+        elf
+    }
+
+    #[test]
+    fn test_load_elf_segments_and_bss() -> std::io::Result<()> {
+        let code = [0x13, 0x05, 0xa0, 0x02]; // addi a0, zero, 42
+        let elf = build_elf(0x80000100, 0x80000100, &code, 16);
+        let name = format!("riscv_emu_loader_test_{}.elf", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, &elf)?;
+
+        let mut memory = Memory::new(0x03000000);
+        memory.write_bytes(0x80000100, &[0xff; 16]).unwrap();
+
+        // ELF 类别与 XLEN 不一致时不写入任何段
+        let loader = Loader::new();
+        let path = path.to_str().unwrap();
+        match loader.load_program(&mut memory, path, Xlen::Rv64) {
+            Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidData),
+            Ok(_) => panic!("ELF32 loaded as RV64"),
+        }
+        assert_eq!(memory.read_bytes(0x80000100, 4).unwrap(), &[0xff; 4]);
+
+        let program = loader.load_program(&mut memory, path, Xlen::Rv32)?;
+        std::fs::remove_file(path)?;
+
+        assert_eq!(program.entry, 0x80000100);
+        assert_eq!(program.end, 0x80000110);
+        // 段按 p_paddr 放置，而不是 p_vaddr
+        assert_eq!(memory.read_bytes(0x80000100, 4).unwrap(), &code);
+        assert_eq!(memory.read_bytes(0x80000104, 12).unwrap(), &[0; 12]);
         Ok(())
     }
 
-    pub fn get_entry_point(&self) -> u32 {
-        // 程序入口点固定为 0x80000000
-        0x80000000
+    #[test]
+    fn test_parse_elf64() {
+        let code = [0x13, 0x05, 0xa0, 0x02]; // addi a0, zero, 42
+        let mut elf = vec![0u8; 64 + 56];
+        elf[..4].copy_from_slice(&[0x7f, b'E', b'L', b'F']);
+        elf[4] = 2; // ELFCLASS64
+        elf[5] = 1; // ELFDATA2LSB
+        elf[18..20].copy_from_slice(&0xF3u16.to_le_bytes()); // EM_RISCV
+        elf[24..32].copy_from_slice(&0x80000000u64.to_le_bytes()); // e_entry
+        elf[32..40].copy_from_slice(&64u64.to_le_bytes()); // e_phoff
+        elf[54..56].copy_from_slice(&56u16.to_le_bytes()); // e_phentsize
+        elf[56..58].copy_from_slice(&1u16.to_le_bytes()); // e_phnum
+
+        let ph = 64;
+        elf[ph..ph + 4].copy_from_slice(&1u32.to_le_bytes()); // PT_LOAD
+        elf[ph + 8..ph + 16].copy_from_slice(&120u64.to_le_bytes()); // p_offset
+        elf[ph + 24..ph + 32].copy_from_slice(&0x80000000u64.to_le_bytes()); // p_paddr
+        elf[ph + 32..ph + 40].copy_from_slice(&4u64.to_le_bytes()); // p_filesz
+        elf[ph + 40..ph + 48].copy_from_slice(&8u64.to_le_bytes()); // p_memsz
+        elf.extend_from_slice(&code);
+
+        let file = ElfFile::parse(&elf).unwrap();
+        assert_eq!(file.xlen, Xlen::Rv64);
+        assert_eq!(file.entry, 0x80000000);
+        assert_eq!(file.segments[0].memsz, 8);
+        assert_eq!(file.segment_data(&file.segments[0]), &code);
+
+        // 超出 32 位物理地址空间的段
+        elf[ph + 24..ph + 32].copy_from_slice(&0x1_0000_0000u64.to_le_bytes());
+        assert!(ElfFile::parse(&elf).is_err());
+    }
+
+    #[test]
+    fn test_reject_non_riscv_elf() {
+        let mut elf = build_elf(0x80000000, 0x80000000, &[], 0);
+        elf[18..20].copy_from_slice(&0x3Eu16.to_le_bytes()); // EM_X86_64
+        assert!(ElfFile::parse(&elf).is_err());
     }
-} 
\ No newline at end of file
+}
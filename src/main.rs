@@ -15,8 +15,12 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use riscv_emu::cpu::{self, StopReason};
+use riscv_emu::gdbstub::GdbStub;
+use riscv_emu::isa::{Isa, DEFAULT_ISA};
+use riscv_emu::memory::{self, Memory, Region};
+use riscv_emu::monitor;
 use std::env;
-use riscv_emu::cpu;
 
 fn print_usage(program: &str) {
     eprintln!("Usage: {} <program-file> [options]", program);
@@ -24,7 +28,21 @@ fn print_usage(program: &str) {
     eprintln!("  --no-itrace    Disable instruction trace");
     eprintln!("  --no-mtrace    Disable memory trace");
     eprintln!("  --no-regtrace  Disable register trace");
-    eprintln!("  --step         Enable single-step execution");
+    eprintln!("  --step         Enable the interactive single-step debugger");
+    eprintln!("  --gdb <port>   Wait for a GDB connection on the given TCP port");
+    eprintln!(
+        "  --isa <string> XLEN and enabled extensions (default: {})",
+        DEFAULT_ISA
+    );
+    eprintln!("  --limit <n>    Stop after executing n instructions");
+    eprintln!("  --region <kind:base:size[:perms]>");
+    eprintln!("                 Add a ram/rom/mmio region, e.g. rom:0x1000:64K:rx (repeatable,");
+    eprintln!("                 replaces the default memory map)");
+    eprintln!("  --sandbox <dir> Host directory the program may open files in");
+    eprintln!("  --pmp-strict   Deny S/U-mode accesses that match no PMP entry, even when every");
+    eprintln!("                 entry is off (default: allow them while PMP is unconfigured)");
+    eprintln!("  --no-syscalls  Deliver ECALL to the guest trap handler instead of emulating");
+    eprintln!("                 riscv-pk system calls");
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -36,26 +54,77 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let program_file = &args[1];
 
-    // 创建 CPU 实例，分配足够的内存
-    // 代码段：0x00000000-0x0FFFFFFF (256MB)
-    // 数据段：0x01000000-0x01FFFFFF (16MB)
-    // 外设段：0x02000000-0x02FFFFFF (16MB)
-    let memory_size = 0x03000000;  // 48MB total
-    let mut cpu = cpu::Cpu::new(memory_size);
-
     // 默认启用所有跟踪
     let mut enable_itrace = true;
     let mut enable_mtrace = true;
     let mut enable_regtrace = true;
     let mut enable_step = false;
+    let mut gdb_port: Option<u16> = None;
+    let mut isa = Isa::default();
+    let mut limit: Option<u64> = None;
+    let mut regions: Vec<Region> = Vec::new();
+    let mut sandbox: Option<String> = None;
+    let mut host_syscalls = true;
+    let mut pmp_strict = false;
 
     // 处理命令行选项
-    for arg in &args[2..] {
+    let mut options = args[2..].iter();
+    while let Some(arg) = options.next() {
         match arg.as_str() {
             "--no-itrace" => enable_itrace = false,
             "--no-mtrace" => enable_mtrace = false,
             "--no-regtrace" => enable_regtrace = false,
             "--step" => enable_step = true,
+            "--no-syscalls" => host_syscalls = false,
+            "--pmp-strict" => pmp_strict = true,
+            "--gdb" => match options.next().and_then(|p| p.parse().ok()) {
+                Some(port) => gdb_port = Some(port),
+                None => {
+                    eprintln!("--gdb requires a port number");
+                    print_usage(&args[0]);
+                    std::process::exit(1);
+                }
+            },
+            "--isa" => match options.next().map(|s| Isa::parse(s)) {
+                Some(Ok(parsed)) => isa = parsed,
+                Some(Err(e)) => {
+                    eprintln!("Invalid ISA string: {}", e);
+                    std::process::exit(1);
+                }
+                None => {
+                    eprintln!("--isa requires an ISA string");
+                    print_usage(&args[0]);
+                    std::process::exit(1);
+                }
+            },
+            "--limit" => match options.next().and_then(|n| n.parse().ok()) {
+                Some(n) => limit = Some(n),
+                None => {
+                    eprintln!("--limit requires a number");
+                    print_usage(&args[0]);
+                    std::process::exit(1);
+                }
+            },
+            "--region" => match options.next().map(|s| Region::parse(s)) {
+                Some(Ok(region)) => regions.push(region),
+                Some(Err(e)) => {
+                    eprintln!("Invalid memory region: {}", e);
+                    std::process::exit(1);
+                }
+                None => {
+                    eprintln!("--region requires a region description");
+                    print_usage(&args[0]);
+                    std::process::exit(1);
+                }
+            },
+            "--sandbox" => match options.next() {
+                Some(dir) => sandbox = Some(dir.clone()),
+                None => {
+                    eprintln!("--sandbox requires a directory");
+                    print_usage(&args[0]);
+                    std::process::exit(1);
+                }
+            },
             _ => {
                 eprintln!("Unknown option: {}", arg);
                 print_usage(&args[0]);
@@ -64,13 +133,45 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    // 默认内存映射：
+    // 代码段：0x80000000-0x82FFFFFF (48MB)
+    // 数据段：0x01000000-0x01FFFFFF (16MB)
+    // 外设段：0x02000000-0x02FFFFFF (16MB)
+    if regions.is_empty() {
+        regions = memory::default_regions(0x80000000, 0x03000000);
+    }
+    let memory = match Memory::with_regions(&regions) {
+        Ok(memory) => memory,
+        Err(e) => {
+            eprintln!("Invalid memory map: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut cpu = cpu::Cpu::with_memory(memory);
+    cpu.set_isa(isa);
+
     // 设置调试选项
     cpu.set_itrace(enable_itrace);
     cpu.set_mtrace(enable_mtrace);
     cpu.set_regtrace(enable_regtrace);
-    cpu.set_single_step(enable_step);
+    // GDB 接管执行控制时不使用单步等待
+    cpu.set_single_step(enable_step && gdb_port.is_none());
+    cpu.set_host_syscalls(host_syscalls);
+    cpu.set_pmp_strict(pmp_strict);
+
+    // 客户机的文件操作限制在沙箱目录中
+    if let Some(dir) = &sandbox {
+        if let Err(e) = cpu.set_sandbox(std::path::Path::new(dir)) {
+            eprintln!("Invalid sandbox directory {}: {}", dir, e);
+            std::process::exit(1);
+        }
+    }
 
     println!("RISC-V Emulator Starting...");
+    println!("ISA: {}", isa);
+    for region in &regions {
+        println!("Memory: {}", region);
+    }
     println!("Loading program: {}", program_file);
 
     // 加载程序
@@ -81,20 +182,44 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         cpu.show_registers();
     }
 
-    // 执行程序
-    loop {
-        match cpu.step() {
-            Ok(()) => {
-                if enable_regtrace {
-                    cpu.show_registers();
-                }
-            }
-            Err(e) => {
-                println!("Execution error: {}", e);
-                break;
-            }
-        }
+    // 由 GDB 控制执行，程序退出时同样以客户机的退出码作为进程的退出状态
+    if let Some(port) = gdb_port {
+        let mut stub = GdbStub::listen(port)?;
+        stub.run(&mut cpu)?;
+        std::process::exit(cpu.exit_code().map_or(0, |code| code as i32));
     }
 
-    Ok(())
+    // 单步模式下在第一条指令执行前进入命令行
+    if enable_step && !monitor::prompt(&mut cpu) {
+        return Ok(());
+    }
+
+    // 执行程序，客户机的退出码作为进程的退出状态
+    let status = match cpu.run(limit) {
+        StopReason::Exited(code) => code as i32,
+        StopReason::Breakpoint(_) | StopReason::Quit => 0,
+        StopReason::Halted => {
+            println!("[SYSTEM] Hart halted in WFI with no interrupts enabled");
+            0
+        }
+        StopReason::InstructionLimit => {
+            println!("[SYSTEM] Instruction limit reached");
+            1
+        }
+        StopReason::Fault(e) => {
+            println!("Execution error: {}", e);
+            1
+        }
+    };
+
+    // 内存占用：只统计实际分配的页
+    let stats = cpu.memory().page_stats();
+    println!(
+        "[SYSTEM] Memory footprint: {} KiB ({} of {} pages allocated)",
+        stats.resident_bytes() / 1024,
+        stats.resident_pages,
+        stats.total_pages
+    );
+
+    std::process::exit(status);
 }
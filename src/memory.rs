@@ -15,200 +15,572 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::devices::Devices;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::devices::{self, Devices, MmioDevice};
+use crate::error::EmuError;
+
+// 内存区域的访问权限
+pub const PERM_R: u8 = 1 << 0;
+pub const PERM_W: u8 = 1 << 1;
+pub const PERM_X: u8 = 1 << 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RegionKind {
+    Ram,
+    Rom,  // 程序只能读取，加载器仍可写入初始内容
+    Mmio, // 不分配存储，访问转发到设备
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub base: usize,
+    pub size: usize,
+    pub kind: RegionKind,
+    pub perms: u8,
+}
+
+impl Region {
+    pub fn new(base: usize, size: usize, kind: RegionKind, perms: u8) -> Self {
+        Self {
+            base,
+            size,
+            kind,
+            perms,
+        }
+    }
+
+    // 解析形如 ram:0x80000000:64M[:rwx] 的描述，省略权限时按类型取默认值
+    pub fn parse(spec: &str) -> Result<Self, &'static str> {
+        let fields: Vec<&str> = spec.split(':').collect();
+        if fields.len() < 3 || fields.len() > 4 {
+            return Err("Region must be kind:base:size[:perms]");
+        }
+        let kind = match fields[0].to_ascii_lowercase().as_str() {
+            "ram" => RegionKind::Ram,
+            "rom" => RegionKind::Rom,
+            "mmio" => RegionKind::Mmio,
+            _ => return Err("Region kind must be ram, rom or mmio"),
+        };
+        let base = parse_number(fields[1]).ok_or("Invalid region base")?;
+        let size = parse_number(fields[2]).ok_or("Invalid region size")?;
+        let perms = match fields.get(3) {
+            Some(perms) => perms.chars().try_fold(0, |bits, c| match c {
+                'r' => Ok(bits | PERM_R),
+                'w' => Ok(bits | PERM_W),
+                'x' => Ok(bits | PERM_X),
+                '-' => Ok(bits),
+                _ => Err("Region permissions must be made of r, w, x"),
+            })?,
+            None => match kind {
+                RegionKind::Ram => PERM_R | PERM_W | PERM_X,
+                RegionKind::Rom => PERM_R | PERM_X,
+                RegionKind::Mmio => PERM_R | PERM_W,
+            },
+        };
+        Ok(Self::new(base, size, kind, perms))
+    }
+
+    pub fn end(&self) -> usize {
+        self.base + self.size
+    }
+
+    fn contains(&self, addr: usize, len: usize) -> bool {
+        addr >= self.base && len <= self.size && addr - self.base <= self.size - len
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            RegionKind::Ram => "ram",
+            RegionKind::Rom => "rom",
+            RegionKind::Mmio => "mmio",
+        };
+        let perm = |bit, c| if self.perms & bit != 0 { c } else { '-' };
+        write!(
+            f,
+            "{:<4} 0x{:08x}-0x{:08x} {}{}{}",
+            kind,
+            self.base,
+            self.end() - 1,
+            perm(PERM_R, 'r'),
+            perm(PERM_W, 'w'),
+            perm(PERM_X, 'x')
+        )
+    }
+}
+
+// 十六进制或十进制数，可带 K/M/G 后缀
+fn parse_number(text: &str) -> Option<usize> {
+    let (digits, scale) = match text.chars().last()?.to_ascii_uppercase() {
+        'K' => (&text[..text.len() - 1], 1 << 10),
+        'M' => (&text[..text.len() - 1], 1 << 20),
+        'G' => (&text[..text.len() - 1], 1 << 30),
+        _ => (text, 1),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => usize::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    value.checked_mul(scale)
+}
+
+// 默认布局：base 起的 RAM、0x01000000 起 16 MiB 数据 RAM、0x02000000 起 16 MiB 外设窗口
+pub fn default_regions(ram_base: usize, ram_size: usize) -> Vec<Region> {
+    vec![
+        Region::new(
+            ram_base,
+            ram_size,
+            RegionKind::Ram,
+            PERM_R | PERM_W | PERM_X,
+        ),
+        Region::new(0x01000000, 0x01000000, RegionKind::Ram, PERM_R | PERM_W),
+        Region::new(0x02000000, 0x01000000, RegionKind::Mmio, PERM_R | PERM_W),
+    ]
+}
+
+// 后备存储按页延迟分配，未写入过的页读出全零
+pub const PAGE_SIZE: usize = 4096;
+
+static ZERO_PAGE: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
+
+type Page = Box<[u8; PAGE_SIZE]>;
+
+// 页级内存占用统计
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PageStats {
+    pub total_pages: usize,    // 声明的 RAM/ROM 覆盖的页数
+    pub resident_pages: usize, // 已分配的页数
+}
+
+impl PageStats {
+    pub fn resident_bytes(&self) -> usize {
+        self.resident_pages * PAGE_SIZE
+    }
+}
+
+// 一个区域及其后备存储，只记录写入过的页，MMIO 区域没有页
+struct Bank {
+    region: Region,
+    pages: HashMap<usize, Page>, // 页号 -> 页
+}
+
+impl Bank {
+    fn new(region: Region) -> Self {
+        Self {
+            region,
+            pages: HashMap::new(),
+        }
+    }
+
+    fn stats(&self) -> PageStats {
+        let total_pages = match self.region.kind {
+            RegionKind::Mmio => 0,
+            _ => self.region.size.div_ceil(PAGE_SIZE),
+        };
+        PageStats {
+            total_pages,
+            resident_pages: self.pages.len(),
+        }
+    }
+
+    // 从区域内偏移 offset 开始读取，可以跨页
+    fn read(&self, mut offset: usize, buf: &mut [u8]) {
+        let mut done = 0;
+        while done < buf.len() {
+            let (index, start) = (offset / PAGE_SIZE, offset % PAGE_SIZE);
+            let len = (PAGE_SIZE - start).min(buf.len() - done);
+            let page = self.pages.get(&index).map_or(&ZERO_PAGE, |p| &**p);
+            buf[done..done + len].copy_from_slice(&page[start..start + len]);
+            done += len;
+            offset += len;
+        }
+    }
+
+    // 写入时分配页；向未分配的页写零不需要分配
+    fn write(&mut self, mut offset: usize, data: &[u8]) {
+        let mut done = 0;
+        while done < data.len() {
+            let (index, start) = (offset / PAGE_SIZE, offset % PAGE_SIZE);
+            let len = (PAGE_SIZE - start).min(data.len() - done);
+            let chunk = &data[done..done + len];
+            if self.pages.contains_key(&index) || chunk.iter().any(|&b| b != 0) {
+                let page = self
+                    .pages
+                    .entry(index)
+                    .or_insert_with(|| Box::new([0; PAGE_SIZE]));
+                page[start..start + len].copy_from_slice(chunk);
+            }
+            done += len;
+            offset += len;
+        }
+    }
+}
 
 pub struct Memory {
-    data: Vec<u8>,
+    banks: Vec<Bank>,
     devices: Devices,
+    reservation: Option<(usize, usize)>, // LR/SC 保留的地址和长度
 }
 
 impl Memory {
+    // 0x80000000 起 size 字节的 RAM 加上默认的数据段和外设窗口
     pub fn new(size: usize) -> Self {
-        Self {
-            data: vec![0; size],
-            devices: Devices::new(),
+        Self::with_regions(&default_regions(0x80000000, size)).unwrap()
+    }
+
+    // 按给定的内存映射创建，RAM/ROM 区域的页在首次写入时才分配。
+    // 默认外设只挂载在完全落在 MMIO 区域内的地址上
+    pub fn with_regions(regions: &[Region]) -> Result<Self, &'static str> {
+        let mut banks: Vec<Bank> = Vec::new();
+        for region in regions {
+            if region.size == 0 {
+                return Err("Memory region size must be non-zero");
+            }
+            if region.base.checked_add(region.size).is_none() {
+                return Err("Memory region exceeds the address space");
+            }
+            if banks
+                .iter()
+                .any(|b| region.base < b.region.end() && b.region.base < region.end())
+            {
+                return Err("Memory regions overlap");
+            }
+            banks.push(Bank::new(*region));
+        }
+        let mut memory = Self {
+            banks,
+            devices: Devices::empty(),
+            reservation: None,
+        };
+        for (base, device) in devices::default_devices() {
+            let mapped = memory.banks.iter().any(|b| {
+                b.region.kind == RegionKind::Mmio && b.region.contains(base, device.size())
+            });
+            if mapped {
+                memory.devices.register(base, device).unwrap();
+            }
         }
+        Ok(memory)
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.banks.iter().map(|b| &b.region)
+    }
+
+    // 每个区域的页统计，MMIO 区域的页数为 0
+    pub fn region_stats(&self) -> impl Iterator<Item = (&Region, PageStats)> {
+        self.banks.iter().map(|b| (&b.region, b.stats()))
+    }
+
+    // 所有区域合计的页统计
+    pub fn page_stats(&self) -> PageStats {
+        self.banks.iter().fold(PageStats::default(), |total, b| {
+            let stats = b.stats();
+            PageStats {
+                total_pages: total.total_pages + stats.total_pages,
+                resident_pages: total.resident_pages + stats.resident_pages,
+            }
+        })
     }
 
-    // 将虚拟地址转换为内存索引
+    // 将物理地址转换为所在 RAM/ROM 区域及区域内偏移，访问不能跨越区域边界
     #[inline]
-    fn translate_address(&self, addr: usize) -> Result<usize, &'static str> {
-        match addr {
-            // 代码段：0x80000000-0x8FFFFFFF -> 0x00000000-0x0FFFFFFF
-            addr if addr >= 0x80000000 && addr < 0x90000000 => Ok(addr - 0x80000000),
-            // 数据段：0x01000000-0x01FFFFFF -> 保持原地址
-            addr if addr >= 0x01000000 && addr < 0x02000000 => Ok(addr),
-            // 外设段：0x02000000-0x02FFFFFF -> 转发到设备
-            addr if addr >= 0x02000000 && addr < 0x03000000 => {
-                Err("Device address") // 特殊错误，表示这是设备地址
+    fn translate_address(&self, addr: usize, len: usize) -> Result<(usize, usize), EmuError> {
+        match self.banks.iter().position(|b| b.region.contains(addr, len)) {
+            // MMIO 区域中没有挂载设备的地址
+            Some(index) if self.banks[index].region.kind == RegionKind::Mmio => {
+                Err(EmuError::AccessFault { addr: addr as u64 })
             }
-            _ => {
+            Some(index) => Ok((index, addr - self.banks[index].region.base)),
+            None => {
                 println!("Invalid memory access at address 0x{:08x}", addr);
-                Err("Invalid memory access: address out of valid ranges")
+                Err(EmuError::AccessFault { addr: addr as u64 })
             }
         }
     }
 
+    // 取指前检查所在区域是否可执行，设备不可执行
+    pub fn executable(&self, addr: usize) -> bool {
+        !self.devices.contains(addr)
+            && self.banks.iter().any(|b| {
+                b.region.contains(addr, 1)
+                    && b.region.kind != RegionKind::Mmio
+                    && b.region.perms & PERM_X != 0
+            })
+    }
+
     #[inline]
-    pub fn vread(&mut self, addr: usize, len: usize) -> Result<u32, &'static str> {
+    pub fn vread(&mut self, addr: usize, len: usize) -> Result<u32, EmuError> {
         // 首先检查长度是否合法
         match len {
             1 | 2 | 4 => (),
-            _ => return Err("Invalid memory access length"),
+            _ => return Err(EmuError::AccessFault { addr: addr as u64 }),
         }
 
         // 检查地址对齐
-        if addr % len != 0 {
-            return Err("Misaligned memory access");
-        }
-
-        // 尝试地址转换
-        match self.translate_address(addr) {
-            Ok(physical_addr) => {
-                // 内存访问
-                if physical_addr + len > self.data.len() {
-                    println!(
-                        "Physical memory access out of bounds: addr={:08x}, len={}, data_len={}",
-                        physical_addr,
-                        len,
-                        self.data.len()
-                    );
-                    return Err("Memory read out of bounds");
-                }
-
-                // 读取数据
-                let slice = &self.data[physical_addr..physical_addr + len];
-                let value = match len {
-                    1 => slice[0] as u32,
-                    2 => u16::from_le_bytes(slice.try_into().unwrap()) as u32,
-                    4 => u32::from_le_bytes(slice.try_into().unwrap()),
-                    _ => unreachable!(),
-                };
-                Ok(value)
-            }
-            Err("Device address") => {
-                // 设备访问
-                self.devices.read(addr, len)
-            }
-            Err(e) => Err(e),
+        if !addr.is_multiple_of(len) {
+            return Err(EmuError::MisalignedAccess {
+                addr: addr as u64,
+                size: len,
+            });
         }
+
+        // 已挂载的设备优先
+        if self.devices.contains(addr) {
+            return self.devices.read(addr, len);
+        }
+
+        let (index, offset) = self.translate_address(addr, len)?;
+        let bank = &self.banks[index];
+        if bank.region.perms & PERM_R == 0 {
+            return Err(EmuError::AccessFault { addr: addr as u64 });
+        }
+
+        // 读取数据
+        let mut bytes = [0; 4];
+        bank.read(offset, &mut bytes[..len]);
+        Ok(u32::from_le_bytes(bytes))
     }
 
     #[inline]
-    pub fn vwrite(&mut self, addr: usize, value: u32, len: usize) -> Result<(), &'static str> {
+    pub fn vwrite(&mut self, addr: usize, value: u32, len: usize) -> Result<(), EmuError> {
         // 首先检查长度是否合法
         match len {
             1 | 2 | 4 => (),
-            _ => return Err("Invalid memory access length"),
+            _ => return Err(EmuError::AccessFault { addr: addr as u64 }),
         }
 
         // 检查地址对齐
-        if addr % len != 0 {
-            return Err("Misaligned memory access");
-        }
-
-        // 尝试地址转换
-        match self.translate_address(addr) {
-            Ok(physical_addr) => {
-                // 内存访问
-                if physical_addr + len > self.data.len() {
-                    println!(
-                        "Physical memory access out of bounds: addr={:08x}, len={}, data_len={}",
-                        physical_addr,
-                        len,
-                        self.data.len()
-                    );
-                    return Err("Memory write out of bounds");
-                }
-
-                // 写入数据
-                let bytes = value.to_le_bytes();
-                self.data[physical_addr..physical_addr + len].copy_from_slice(&bytes[..len]);
-                Ok(())
-            }
-            Err("Device address") => {
-                // 设备访问
-                self.devices.write(addr, value, len)
-            }
-            Err(e) => Err(e),
-        }
-    }
-
-    pub fn write_bytes(&mut self, addr: usize, data: &[u8]) -> Result<(), &'static str> {
-        // 首先检查数据大小是否合理
-        if data.len() > 0x10000000 {
-            // 代码段最大 256MB
-            println!("Data too large: {} bytes", data.len());
-            return Err("Data size exceeds maximum allowed");
-        }
-
-        // 尝试地址转换
-        match self.translate_address(addr) {
-            Ok(physical_addr) => {
-                // 内存访问
-                if physical_addr + data.len() > self.data.len() {
-                    println!(
-                        "Physical memory access out of bounds: addr={:08x}, len={}, data_len={}",
-                        physical_addr,
-                        data.len(),
-                        self.data.len()
-                    );
-                    return Err("Memory write out of bounds");
-                }
-
-                println!(
-                    "Writing {} bytes to physical address 0x{:08x}",
-                    data.len(),
-                    physical_addr
-                );
-                self.data[physical_addr..physical_addr + data.len()].copy_from_slice(data);
-                Ok(())
-            }
-            Err("Device address") => {
-                // 不允许对设备进行批量写入
-                Err("Cannot write bytes to device address")
-            }
-            Err(e) => Err(e),
-        }
-    }
-
-    pub fn read_bytes(&self, addr: usize, len: usize) -> Result<&[u8], &'static str> {
-        // 首先检查长度是否合理
-        if len > 0x10000000 {
-            // 代码段最大 256MB
-            println!("Read length too large: {} bytes", len);
-            return Err("Read length exceeds maximum allowed");
-        }
-
-        // 尝试地址转换
-        match self.translate_address(addr) {
-            Ok(physical_addr) => {
-                // 内存访问
-                if physical_addr + len > self.data.len() {
-                    println!(
-                        "Physical memory access out of bounds: addr={:08x}, len={}, data_len={}",
-                        physical_addr,
-                        len,
-                        self.data.len()
-                    );
-                    return Err("Memory read out of bounds");
-                }
-
-                println!(
-                    "Reading {} bytes from physical address 0x{:08x}",
-                    len, physical_addr
-                );
-                Ok(&self.data[physical_addr..physical_addr + len])
-            }
-            Err("Device address") => {
-                // 不允许对设备进行批量读取
-                Err("Cannot read bytes from device address")
+        if !addr.is_multiple_of(len) {
+            return Err(EmuError::MisalignedAccess {
+                addr: addr as u64,
+                size: len,
+            });
+        }
+
+        // 写入与保留地址重叠时使保留失效
+        if let Some((reserved, size)) = self.reservation {
+            if addr < reserved + size && reserved < addr + len {
+                self.reservation = None;
             }
-            Err(e) => Err(e),
         }
+
+        // 已挂载的设备优先
+        if self.devices.contains(addr) {
+            return self.devices.write(addr, value, len);
+        }
+
+        let (index, offset) = self.translate_address(addr, len)?;
+        let bank = &mut self.banks[index];
+        if bank.region.kind == RegionKind::Rom || bank.region.perms & PERM_W == 0 {
+            return Err(EmuError::AccessFault { addr: addr as u64 });
+        }
+
+        // 写入数据
+        bank.write(offset, &value.to_le_bytes()[..len]);
+        Ok(())
+    }
+
+    // 加载器使用的批量写入，不检查区域权限，可以写入 ROM
+    // 设备不支持批量读写
+    pub fn write_bytes(&mut self, addr: usize, data: &[u8]) -> Result<(), EmuError> {
+        let (index, offset) = self.translate_address(addr, data.len())?;
+        println!(
+            "Writing {} bytes to physical address 0x{:08x}",
+            data.len(),
+            addr
+        );
+        self.banks[index].write(offset, data);
+        Ok(())
+    }
+
+    pub fn read_bytes(&self, addr: usize, len: usize) -> Result<Vec<u8>, EmuError> {
+        let (index, offset) = self.translate_address(addr, len)?;
+        println!("Reading {} bytes from physical address 0x{:08x}", len, addr);
+        let mut data = vec![0; len];
+        self.banks[index].read(offset, &mut data);
+        Ok(data)
+    }
+
+    // LR：在该字（LR.D 为双字）上建立保留
+    pub fn set_reservation(&mut self, addr: usize, len: usize) {
+        self.reservation = Some((addr, len));
+    }
+
+    // SC：检查保留是否仍然有效，并清除保留
+    pub fn take_reservation(&mut self, addr: usize, len: usize) -> bool {
+        self.reservation.take() == Some((addr, len))
+    }
+
+    pub fn clear_reservation(&mut self) {
+        self.reservation = None;
+    }
+
+    // 在 RAM/ROM 区域以外的任意基地址挂载自定义外设，设备不能遮住声明的内存
+    pub fn register_device(
+        &mut self,
+        base: usize,
+        device: Box<dyn MmioDevice>,
+    ) -> Result<(), EmuError> {
+        let size = device.size();
+        let end = base.saturating_add(size);
+        if self.banks.iter().any(|b| {
+            b.region.kind != RegionKind::Mmio && base < b.region.end() && b.region.base < end
+        }) {
+            return Err(EmuError::DeviceOverlap { base, size });
+        }
+        self.devices.register(base, device)
+    }
+
+    pub fn devices(&self) -> &Devices {
+        &self.devices
     }
 
     pub fn tick_devices(&mut self) {
         self.devices.tick();
     }
+
+    pub fn check_interrupts(&self) -> u32 {
+        self.devices.check_interrupts()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_map() {
+        let rom = Region::parse("rom:0x1000:4K").unwrap();
+        assert_eq!(
+            rom,
+            Region::new(0x1000, 0x1000, RegionKind::Rom, PERM_R | PERM_X)
+        );
+        assert_eq!(rom.to_string(), "rom  0x00001000-0x00001fff r-x");
+        let ram = Region::parse("RAM:0x80000000:64k:rw").unwrap();
+        assert_eq!(ram.size, 0x10000);
+        assert!(Region::parse("flash:0:4K").is_err());
+        assert!(Region::parse("ram:0x1000").is_err());
+        assert!(Region::parse("ram:0:4K:rwz").is_err());
+
+        let overlapping = [rom, Region::new(0x1800, 0x1000, RegionKind::Ram, PERM_R)];
+        assert!(Memory::with_regions(&overlapping).is_err());
+
+        let mut memory = Memory::with_regions(&[rom, ram]).unwrap();
+        // 加载器可以写入 ROM，程序只能读取
+        memory.write_bytes(0x1000, &[0x13, 0, 0, 0]).unwrap();
+        assert_eq!(memory.vread(0x1000, 4).unwrap(), 0x13);
+        assert_eq!(
+            memory.vwrite(0x1000, 0, 4),
+            Err(EmuError::AccessFault { addr: 0x1000 })
+        );
+        assert_eq!(
+            memory.vread(0x1002, 4),
+            Err(EmuError::MisalignedAccess {
+                addr: 0x1002,
+                size: 4
+            })
+        );
+        assert!(memory.executable(0x1000));
+
+        memory.vwrite(0x8000fffc, 42, 4).unwrap();
+        assert_eq!(memory.vread(0x8000fffc, 4).unwrap(), 42);
+        assert!(!memory.executable(0x80000000));
+        // 未声明的地址没有后备存储
+        assert!(memory.vread(0x80010000, 4).is_err());
+        assert!(memory.write_bytes(0x8000fffe, &[0; 4]).is_err());
+    }
+
+    #[test]
+    fn test_access_larger_than_region() {
+        // 从区域起始地址开始、长度超过区域大小的访问不能越界
+        let ram = Region::new(0x1000, 0x1000, RegionKind::Ram, PERM_R | PERM_W);
+        let tiny = Region::new(0x4000, 2, RegionKind::Ram, PERM_R | PERM_W);
+        let mut memory = Memory::with_regions(&[ram, tiny]).unwrap();
+        assert_eq!(
+            memory.write_bytes(0x1000, &[0; 0x2000]),
+            Err(EmuError::AccessFault { addr: 0x1000 })
+        );
+        assert!(memory.read_bytes(0x1000, 0x1001).is_err());
+        assert_eq!(
+            memory.vread(0x4000, 4),
+            Err(EmuError::AccessFault { addr: 0x4000 })
+        );
+        assert_eq!(memory.vread(0x4000, 2).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_devices_follow_mmio_regions() {
+        // 默认映射在 MMIO 窗口中挂载全部外设
+        let memory = Memory::new(0x1000);
+        assert_eq!(memory.devices().iter().count(), 4);
+
+        // 在外设地址上声明 RAM 时不挂载外设，访问落到 RAM
+        let ram = Region::new(0x02000000, 0x1000, RegionKind::Ram, PERM_R | PERM_W);
+        let mut memory = Memory::with_regions(&[ram]).unwrap();
+        assert_eq!(memory.devices().iter().count(), 0);
+        memory.vwrite(0x02000000, 0x41, 4).unwrap();
+        memory.vwrite(0x02000204, 7, 4).unwrap();
+        assert_eq!(memory.vread(0x02000000, 4).unwrap(), 0x41);
+        assert_eq!(memory.vread(0x02000204, 4).unwrap(), 7);
+
+        // 自定义外设也不能遮住 RAM
+        let uart = Box::new(crate::devices::uart::Uart::new());
+        let size = uart.size();
+        assert_eq!(
+            memory.register_device(0x02000800, uart),
+            Err(EmuError::DeviceOverlap {
+                base: 0x02000800,
+                size
+            })
+        );
+
+        // 只覆盖 UART 的 MMIO 区域只挂载 UART
+        let mmio = Region::new(0x02000000, 0x100, RegionKind::Mmio, PERM_R | PERM_W);
+        let memory = Memory::with_regions(&[mmio]).unwrap();
+        let mounted: Vec<_> = memory.devices().iter().map(|(base, _)| base).collect();
+        assert_eq!(mounted, [0x02000000]);
+    }
+
+    #[test]
+    fn test_lazy_pages() {
+        // 4 GiB 的 RAM 只为写入过的页分配存储
+        let ram = Region::new(0x80000000, 1 << 32, RegionKind::Ram, PERM_R | PERM_W);
+        let mut memory = Memory::with_regions(&[ram]).unwrap();
+        let stats = memory.page_stats();
+        assert_eq!(stats.total_pages, 1 << 20);
+        assert_eq!(stats.resident_pages, 0);
+
+        assert_eq!(memory.vread(0x17ffffffc, 4).unwrap(), 0);
+        memory.vwrite(0x17ffffffc, 0xdeadbeef, 4).unwrap();
+        assert_eq!(memory.vread(0x17ffffffc, 4).unwrap(), 0xdeadbeef);
+        // 写零不分配页
+        memory.write_bytes(0x80000000, &[0; 3 * PAGE_SIZE]).unwrap();
+        assert_eq!(memory.page_stats().resident_pages, 1);
+
+        // 跨页的批量读写
+        memory.write_bytes(0x80000ffe, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(
+            memory.read_bytes(0x80000ffc, 8).unwrap(),
+            [0, 0, 1, 2, 3, 4, 0, 0]
+        );
+        let stats = memory.page_stats();
+        assert_eq!(stats.resident_pages, 3);
+        assert_eq!(stats.resident_bytes(), 3 * PAGE_SIZE);
+
+        // 声明 1 TiB 也不预先分配页表
+        let huge = Region::new(1 << 40, 1 << 40, RegionKind::Ram, PERM_R | PERM_W);
+        let mut memory = Memory::with_regions(&[huge]).unwrap();
+        memory.vwrite((1 << 41) - 4, 7, 4).unwrap();
+        assert_eq!(memory.vread((1 << 41) - 4, 4).unwrap(), 7);
+        let stats = memory.page_stats();
+        assert_eq!(stats.total_pages, 1 << 28);
+        assert_eq!(stats.resident_pages, 1);
+    }
 }
@@ -0,0 +1,334 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// Sv32 虚拟地址转换：两级页表遍历和直接映射的软件 TLB
+
+use crate::csr::{CsrFile, Privilege, MSTATUS_MPRV, MSTATUS_MXR, MSTATUS_SUM};
+use crate::error::EmuError;
+use crate::memory::Memory;
+use crate::pmp::Pmp;
+use crate::trap::Exception;
+
+// PTE 标志位
+const PTE_V: u32 = 1 << 0;
+const PTE_R: u32 = 1 << 1;
+const PTE_W: u32 = 1 << 2;
+const PTE_X: u32 = 1 << 3;
+const PTE_U: u32 = 1 << 4;
+const PTE_G: u32 = 1 << 5;
+const PTE_A: u32 = 1 << 6;
+const PTE_D: u32 = 1 << 7;
+
+const PAGE_SHIFT: u32 = 12;
+const TLB_SIZE: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AccessType {
+    Fetch,
+    Load,
+    Store, // AMO 也按写访问检查
+}
+
+impl AccessType {
+    pub fn page_fault(self, addr: u64) -> Exception {
+        match self {
+            AccessType::Fetch => Exception::InstructionPageFault(addr),
+            AccessType::Load => Exception::LoadPageFault(addr),
+            AccessType::Store => Exception::StorePageFault(addr),
+        }
+    }
+
+    pub fn access_fault(self, addr: u64) -> Exception {
+        match self {
+            AccessType::Fetch => Exception::InstructionAccessFault(addr),
+            AccessType::Load => Exception::LoadAccessFault(addr),
+            AccessType::Store => Exception::StoreAccessFault(addr),
+        }
+    }
+}
+
+// 页表遍历失败：PTE 无效或读写 PTE 本身失败
+enum WalkFault {
+    Page,
+    Access,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct TlbEntry {
+    vpn: u32,
+    asid: u32,
+    ppn: u32,   // 叶子 PTE 的 PPN
+    flags: u32, // 叶子 PTE 的低 10 位（含 RSW）
+    level: u32, // 1 表示 4 MiB 大页
+}
+
+// 4 KiB 页按 VPN 索引，大页按 VPN[1] 索引，查找时两个位置都要检查
+fn tlb_index(vpn: u32, level: u32) -> usize {
+    (vpn >> (10 * level)) as usize % TLB_SIZE
+}
+
+impl TlbEntry {
+    // 大页的表项覆盖 1024 个虚拟页
+    fn covers(&self, vpn: u32) -> bool {
+        (self.vpn ^ vpn) >> (10 * self.level) == 0
+    }
+
+    fn physical(&self, vaddr: u32) -> u64 {
+        let offset_bits = PAGE_SHIFT + 10 * self.level;
+        let page = (self.ppn >> (10 * self.level)) as u64;
+        (page << offset_bits) | (vaddr & ((1 << offset_bits) - 1)) as u64
+    }
+}
+
+pub struct Mmu {
+    tlb: [Option<TlbEntry>; TLB_SIZE],
+}
+
+impl Default for Mmu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// MPRV=1 时 M 模式的读写按 MPP 中的特权级转换，取指不受影响
+pub fn effective_privilege(csrs: &CsrFile, access: AccessType) -> Privilege {
+    let mstatus = csrs.mstatus();
+    if access != AccessType::Fetch
+        && csrs.privilege() == Privilege::Machine
+        && mstatus & MSTATUS_MPRV != 0
+    {
+        Privilege::from_bits(mstatus >> 11)
+    } else {
+        csrs.privilege()
+    }
+}
+
+// 检查叶子 PTE 的 R/W/X 和 U 位；MXR 允许读可执行页，SUM 允许 S 模式读写用户页
+fn permitted(flags: u32, access: AccessType, privilege: Privilege, mstatus: u64) -> bool {
+    let allowed = match access {
+        AccessType::Fetch => flags & PTE_X != 0,
+        AccessType::Load => {
+            flags & PTE_R != 0 || (mstatus & MSTATUS_MXR != 0 && flags & PTE_X != 0)
+        }
+        AccessType::Store => flags & PTE_W != 0,
+    };
+    let user_page = flags & PTE_U != 0;
+    let mode_allowed = match privilege {
+        Privilege::User => user_page,
+        Privilege::Supervisor => {
+            !user_page || (access != AccessType::Fetch && mstatus & MSTATUS_SUM != 0)
+        }
+        Privilege::Machine => true,
+    };
+    allowed && mode_allowed
+}
+
+// 从 satp.PPN 指向的根页表开始遍历，返回叶子项和叶子 PTE 的物理地址
+// 读取 PTE 按 S 模式做 PMP 检查，调试器的转换不传入 pmp
+fn walk(
+    memory: &mut Memory,
+    pmp: Option<&Pmp>,
+    satp: u64,
+    vaddr: u32,
+) -> Result<(TlbEntry, u64), WalkFault> {
+    let mut table = (satp & 0x3fffff) << PAGE_SHIFT;
+    for level in (0..2).rev() {
+        let vpn_i = (vaddr >> (PAGE_SHIFT + 10 * level)) & 0x3ff;
+        let pte_addr = table + vpn_i as u64 * 4;
+        if pmp.is_some_and(|pmp| !pmp.check(pte_addr, 4, AccessType::Load, Privilege::Supervisor)) {
+            return Err(WalkFault::Access);
+        }
+        let pte = memory
+            .vread(pte_addr as usize, 4)
+            .map_err(|_| WalkFault::Access)?;
+
+        if pte & PTE_V == 0 || (pte & PTE_R == 0 && pte & PTE_W != 0) {
+            return Err(WalkFault::Page);
+        }
+        let ppn = pte >> 10;
+        if pte & (PTE_R | PTE_X) == 0 {
+            // 指向下一级页表
+            table = (ppn as u64) << PAGE_SHIFT;
+            continue;
+        }
+        // 大页的 PPN[0] 必须为 0
+        if level == 1 && ppn & 0x3ff != 0 {
+            return Err(WalkFault::Page);
+        }
+
+        let entry = TlbEntry {
+            vpn: vaddr >> PAGE_SHIFT,
+            asid: ((satp >> 22) & 0x1ff) as u32,
+            ppn,
+            flags: pte & 0x3ff,
+            level,
+        };
+        return Ok((entry, pte_addr));
+    }
+    // 第 0 级仍然不是叶子
+    Err(WalkFault::Page)
+}
+
+impl Mmu {
+    pub fn new() -> Self {
+        Self {
+            tlb: [None; TLB_SIZE],
+        }
+    }
+
+    // 虚拟地址转换为物理地址；Bare 模式和 M 模式下地址不变
+    pub fn translate(
+        &mut self,
+        memory: &mut Memory,
+        csrs: &CsrFile,
+        vaddr: u64,
+        access: AccessType,
+    ) -> Result<u64, Exception> {
+        let privilege = effective_privilege(csrs, access);
+        if !csrs.paging_enabled() || privilege == Privilege::Machine {
+            return Ok(vaddr);
+        }
+
+        let satp = csrs.satp();
+        let mstatus = csrs.mstatus();
+        let va = vaddr as u32;
+        let vpn = va >> PAGE_SHIFT;
+        let asid = ((satp >> 22) & 0x1ff) as u32;
+
+        // TLB 命中：权限满足、写访问时 D 位已置位才直接使用
+        for level in 0..2 {
+            let index = tlb_index(vpn, level);
+            let Some(entry) = self.tlb[index] else {
+                continue;
+            };
+            if entry.level != level
+                || !entry.covers(vpn)
+                || (entry.asid != asid && entry.flags & PTE_G == 0)
+            {
+                continue;
+            }
+            if permitted(entry.flags, access, privilege, mstatus)
+                && (access != AccessType::Store || entry.flags & PTE_D != 0)
+            {
+                return Ok(entry.physical(va));
+            }
+            // 表项可能已过期，重新遍历页表后再判断
+            self.tlb[index] = None;
+            break;
+        }
+
+        let (mut entry, pte_addr) =
+            walk(memory, Some(csrs.pmp()), satp, va).map_err(|fault| match fault {
+                WalkFault::Page => access.page_fault(vaddr),
+                WalkFault::Access => access.access_fault(vaddr),
+            })?;
+        if !permitted(entry.flags, access, privilege, mstatus) {
+            return Err(access.page_fault(vaddr));
+        }
+
+        // 硬件更新 A/D 位
+        let needed = if access == AccessType::Store {
+            PTE_A | PTE_D
+        } else {
+            PTE_A
+        };
+        if entry.flags & needed != needed {
+            if !csrs
+                .pmp()
+                .check(pte_addr, 4, AccessType::Store, Privilege::Supervisor)
+            {
+                return Err(access.access_fault(vaddr));
+            }
+            entry.flags |= needed;
+            memory
+                .vwrite(pte_addr as usize, (entry.ppn << 10) | entry.flags, 4)
+                .map_err(|_| access.access_fault(vaddr))?;
+        }
+
+        self.tlb[tlb_index(entry.vpn, entry.level)] = Some(entry);
+        Ok(entry.physical(va))
+    }
+
+    // 调试器使用的转换：不检查权限、不更新 A/D 位，也不填充 TLB
+    pub fn translate_debug(
+        &self,
+        memory: &mut Memory,
+        csrs: &CsrFile,
+        vaddr: u64,
+    ) -> Result<u64, EmuError> {
+        if !csrs.paging_enabled() || csrs.privilege() == Privilege::Machine {
+            return Ok(vaddr);
+        }
+        walk(memory, None, csrs.satp(), vaddr as u32)
+            .map(|(entry, _)| entry.physical(vaddr as u32))
+            .map_err(|_| EmuError::PageFault { addr: vaddr })
+    }
+
+    // SFENCE.VMA：vaddr/asid 为 None 时刷新全部地址/地址空间，全局映射不受 ASID 限定的刷新影响。
+    // 大页表项只要 VPN[1] 相同就被刷新
+    pub fn flush(&mut self, vaddr: Option<u64>, asid: Option<u64>) {
+        let vpn = vaddr.map(|v| (v as u32) >> PAGE_SHIFT);
+        let asid = asid.map(|a| (a & 0x1ff) as u32);
+        for slot in self.tlb.iter_mut() {
+            let Some(entry) = slot else {
+                continue;
+            };
+            let vpn_match = vpn.is_none_or(|vpn| entry.covers(vpn));
+            let asid_match = asid.is_none_or(|asid| entry.flags & PTE_G == 0 && entry.asid == asid);
+            if vpn_match && asid_match {
+                *slot = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csr::{MSTATUS, MSTATUS_MPRV, SATP};
+
+    #[test]
+    fn test_superpage_tlb() {
+        let mut memory = Memory::new(0x03000000);
+        let mut csrs = CsrFile::new();
+        // MPRV=1、MPP=S：M 模式的读写按 S 模式转换，根页表 0x80010000
+        csrs.write(MSTATUS, MSTATUS_MPRV | 1 << 11).unwrap();
+        csrs.write(SATP, 0x80080010).unwrap();
+        // 0x00400000 起的 4 MiB 大页 -> 0x80400000
+        let pte_addr = 0x80010000 + 4;
+        memory
+            .write_bytes(pte_addr, &0x201000cfu32.to_le_bytes())
+            .unwrap();
+
+        let mut mmu = Mmu::new();
+        let load = |mmu: &mut Mmu, memory: &mut Memory, va| {
+            mmu.translate(memory, &csrs, va, AccessType::Load).unwrap()
+        };
+        assert_eq!(load(&mut mmu, &mut memory, 0x00400010), 0x80400010);
+
+        // 大页中的其他 4 KiB 页同样命中 TLB，修改页表后仍使用旧映射
+        memory
+            .write_bytes(pte_addr, &0x202000cfu32.to_le_bytes())
+            .unwrap();
+        assert_eq!(load(&mut mmu, &mut memory, 0x00401234), 0x80401234);
+        assert_eq!(load(&mut mmu, &mut memory, 0x007ffffc), 0x807ffffc);
+
+        // 刷新大页中任意一个地址都使整个大页的表项失效
+        mmu.flush(Some(0x00402000), None);
+        assert_eq!(load(&mut mmu, &mut memory, 0x00401234), 0x80801234);
+    }
+}
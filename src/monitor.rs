@@ -0,0 +1,364 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// 单步模式下的交互式调试命令行
+
+use crate::cpu::Cpu;
+use crate::debugger::WatchKind;
+use crate::disasm::disassemble;
+use crate::isa::Xlen;
+use crate::memory::PAGE_SIZE;
+use crate::register::ABI_NAMES;
+use crate::rvc;
+use std::io::Write;
+
+const HELP: &str = "\
+Commands:
+  s [n]              Step n instructions (default 1)
+  c                  Continue until a breakpoint or watchpoint
+  b [addr|symbol]    Set a breakpoint, or list breakpoints and watchpoints
+  d <n>              Delete breakpoint/watchpoint number n
+  w <addr|symbol>    Watch writes to a word
+  x/<n><fmt><size> <addr>
+                     Examine memory; fmt: x d u c i, size: b h w
+  p <expr>           Print a value, e.g. p $a0, p $pc, p main
+  info regs          Show all registers
+  info fregs         Show floating-point registers and fcsr
+  info mem           Show memory regions and allocated pages
+  history            Show recent instruction and memory traces
+  trace on|off       Enable or disable instruction and memory trace output
+  q                  Quit";
+
+// 内存区域和已分配的页
+fn show_memory(cpu: &Cpu) {
+    for (region, stats) in cpu.memory().region_stats() {
+        println!(
+            "{}  {}/{} pages",
+            region, stats.resident_pages, stats.total_pages
+        );
+    }
+    let stats = cpu.memory().page_stats();
+    println!(
+        "resident: {} KiB ({} x {} byte pages)",
+        stats.resident_bytes() / 1024,
+        stats.resident_pages,
+        PAGE_SIZE
+    );
+}
+
+// 解析寄存器名（$a0、$x10、$pc）、数字或符号名
+fn parse_value(cpu: &Cpu, token: &str) -> Option<u64> {
+    if let Some(name) = token.strip_prefix('$') {
+        if name == "pc" {
+            return Some(cpu.pc());
+        }
+        if name == "fp" {
+            return Some(cpu.read_register(8));
+        }
+        let index = ABI_NAMES.iter().position(|&n| n == name).or_else(|| {
+            name.strip_prefix('x')
+                .and_then(|i| i.parse::<usize>().ok())
+                .filter(|&i| i < 32)
+        })?;
+        return Some(cpu.read_register(index));
+    }
+    if let Some(hex) = token.strip_prefix("0x") {
+        return u64::from_str_radix(hex, 16).ok();
+    }
+    if let Ok(value) = token.parse::<i64>() {
+        return Some(value as u64 & cpu.xlen().mask());
+    }
+    cpu.debugger().symbols.find(token)
+}
+
+fn show_location(cpu: &mut Cpu) {
+    let pc = cpu.pc();
+    let symbol = cpu.debugger().symbols.describe(pc);
+    match cpu.debug_fetch(pc) {
+        Ok(inst) => println!(
+            "=> 0x{:08x}{}: {}",
+            pc,
+            symbol,
+            disassemble(inst, pc, cpu.xlen())
+        ),
+        Err(e) => println!("=> 0x{:08x}{}: <{}>", pc, symbol, e),
+    }
+}
+
+// x/<n><fmt><size> <addr>，返回要输出的各行
+fn examine(cpu: &mut Cpu, spec: &str, addr: u64) -> Vec<String> {
+    let digits: String = spec.chars().take_while(|c| c.is_ascii_digit()).collect();
+    let count = digits.parse::<u32>().unwrap_or(1);
+    let mut fmt = 'x';
+    let mut size = 4;
+    for c in spec[digits.len()..].chars() {
+        match c {
+            'b' => size = 1,
+            'h' => size = 2,
+            'w' => size = 4,
+            'x' | 'd' | 'u' | 'c' | 'i' => fmt = c,
+            _ => return vec![format!("Unknown format '{}'", c)],
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut cur = addr;
+    for _ in 0..count {
+        let label = cpu.debugger().symbols.describe(cur);
+        // 指令按实际长度前进
+        let result = if fmt == 'i' {
+            cpu.debug_fetch(cur)
+        } else {
+            cpu.debug_read(cur, size)
+        };
+        let value = match result {
+            Ok(value) => value,
+            Err(e) => {
+                lines.push(format!("0x{:08x}: <{}>", cur, e));
+                break;
+            }
+        };
+        let text = match fmt {
+            'd' => match size {
+                1 => (value as i8).to_string(),
+                2 => (value as i16).to_string(),
+                _ => (value as i32).to_string(),
+            },
+            'u' => value.to_string(),
+            'c' => format!("{:?}", value as u8 as char),
+            'i' => disassemble(value, cur, cpu.xlen()),
+            _ => format!("0x{:0width$x}", value, width = size * 2),
+        };
+        lines.push(format!("0x{:08x}{}: {}", cur, label, text));
+
+        let len = match fmt {
+            'i' if rvc::is_compressed(value) => 2,
+            'i' => 4,
+            _ => size as u64,
+        };
+        cur = cur.wrapping_add(len);
+    }
+    lines
+}
+
+fn list_breakpoints(cpu: &Cpu) {
+    let debugger = cpu.debugger();
+    let breakpoints = debugger.breakpoints();
+    if breakpoints.is_empty() && debugger.watchpoints().is_empty() {
+        println!("No breakpoints or watchpoints");
+        return;
+    }
+    for (i, &addr) in breakpoints.iter().enumerate() {
+        println!(
+            "#{:<3} breakpoint 0x{:08x}{}",
+            i,
+            addr,
+            debugger.symbols.describe(addr)
+        );
+    }
+    for (i, w) in debugger.watchpoints().iter().enumerate() {
+        println!(
+            "#{:<3} watchpoint 0x{:08x} ({} bytes, {:?})",
+            breakpoints.len() + i,
+            w.addr,
+            w.len,
+            w.kind
+        );
+    }
+}
+
+// 交互命令行，返回 false 表示用户要求退出
+pub fn prompt(cpu: &mut Cpu) -> bool {
+    show_location(cpu);
+    let mut last_command = String::from("s");
+
+    loop {
+        print!("(cakemu) ");
+        std::io::stdout().flush().ok();
+
+        let mut input = String::new();
+        match std::io::stdin().read_line(&mut input) {
+            Ok(0) | Err(_) => return false, // EOF
+            Ok(_) => (),
+        }
+
+        // 空行重复上一条命令
+        let line = match input.trim() {
+            "" => last_command.clone(),
+            line => line.to_string(),
+        };
+        last_command = line.clone();
+
+        let mut parts = line.split_whitespace();
+        let cmd = parts.next().unwrap_or("");
+        let arg = parts.next();
+        let value = arg.and_then(|a| parse_value(cpu, a));
+
+        match cmd {
+            "s" | "step" => {
+                let n = arg.and_then(|a| a.parse().ok()).unwrap_or(1);
+                cpu.debugger_mut().step_n(n);
+                return true;
+            }
+            "c" | "continue" => {
+                cpu.debugger_mut().resume();
+                return true;
+            }
+            "b" | "break" => match (arg, value) {
+                (None, _) => list_breakpoints(cpu),
+                (Some(_), Some(addr)) => {
+                    cpu.debugger_mut().add_breakpoint(addr);
+                    println!("Breakpoint at 0x{:08x}", addr);
+                }
+                (Some(a), None) => println!("Unknown address or symbol: {}", a),
+            },
+            "d" | "delete" => match arg.and_then(|a| a.parse().ok()) {
+                Some(n) if cpu.debugger_mut().delete(n) => println!("Deleted #{}", n),
+                _ => println!("No such breakpoint or watchpoint"),
+            },
+            "w" | "watch" => match value {
+                Some(addr) => {
+                    cpu.debugger_mut().add_watchpoint(addr, 4, WatchKind::Write);
+                    println!("Watchpoint at 0x{:08x}", addr);
+                }
+                None => println!("Usage: w <addr|symbol>"),
+            },
+            "p" | "print" => match value {
+                Some(v) => {
+                    let signed = match cpu.xlen() {
+                        Xlen::Rv32 => v as i32 as i64,
+                        Xlen::Rv64 => v as i64,
+                    };
+                    let symbol = cpu.debugger().symbols.describe(v);
+                    println!("0x{:08x} ({}){}", v, signed, symbol);
+                }
+                None => println!("Usage: p $reg | p <addr|symbol>"),
+            },
+            "info" => match arg {
+                Some("regs") | Some("r") | Some("registers") => cpu.dump_registers(),
+                Some("f") | Some("fregs") | Some("float") => cpu.dump_fp_registers(),
+                Some("b") | Some("break") => list_breakpoints(cpu),
+                Some("m") | Some("mem") | Some("memory") => show_memory(cpu),
+                _ => println!("Usage: info regs | info fregs | info break | info mem"),
+            },
+            "history" => {
+                cpu.debugger().show_instruction_trace();
+                cpu.debugger().show_memory_trace();
+            }
+            "trace" => {
+                let enabled = match arg {
+                    Some("on") => true,
+                    Some("off") => false,
+                    _ => {
+                        println!("Usage: trace on|off");
+                        continue;
+                    }
+                };
+                cpu.set_itrace(enabled);
+                cpu.set_mtrace(enabled);
+            }
+            "q" | "quit" => return false,
+            "h" | "help" => println!("{}", HELP),
+            _ if cmd.starts_with("x") => {
+                let spec = cmd.strip_prefix("x").unwrap().trim_start_matches('/');
+                match value {
+                    Some(addr) => {
+                        for line in examine(cpu, spec, addr) {
+                            println!("{}", line);
+                        }
+                    }
+                    None => println!("Usage: x/<n><fmt><size> <addr>"),
+                }
+            }
+            _ => println!("Unknown command '{}', type 'help' for a list", cmd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbols::{Symbol, SymbolTable};
+
+    fn test_cpu() -> Cpu {
+        let mut cpu = Cpu::new(0x03000000);
+        cpu.set_itrace(false);
+        cpu.set_mtrace(false);
+        cpu.set_regtrace(false);
+        cpu.debugger_mut().symbols = SymbolTable::new(vec![Symbol {
+            name: "main".to_string(),
+            addr: 0x80000000,
+            size: 0x10,
+        }]);
+        cpu
+    }
+
+    #[test]
+    fn test_parse_value() {
+        let mut cpu = test_cpu();
+        cpu.write_register(10, 42);
+        cpu.write_register(8, 0x80001000);
+        assert_eq!(parse_value(&cpu, "$a0"), Some(42));
+        assert_eq!(parse_value(&cpu, "$x10"), Some(42));
+        assert_eq!(parse_value(&cpu, "$fp"), Some(0x80001000));
+        assert_eq!(parse_value(&cpu, "$pc"), Some(0x80000000));
+        assert_eq!(parse_value(&cpu, "$x32"), None);
+        assert_eq!(parse_value(&cpu, "$foo"), None);
+        assert_eq!(parse_value(&cpu, "0x80000010"), Some(0x80000010));
+        assert_eq!(parse_value(&cpu, "0xzz"), None);
+        assert_eq!(parse_value(&cpu, "16"), Some(16));
+        // 负数按 XLEN 截断
+        assert_eq!(parse_value(&cpu, "-1"), Some(0xffffffff));
+        assert_eq!(parse_value(&cpu, "main"), Some(0x80000000));
+        assert_eq!(parse_value(&cpu, "missing"), None);
+    }
+
+    #[test]
+    fn test_examine() {
+        let mut cpu = test_cpu();
+        cpu.debug_write(0x80000000, 0x00500513, 4).unwrap(); // li a0, 5
+        cpu.debug_write(0x80000004, 0xfff00593, 4).unwrap(); // li a1, -1
+
+        assert_eq!(
+            examine(&mut cpu, "2x", 0x80000000),
+            [
+                "0x80000000 <main>: 0x00500513",
+                "0x80000004 <main+0x4>: 0xfff00593"
+            ]
+        );
+        assert_eq!(
+            examine(&mut cpu, "2i", 0x80000000),
+            [
+                "0x80000000 <main>: li a0, 5",
+                "0x80000004 <main+0x4>: li a1, -1"
+            ]
+        );
+        assert_eq!(
+            examine(&mut cpu, "db", 0x80000007),
+            ["0x80000007 <main+0x7>: -1"]
+        );
+        assert_eq!(
+            examine(&mut cpu, "3xh", 0x80000000)[2],
+            "0x80000004 <main+0x4>: 0x0593"
+        );
+        assert_eq!(examine(&mut cpu, "q", 0x80000000), ["Unknown format 'q'"]);
+        // 读取失败时停止
+        let lines = examine(&mut cpu, "4x", 0x82fffffc);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("0x83000000: <"));
+    }
+}
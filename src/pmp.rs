@@ -0,0 +1,257 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// 物理内存保护：16 个表项，粒度为 4 字节
+
+use crate::csr::Privilege;
+use crate::isa::Xlen;
+use crate::mmu::AccessType;
+
+pub const PMP_ENTRIES: usize = 16;
+
+// pmpcfg 每项的位
+const PMP_R: u8 = 1 << 0;
+const PMP_W: u8 = 1 << 1;
+const PMP_X: u8 = 1 << 2;
+const PMP_A: u8 = 0b11 << 3;
+const PMP_L: u8 = 1 << 7;
+const PMP_CFG_MASK: u8 = PMP_R | PMP_W | PMP_X | PMP_A | PMP_L;
+
+// 地址匹配模式
+const PMP_OFF: u8 = 0;
+const PMP_TOR: u8 = 1;
+const PMP_NA4: u8 = 2;
+const PMP_NAPOT: u8 = 3;
+
+pub struct Pmp {
+    cfg: [u8; PMP_ENTRIES],
+    addr: [u64; PMP_ENTRIES], // 物理地址右移 2 位
+    strict: bool,             // 按规范拒绝没有匹配表项的 S/U 模式访问，即使所有表项都关闭
+}
+
+impl Default for Pmp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pmp {
+    pub fn new() -> Self {
+        Self {
+            cfg: [0; PMP_ENTRIES],
+            addr: [0; PMP_ENTRIES],
+            strict: false,
+        }
+    }
+
+    // 默认与 QEMU 一致，所有表项都关闭时不限制 S/U 模式，未配置 PMP 的裸机程序可以直接进入 U 模式
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    fn mode(&self, index: usize) -> u8 {
+        (self.cfg[index] & PMP_A) >> 3
+    }
+
+    fn locked(&self, index: usize) -> bool {
+        self.cfg[index] & PMP_L != 0
+    }
+
+    // 从 first 开始的 count 项打包成一个 pmpcfg 值
+    pub fn read_cfg(&self, first: usize, count: usize) -> u64 {
+        (0..count).fold(0, |value, i| {
+            value | (self.cfg[first + i] as u64) << (8 * i)
+        })
+    }
+
+    // 锁定的表项不可修改；R=0、W=1 是保留组合，按不可写处理
+    pub fn write_cfg(&mut self, first: usize, count: usize, value: u64) {
+        for i in 0..count {
+            if self.locked(first + i) {
+                continue;
+            }
+            let mut cfg = (value >> (8 * i)) as u8 & PMP_CFG_MASK;
+            if cfg & PMP_R == 0 {
+                cfg &= !PMP_W;
+            }
+            self.cfg[first + i] = cfg;
+        }
+    }
+
+    pub fn read_addr(&self, index: usize) -> u64 {
+        self.addr[index]
+    }
+
+    // 表项锁定，或下一项是锁定的 TOR 区域时 pmpaddr 不可修改
+    pub fn write_addr(&mut self, index: usize, value: u64, xlen: Xlen) {
+        let next_locked_tor =
+            index + 1 < PMP_ENTRIES && self.locked(index + 1) && self.mode(index + 1) == PMP_TOR;
+        if self.locked(index) || next_locked_tor {
+            return;
+        }
+        // RV32 覆盖 34 位物理地址，RV64 覆盖 56 位
+        self.addr[index] = match xlen {
+            Xlen::Rv32 => value & 0xffffffff,
+            Xlen::Rv64 => value & ((1 << 54) - 1),
+        };
+    }
+
+    // 表项覆盖的物理地址范围 [base, top)，OFF 或空区域返回 None
+    fn range(&self, index: usize) -> Option<(u64, u64)> {
+        let addr = self.addr[index];
+        match self.mode(index) {
+            PMP_OFF => None,
+            PMP_TOR => {
+                let base = if index == 0 {
+                    0
+                } else {
+                    self.addr[index - 1] << 2
+                };
+                let top = addr << 2;
+                (base < top).then_some((base, top))
+            }
+            PMP_NA4 => Some((addr << 2, (addr << 2) + 4)),
+            PMP_NAPOT => {
+                // pmpaddr 末尾的 n 个 1 表示 2^(n+3) 字节的区域
+                let size = 1u64 << (addr.trailing_ones() + 3);
+                let base = (addr << 2) & !(size - 1);
+                Some((base, base + size))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // 编号最小的匹配项决定访问是否允许，访问的所有字节都必须落在该区域内
+    // M 模式只受锁定表项约束；没有匹配项时拒绝 S/U 模式访问，非严格模式下所有表项都关闭时除外
+    pub fn check(&self, addr: u64, len: usize, access: AccessType, privilege: Privilege) -> bool {
+        let end = addr + len as u64;
+        for index in 0..PMP_ENTRIES {
+            let Some((base, top)) = self.range(index) else {
+                continue;
+            };
+            if addr >= top || end <= base {
+                continue;
+            }
+            if addr < base || end > top {
+                return false;
+            }
+
+            let cfg = self.cfg[index];
+            if privilege == Privilege::Machine && cfg & PMP_L == 0 {
+                return true;
+            }
+            let bit = match access {
+                AccessType::Fetch => PMP_X,
+                AccessType::Load => PMP_R,
+                AccessType::Store => PMP_W,
+            };
+            return cfg & bit != 0;
+        }
+        privilege == Privilege::Machine
+            || (!self.strict && (0..PMP_ENTRIES).all(|i| self.mode(i) == PMP_OFF))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOR: u64 = (PMP_TOR as u64) << 3;
+    const NA4: u64 = (PMP_NA4 as u64) << 3;
+    const NAPOT: u64 = (PMP_NAPOT as u64) << 3;
+    const R: u64 = PMP_R as u64;
+    const W: u64 = PMP_W as u64;
+    const X: u64 = PMP_X as u64;
+    const L: u64 = PMP_L as u64;
+
+    #[test]
+    fn test_pmp_regions() {
+        let mut pmp = Pmp::new();
+        let user = Privilege::User;
+        assert!(pmp.check(0x80000000, 4, AccessType::Store, user));
+
+        // 0: [0x80000000, 0x80001000) TOR 可读可执行
+        // 1: 0x80002000 NA4 只读
+        // 2: 0x80010000 起 64 KiB NAPOT 可读写
+        pmp.write_addr(0, 0x80000000 >> 2, Xlen::Rv32);
+        pmp.write_addr(1, 0x80001000 >> 2, Xlen::Rv32);
+        pmp.write_addr(2, 0x80002000 >> 2, Xlen::Rv32);
+        pmp.write_addr(3, (0x80010000 >> 2) | 0x1fff, Xlen::Rv32);
+        pmp.write_cfg(
+            0,
+            4,
+            (NAPOT | R | W) << 24 | (NA4 | R) << 16 | (TOR | R | X) << 8,
+        );
+        assert_eq!(pmp.read_cfg(0, 4) >> 8, 0x1b110d);
+
+        assert!(pmp.check(0x80000ffc, 4, AccessType::Fetch, user));
+        assert!(!pmp.check(0x80000ffc, 4, AccessType::Store, user));
+        assert!(!pmp.check(0x80000ffe, 4, AccessType::Load, user)); // 跨越区域边界
+        assert!(pmp.check(0x80002000, 4, AccessType::Load, user));
+        assert!(!pmp.check(0x80002004, 4, AccessType::Load, user));
+        assert!(pmp.check(0x8001fffc, 4, AccessType::Store, user));
+        assert!(!pmp.check(0x80020000, 4, AccessType::Store, user));
+        // 未锁定的表项不限制 M 模式
+        assert!(pmp.check(0x80002000, 4, AccessType::Store, Privilege::Machine));
+        assert!(pmp.check(0x90000000, 4, AccessType::Store, Privilege::Machine));
+
+        // W=1、R=0 是保留组合
+        pmp.write_cfg(4, 1, NA4 | W);
+        assert_eq!(pmp.read_cfg(4, 1), NA4);
+    }
+
+    #[test]
+    fn test_pmp_default_policy() {
+        let user = Privilege::User;
+        let supervisor = Privilege::Supervisor;
+        let mut pmp = Pmp::new();
+        assert!(pmp.check(0x80000000, 4, AccessType::Load, user));
+        assert!(pmp.check(0x80000000, 4, AccessType::Fetch, supervisor));
+
+        // 严格模式：即使所有表项都关闭，S/U 模式也没有任何权限，M 模式不受影响
+        pmp.set_strict(true);
+        assert!(!pmp.check(0x80000000, 4, AccessType::Load, user));
+        assert!(!pmp.check(0x80000000, 4, AccessType::Fetch, supervisor));
+        assert!(pmp.check(0x80000000, 4, AccessType::Store, Privilege::Machine));
+
+        // 两种模式下，启用任一表项后没有匹配的 S/U 访问都失败
+        pmp.write_addr(0, 0x80000000 >> 2, Xlen::Rv32);
+        pmp.write_cfg(0, 1, NA4 | R);
+        assert!(pmp.check(0x80000000, 4, AccessType::Load, user));
+        assert!(!pmp.check(0x80000004, 4, AccessType::Load, user));
+        pmp.set_strict(false);
+        assert!(!pmp.check(0x80000004, 4, AccessType::Load, user));
+    }
+
+    #[test]
+    fn test_pmp_lock() {
+        let mut pmp = Pmp::new();
+        pmp.write_addr(0, 0x1000 >> 2, Xlen::Rv32);
+        pmp.write_addr(1, 0x2000 >> 2, Xlen::Rv32);
+        pmp.write_cfg(0, 2, (L | TOR | R) << 8);
+
+        // 锁定后 cfg 和 TOR 的上下界都不可修改，并且对 M 模式生效
+        pmp.write_cfg(0, 2, 0);
+        assert_eq!(pmp.read_cfg(0, 2), (L | TOR | R) << 8);
+        pmp.write_addr(0, 0, Xlen::Rv32);
+        pmp.write_addr(1, 0, Xlen::Rv32);
+        assert_eq!(pmp.read_addr(0), 0x1000 >> 2);
+        assert_eq!(pmp.read_addr(1), 0x2000 >> 2);
+        assert!(pmp.check(0x1000, 4, AccessType::Load, Privilege::Machine));
+        assert!(!pmp.check(0x1000, 4, AccessType::Store, Privilege::Machine));
+    }
+}
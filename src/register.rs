@@ -15,38 +15,131 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::fpu::FpFormat;
+use crate::isa::Xlen;
+use crate::symbols::SymbolTable;
+
+// ABI 寄存器名
+pub const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+// 浮点寄存器 ABI 名
+pub const FP_ABI_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2",
+    "fa3", "fa4", "fa5", "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9",
+    "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+// 单精度值写入 64 位寄存器时高 32 位全部置 1（NaN-boxing）
+const NAN_BOX: u64 = 0xffffffff_00000000;
+
+// 寄存器按 64 位存储，RV32 下写入时截断为 32 位
 pub struct RegisterFile {
-    regs: [u32; 32],
+    regs: [u64; 32],
+    xlen: Xlen,
+}
+
+impl Default for RegisterFile {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RegisterFile {
     pub fn new() -> Self {
-        Self { regs: [0; 32] }
+        Self {
+            regs: [0; 32],
+            xlen: Xlen::Rv32,
+        }
+    }
+
+    pub fn set_xlen(&mut self, xlen: Xlen) {
+        self.xlen = xlen;
+        for reg in self.regs.iter_mut() {
+            *reg &= xlen.mask();
+        }
     }
 
     #[inline]
-    pub fn read(&self, index: usize) -> u32 {
+    pub fn read(&self, index: usize) -> u64 {
         // x0 is always 0, and we maintain this invariant in write
         self.regs[index]
     }
 
     #[inline]
-    pub fn write(&mut self, index: usize, value: u32) {
+    pub fn write(&mut self, index: usize, value: u64) {
         if index != 0 {
             // Cannot write to x0
-            self.regs[index] = value;
+            self.regs[index] = value & self.xlen.mask();
+        }
+    }
+
+    pub fn dump(&self, symbols: &SymbolTable) {
+        let width = self.xlen.bits() as usize / 4;
+        for (i, (name, &value)) in ABI_NAMES.iter().zip(self.regs.iter()).enumerate() {
+            let symbol = symbols.describe(value);
+            println!(
+                "x{:<2} ({:<5}): 0x{:0width$x}{}",
+                i,
+                name,
+                value,
+                symbol,
+                width = width
+            );
+        }
+    }
+}
+
+// F/D 扩展的浮点寄存器，FLEN = 64
+pub struct FpRegisterFile {
+    regs: [u64; 32],
+}
+
+impl Default for FpRegisterFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FpRegisterFile {
+    pub fn new() -> Self {
+        Self { regs: [0; 32] }
+    }
+
+    // 读取指定格式的值，未正确装箱的单精度值视为规范 NaN
+    pub fn read(&self, index: usize, fmt: FpFormat) -> u64 {
+        let value = self.regs[index];
+        match fmt {
+            FpFormat::Double => value,
+            FpFormat::Single if value & NAN_BOX == NAN_BOX => value & 0xffffffff,
+            FpFormat::Single => FpFormat::Single.canonical_nan(),
         }
     }
 
+    pub fn write(&mut self, index: usize, fmt: FpFormat, value: u64) {
+        self.regs[index] = match fmt {
+            FpFormat::Double => value,
+            FpFormat::Single => NAN_BOX | (value & 0xffffffff),
+        };
+    }
+
+    // 不做装箱检查的原始读取（FSW、FMV.X.W）
+    #[inline]
+    pub fn read_raw(&self, index: usize) -> u64 {
+        self.regs[index]
+    }
+
     pub fn dump(&self) {
-        let reg_names = [
-            "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0/fp", "s1", "a0", "a1", "a2",
-            "a3", "a4", "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10",
-            "s11", "t3", "t4", "t5", "t6",
-        ];
-
-        for (i, (name, value)) in reg_names.iter().zip(self.regs.iter()).enumerate() {
-            println!("x{:<2} ({:<5}): 0x{:08x}", i, name, value);
+        for (i, (name, value)) in FP_ABI_NAMES.iter().zip(self.regs.iter()).enumerate() {
+            let float = if value & NAN_BOX == NAN_BOX {
+                f32::from_bits(*value as u32) as f64
+            } else {
+                f64::from_bits(*value)
+            };
+            println!("f{:<2} ({:<4}): 0x{:016x} ({})", i, name, value, float);
         }
     }
 }
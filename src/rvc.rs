@@ -0,0 +1,410 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// C 扩展：把 16 位压缩指令展开为等价的 32 位指令
+
+use crate::error::EmuError;
+use crate::isa::Xlen;
+
+// 是否为 16 位压缩指令（低两位不为 0b11）
+#[inline]
+pub fn is_compressed(inst: u32) -> bool {
+    inst & 0x3 != 0x3
+}
+
+// 从指令位中取出 [hi:lo] 并左移到 pos
+#[inline]
+fn bits(inst: u32, hi: u32, lo: u32, pos: u32) -> u32 {
+    ((inst >> lo) & ((1 << (hi - lo + 1)) - 1)) << pos
+}
+
+// 符号扩展 width 位的立即数
+#[inline]
+fn sext(value: u32, width: u32) -> i32 {
+    ((value << (32 - width)) as i32) >> (32 - width)
+}
+
+// 压缩寄存器编号 rd'/rs1'/rs2' 对应 x8-x15
+#[inline]
+fn creg(inst: u32, lo: u32) -> u32 {
+    ((inst >> lo) & 0x7) + 8
+}
+
+fn enc_r(opcode: u32, funct3: u32, funct7: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+    (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+fn enc_i(opcode: u32, funct3: u32, rd: u32, rs1: u32, imm: i32) -> u32 {
+    ((imm as u32 & 0xfff) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+fn enc_s(opcode: u32, funct3: u32, rs1: u32, rs2: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    (bits(imm, 11, 5, 25))
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | (funct3 << 12)
+        | bits(imm, 4, 0, 7)
+        | opcode
+}
+
+fn enc_b(funct3: u32, rs1: u32, rs2: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    bits(imm, 12, 12, 31)
+        | bits(imm, 10, 5, 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | (funct3 << 12)
+        | bits(imm, 4, 1, 8)
+        | bits(imm, 11, 11, 7)
+        | 0x63
+}
+
+fn enc_j(rd: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    bits(imm, 20, 20, 31)
+        | bits(imm, 10, 1, 21)
+        | bits(imm, 11, 11, 20)
+        | bits(imm, 19, 12, 12)
+        | (rd << 7)
+        | 0x6f
+}
+
+fn enc_u(opcode: u32, rd: u32, imm: u32) -> u32 {
+    (imm & 0xfffff000) | (rd << 7) | opcode
+}
+
+// RV64C 中 C.FLW/C.FSW/C.JAL 等编码被 C.LD/C.SD/C.ADDIW 等指令复用
+pub fn expand(inst: u16, xlen: Xlen) -> Result<u32, EmuError> {
+    let inst = inst as u32;
+    let rv64 = xlen == Xlen::Rv64;
+    let funct3 = (inst >> 13) & 0x7;
+    let rd = (inst >> 7) & 0x1f; // 同时也是 rs1
+    let rs2 = (inst >> 2) & 0x1f;
+
+    match (inst & 0x3, funct3) {
+        // ---------------- Quadrant 0 ----------------
+        (0b00, 0b000) => {
+            // C.ADDI4SPN
+            let imm = bits(inst, 12, 11, 4)
+                | bits(inst, 10, 7, 6)
+                | bits(inst, 6, 6, 2)
+                | bits(inst, 5, 5, 3);
+            if imm == 0 {
+                return Err(EmuError::illegal(inst));
+            }
+            Ok(enc_i(0x13, 0x0, creg(inst, 2), 2, imm as i32))
+        }
+        (0b00, 0b001) => {
+            // C.FLD
+            let imm = bits(inst, 12, 10, 3) | bits(inst, 6, 5, 6);
+            Ok(enc_i(0x07, 0x3, creg(inst, 2), creg(inst, 7), imm as i32))
+        }
+        (0b00, 0b010) => {
+            // C.LW
+            let imm = bits(inst, 12, 10, 3) | bits(inst, 6, 6, 2) | bits(inst, 5, 5, 6);
+            Ok(enc_i(0x03, 0x2, creg(inst, 2), creg(inst, 7), imm as i32))
+        }
+        (0b00, 0b011) if rv64 => {
+            // C.LD
+            let imm = bits(inst, 12, 10, 3) | bits(inst, 6, 5, 6);
+            Ok(enc_i(0x03, 0x3, creg(inst, 2), creg(inst, 7), imm as i32))
+        }
+        (0b00, 0b011) => {
+            // C.FLW
+            let imm = bits(inst, 12, 10, 3) | bits(inst, 6, 6, 2) | bits(inst, 5, 5, 6);
+            Ok(enc_i(0x07, 0x2, creg(inst, 2), creg(inst, 7), imm as i32))
+        }
+        (0b00, 0b101) => {
+            // C.FSD
+            let imm = bits(inst, 12, 10, 3) | bits(inst, 6, 5, 6);
+            Ok(enc_s(0x27, 0x3, creg(inst, 7), creg(inst, 2), imm as i32))
+        }
+        (0b00, 0b110) => {
+            // C.SW
+            let imm = bits(inst, 12, 10, 3) | bits(inst, 6, 6, 2) | bits(inst, 5, 5, 6);
+            Ok(enc_s(0x23, 0x2, creg(inst, 7), creg(inst, 2), imm as i32))
+        }
+        (0b00, 0b111) if rv64 => {
+            // C.SD
+            let imm = bits(inst, 12, 10, 3) | bits(inst, 6, 5, 6);
+            Ok(enc_s(0x23, 0x3, creg(inst, 7), creg(inst, 2), imm as i32))
+        }
+        (0b00, 0b111) => {
+            // C.FSW
+            let imm = bits(inst, 12, 10, 3) | bits(inst, 6, 6, 2) | bits(inst, 5, 5, 6);
+            Ok(enc_s(0x27, 0x2, creg(inst, 7), creg(inst, 2), imm as i32))
+        }
+
+        // ---------------- Quadrant 1 ----------------
+        (0b01, 0b000) => {
+            // C.ADDI / C.NOP
+            let imm = sext(bits(inst, 12, 12, 5) | bits(inst, 6, 2, 0), 6);
+            Ok(enc_i(0x13, 0x0, rd, rd, imm))
+        }
+        (0b01, 0b001) if rv64 => {
+            // C.ADDIW
+            if rd == 0 {
+                return Err(EmuError::illegal(inst));
+            }
+            let imm = sext(bits(inst, 12, 12, 5) | bits(inst, 6, 2, 0), 6);
+            Ok(enc_i(0x1b, 0x0, rd, rd, imm))
+        }
+        (0b01, 0b001) | (0b01, 0b101) => {
+            // C.JAL / C.J
+            let imm = bits(inst, 12, 12, 11)
+                | bits(inst, 11, 11, 4)
+                | bits(inst, 10, 9, 8)
+                | bits(inst, 8, 8, 10)
+                | bits(inst, 7, 7, 6)
+                | bits(inst, 6, 6, 7)
+                | bits(inst, 5, 3, 1)
+                | bits(inst, 2, 2, 5);
+            let link = if funct3 == 0b001 { 1 } else { 0 };
+            Ok(enc_j(link, sext(imm, 12)))
+        }
+        (0b01, 0b010) => {
+            // C.LI
+            let imm = sext(bits(inst, 12, 12, 5) | bits(inst, 6, 2, 0), 6);
+            Ok(enc_i(0x13, 0x0, rd, 0, imm))
+        }
+        (0b01, 0b011) if rd == 2 => {
+            // C.ADDI16SP
+            let imm = bits(inst, 12, 12, 9)
+                | bits(inst, 6, 6, 4)
+                | bits(inst, 5, 5, 6)
+                | bits(inst, 4, 3, 7)
+                | bits(inst, 2, 2, 5);
+            if imm == 0 {
+                return Err(EmuError::illegal(inst));
+            }
+            Ok(enc_i(0x13, 0x0, 2, 2, sext(imm, 10)))
+        }
+        (0b01, 0b011) => {
+            // C.LUI
+            let imm = bits(inst, 12, 12, 17) | bits(inst, 6, 2, 12);
+            if imm == 0 {
+                return Err(EmuError::illegal(inst));
+            }
+            Ok(enc_u(0x37, rd, sext(imm, 18) as u32))
+        }
+        (0b01, 0b100) => {
+            let rd = creg(inst, 7);
+            let shamt = bits(inst, 12, 12, 5) | bits(inst, 6, 2, 0);
+            match (inst >> 10) & 0x3 {
+                // C.SRLI / C.SRAI，RV32 中 shamt[5] 必须为 0
+                0b00 | 0b01 if shamt >= 32 && !rv64 => Err(EmuError::illegal(inst)),
+                0b00 => Ok(enc_i(0x13, 0x5, rd, rd, shamt as i32)),
+                0b01 => Ok(enc_i(0x13, 0x5, rd, rd, (shamt | 0x400) as i32)),
+                0b10 => {
+                    // C.ANDI
+                    Ok(enc_i(0x13, 0x7, rd, rd, sext(shamt, 6)))
+                }
+                _ => {
+                    let rs2 = creg(inst, 2);
+                    match (inst >> 12 & 0x1, (inst >> 5) & 0x3) {
+                        (0, 0b00) => Ok(enc_r(0x33, 0x0, 0x20, rd, rd, rs2)), // C.SUB
+                        (0, 0b01) => Ok(enc_r(0x33, 0x4, 0x00, rd, rd, rs2)), // C.XOR
+                        (0, 0b10) => Ok(enc_r(0x33, 0x6, 0x00, rd, rd, rs2)), // C.OR
+                        (0, 0b11) => Ok(enc_r(0x33, 0x7, 0x00, rd, rd, rs2)), // C.AND
+                        (1, 0b00) if rv64 => Ok(enc_r(0x3b, 0x0, 0x20, rd, rd, rs2)), // C.SUBW
+                        (1, 0b01) if rv64 => Ok(enc_r(0x3b, 0x0, 0x00, rd, rd, rs2)), // C.ADDW
+                        _ => Err(EmuError::illegal(inst)),
+                    }
+                }
+            }
+        }
+        (0b01, 0b110) | (0b01, 0b111) => {
+            // C.BEQZ / C.BNEZ
+            let imm = bits(inst, 12, 12, 8)
+                | bits(inst, 11, 10, 3)
+                | bits(inst, 6, 5, 6)
+                | bits(inst, 4, 3, 1)
+                | bits(inst, 2, 2, 5);
+            let branch_funct3 = if funct3 == 0b110 { 0x0 } else { 0x1 };
+            Ok(enc_b(branch_funct3, creg(inst, 7), 0, sext(imm, 9)))
+        }
+
+        // ---------------- Quadrant 2 ----------------
+        (0b10, 0b000) => {
+            // C.SLLI
+            let shamt = bits(inst, 12, 12, 5) | rs2;
+            if shamt >= 32 && !rv64 {
+                return Err(EmuError::illegal(inst));
+            }
+            Ok(enc_i(0x13, 0x1, rd, rd, shamt as i32))
+        }
+        (0b10, 0b001) => {
+            // C.FLDSP
+            let imm = bits(inst, 12, 12, 5) | bits(inst, 6, 5, 3) | bits(inst, 4, 2, 6);
+            Ok(enc_i(0x07, 0x3, rd, 2, imm as i32))
+        }
+        (0b10, 0b010) => {
+            // C.LWSP
+            if rd == 0 {
+                return Err(EmuError::illegal(inst));
+            }
+            let imm = bits(inst, 12, 12, 5) | bits(inst, 6, 4, 2) | bits(inst, 3, 2, 6);
+            Ok(enc_i(0x03, 0x2, rd, 2, imm as i32))
+        }
+        (0b10, 0b011) if rv64 => {
+            // C.LDSP
+            if rd == 0 {
+                return Err(EmuError::illegal(inst));
+            }
+            let imm = bits(inst, 12, 12, 5) | bits(inst, 6, 5, 3) | bits(inst, 4, 2, 6);
+            Ok(enc_i(0x03, 0x3, rd, 2, imm as i32))
+        }
+        (0b10, 0b011) => {
+            // C.FLWSP，rd 可以是 f0
+            let imm = bits(inst, 12, 12, 5) | bits(inst, 6, 4, 2) | bits(inst, 3, 2, 6);
+            Ok(enc_i(0x07, 0x2, rd, 2, imm as i32))
+        }
+        (0b10, 0b100) => {
+            let bit12 = inst & (1 << 12) != 0;
+            match (bit12, rd, rs2) {
+                (false, 0, 0) => Err(EmuError::illegal(inst)),
+                (false, rs1, 0) => Ok(enc_i(0x67, 0x0, 0, rs1, 0)), // C.JR
+                (false, rd, rs2) => Ok(enc_r(0x33, 0x0, 0x00, rd, 0, rs2)), // C.MV
+                (true, 0, 0) => Ok(0x00100073),                     // C.EBREAK
+                (true, rs1, 0) => Ok(enc_i(0x67, 0x0, 1, rs1, 0)),  // C.JALR
+                (true, rd, rs2) => Ok(enc_r(0x33, 0x0, 0x00, rd, rd, rs2)), // C.ADD
+            }
+        }
+        (0b10, 0b101) => {
+            // C.FSDSP
+            let imm = bits(inst, 12, 10, 3) | bits(inst, 9, 7, 6);
+            Ok(enc_s(0x27, 0x3, 2, rs2, imm as i32))
+        }
+        (0b10, 0b110) => {
+            // C.SWSP
+            let imm = bits(inst, 12, 9, 2) | bits(inst, 8, 7, 6);
+            Ok(enc_s(0x23, 0x2, 2, rs2, imm as i32))
+        }
+        (0b10, 0b111) if rv64 => {
+            // C.SDSP
+            let imm = bits(inst, 12, 10, 3) | bits(inst, 9, 7, 6);
+            Ok(enc_s(0x23, 0x3, 2, rs2, imm as i32))
+        }
+        (0b10, 0b111) => {
+            // C.FSWSP
+            let imm = bits(inst, 12, 9, 2) | bits(inst, 8, 7, 6);
+            Ok(enc_s(0x27, 0x2, 2, rs2, imm as i32))
+        }
+
+        _ => Err(EmuError::illegal(inst)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_compressed() {
+        let cases = [
+            (0x1fe8, 0x3fc10513), // addi a0, sp, 1020
+            (0x0044, 0x00410493), // addi s1, sp, 4
+            (0x5fe8, 0x07c7a503), // lw a0, 124(a5)
+            (0x4080, 0x0004a403), // lw s0, 0(s1)
+            (0xc2b0, 0x04c6a023), // sw a2, 64(a3)
+            (0x0001, 0x00000013), // nop
+            (0x1501, 0xfe050513), // addi a0, a0, -32
+            (0x02fd, 0x01f28293), // addi t0, t0, 31
+            (0x3001, 0x801ff0ef), // jal -2048
+            (0x2ffd, 0x7fe000ef), // jal 2046
+            (0x557d, 0xfff00513), // li a0, -1
+            (0x4ffd, 0x01f00f93), // li t6, 31
+            (0x7101, 0xe0010113), // addi sp, sp, -512
+            (0x617d, 0x1f010113), // addi sp, sp, 496
+            (0x6505, 0x00001537), // lui a0, 1
+            (0x7281, 0xfffe02b7), // lui t0, 1048544
+            (0x817d, 0x01f55513), // srli a0, a0, 31
+            (0x8485, 0x4014d493), // srai s1, s1, 1
+            (0x9b81, 0xfe07f793), // andi a5, a5, -32
+            (0x8d0d, 0x40b50533), // sub a0, a0, a1
+            (0x8c25, 0x00944433), // xor s0, s0, s1
+            (0x8f5d, 0x00f76733), // or a4, a4, a5
+            (0x8e75, 0x00d67633), // and a2, a2, a3
+            (0xb001, 0x801ff06f), // j -2048
+            (0xa095, 0x0640006f), // j 100
+            (0xd101, 0xf00500e3), // beqz a0, -256
+            (0xecfd, 0x0e049f63), // bnez s1, 254
+            (0x02fe, 0x01f29293), // slli t0, t0, 31
+            (0x50fe, 0x0fc12083), // lw ra, 252(sp)
+            (0x8082, 0x00008067), // ret
+            (0x857e, 0x01f00533), // mv a0, t6 (add a0, zero, t6)
+            (0x9002, 0x00100073), // ebreak
+            (0x9782, 0x000780e7), // jalr a5
+            (0x911a, 0x00610133), // add sp, sp, t1
+            (0xdf86, 0x0e112e23), // sw ra, 252(sp)
+            (0xc002, 0x00012023), // sw zero, 0(sp)
+            (0x61c8, 0x0045a507), // flw fa0, 4(a1)
+            (0xe1c8, 0x00a5a227), // fsw fa0, 4(a1)
+            (0x2588, 0x0085b507), // fld fa0, 8(a1)
+            (0xa588, 0x00a5b427), // fsd fa0, 8(a1)
+            (0x6532, 0x00c12507), // flw fa0, 12(sp)
+            (0xe62a, 0x00a12627), // fsw fa0, 12(sp)
+            (0x2462, 0x01813407), // fld fs0, 24(sp)
+            (0xac22, 0x00813c27), // fsd fs0, 24(sp)
+            (0x307e, 0x1f813007), // fld ft0, 504(sp)
+        ];
+        for (inst, expected) in cases {
+            assert_eq!(
+                expand(inst, Xlen::Rv32).unwrap(),
+                expected,
+                "0x{:04x}",
+                inst
+            );
+        }
+    }
+
+    #[test]
+    fn test_expand_compressed_rv64() {
+        let cases = [
+            (0x6588, 0x0085b503), // ld a0, 8(a1)
+            (0xe588, 0x00a5b423), // sd a0, 8(a1)
+            (0x357d, 0xfff5051b), // addiw a0, a0, -1
+            (0x9d0d, 0x40b5053b), // subw a0, a0, a1
+            (0x9d2d, 0x00b5053b), // addw a0, a0, a1
+            (0x6522, 0x00813503), // ld a0, 8(sp)
+            (0xe42a, 0x00a13423), // sd a0, 8(sp)
+            (0x1502, 0x02051513), // slli a0, a0, 32
+            (0x9101, 0x02055513), // srli a0, a0, 32
+            (0x2588, 0x0085b507), // fld fa0, 8(a1)
+        ];
+        for (inst, expected) in cases {
+            assert_eq!(
+                expand(inst, Xlen::Rv64).unwrap(),
+                expected,
+                "0x{:04x}",
+                inst
+            );
+        }
+        assert!(expand(0x2001, Xlen::Rv64).is_err()); // C.ADDIW rd=x0
+        assert!(expand(0x9d0d, Xlen::Rv32).is_err()); // C.SUBW 只属于 RV64
+    }
+
+    #[test]
+    fn test_reserved_compressed() {
+        assert!(expand(0x0000, Xlen::Rv32).is_err()); // 全零为非法指令
+        assert!(expand(0x6101, Xlen::Rv32).is_err()); // C.ADDI16SP nzimm=0
+        assert!(expand(0x8002, Xlen::Rv32).is_err()); // C.JR rs1=x0
+        assert!(expand(0x4002, Xlen::Rv32).is_err()); // C.LWSP rd=x0
+        assert!(expand(0x1002, Xlen::Rv32).is_err()); // C.SLLI shamt[5]=1
+    }
+}
@@ -0,0 +1,116 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// 符号表：用于在跟踪和调试输出中把地址显示为 func+0x1c
+
+pub struct Symbol {
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+}
+
+#[derive(Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>, // 按地址排序
+}
+
+impl SymbolTable {
+    pub fn new(mut symbols: Vec<Symbol>) -> Self {
+        // 同一地址上有大小的符号排在后面，查找时优先命中
+        symbols.sort_by_key(|s| (s.addr, s.size != 0));
+        Self { symbols }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    // 按名字查找符号地址
+    pub fn find(&self, name: &str) -> Option<u64> {
+        self.symbols.iter().find(|s| s.name == name).map(|s| s.addr)
+    }
+
+    // 查找包含该地址的符号，返回符号名和偏移
+    // 没有大小的符号（如汇编标号）覆盖到下一个符号为止
+    pub fn lookup(&self, addr: u64) -> Option<(&str, u64)> {
+        let idx = self.symbols.partition_point(|s| s.addr <= addr);
+        let sym = self.symbols.get(idx.checked_sub(1)?)?;
+        let offset = addr - sym.addr;
+
+        let contains = if sym.size != 0 {
+            offset < sym.size
+        } else {
+            offset == 0 || self.symbols.get(idx).is_some()
+        };
+        if contains {
+            Some((&sym.name, offset))
+        } else {
+            None
+        }
+    }
+
+    // 格式化为 " <func+0x1c>"，找不到符号时返回空串
+    pub fn describe(&self, addr: u64) -> String {
+        match self.lookup(addr) {
+            Some((name, 0)) => format!(" <{}>", name),
+            Some((name, offset)) => format!(" <{}+0x{:x}>", name, offset),
+            None => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, addr: u64, size: u64) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            addr,
+            size,
+        }
+    }
+
+    #[test]
+    fn test_symbol_lookup() {
+        let table = SymbolTable::new(vec![
+            symbol("main", 0x80000100, 0x20),
+            symbol("_start", 0x80000000, 0),
+            symbol("helper", 0x80000200, 0x10),
+            symbol("_end", 0x80000300, 0),
+        ]);
+
+        assert_eq!(table.describe(0x80000000), " <_start>");
+        assert_eq!(table.describe(0x80000010), " <_start+0x10>");
+        assert_eq!(table.describe(0x8000011c), " <main+0x1c>");
+        // main 之后、helper 之前的空隙不属于任何符号
+        assert_eq!(table.describe(0x80000120), "");
+        assert_eq!(table.describe(0x80000300), " <_end>");
+        assert_eq!(table.describe(0x80000304), "");
+        assert_eq!(table.describe(0x10), "");
+        assert_eq!(table.find("helper"), Some(0x80000200));
+
+        // RV64 的地址不在 4 GiB 处回绕
+        let table = SymbolTable::new(vec![symbol("high", 0x1_0000_0000, 8)]);
+        assert_eq!(table.describe(0x1_0000_0004), " <high+0x4>");
+        assert_eq!(table.describe(0x4), "");
+    }
+}
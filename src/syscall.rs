@@ -0,0 +1,634 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// riscv-pk 兼容的系统调用：a7 为调用号，a0-a5 为参数，返回值写回 a0，失败时返回 -errno。
+// 文件只能在 set_sandbox 指定的主机目录中打开，时间由执行的指令数推算
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::EmuError;
+use crate::isa::Xlen;
+
+// 系统调用号（Linux 通用 ABI，与 riscv-pk 一致）
+const SYS_OPENAT: u64 = 56;
+const SYS_CLOSE: u64 = 57;
+const SYS_LSEEK: u64 = 62;
+const SYS_READ: u64 = 63;
+const SYS_WRITE: u64 = 64;
+const SYS_FSTAT: u64 = 80;
+const SYS_EXIT: u64 = 93;
+const SYS_EXIT_GROUP: u64 = 94;
+const SYS_CLOCK_GETTIME: u64 = 113;
+const SYS_GETTIMEOFDAY: u64 = 169;
+const SYS_BRK: u64 = 214;
+const SYS_CLOCK_GETTIME64: u64 = 403;
+const SYS_OPEN: u64 = 1024; // riscv-pk 的旧调用号，等价于 openat(AT_FDCWD, ...)
+
+// errno
+const ENOENT: i64 = 2;
+const EIO: i64 = 5;
+const EBADF: i64 = 9;
+const EACCES: i64 = 13;
+const EFAULT: i64 = 14;
+const EEXIST: i64 = 17;
+const EINVAL: i64 = 22;
+const ESPIPE: i64 = 29;
+const ENOSYS: i64 = 38;
+
+// open 标志
+const O_ACCMODE: u64 = 0x3;
+const O_WRONLY: u64 = 0x1;
+const O_RDWR: u64 = 0x2;
+const O_CREAT: u64 = 0x40;
+const O_EXCL: u64 = 0x80;
+const O_TRUNC: u64 = 0x200;
+const O_APPEND: u64 = 0x400;
+
+const AT_FDCWD: i64 = -100;
+
+// st_mode 中的文件类型
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+// 虚拟时钟：每条指令一个周期
+pub const CLOCK_HZ: u64 = 100_000_000;
+
+// 栈顶以下为栈保留的空间，brk 不能增长到这里
+pub const STACK_SIZE: u64 = 0x100000;
+
+// 单次 read/write 最多传输的字节数，超出部分由客户机重试
+const MAX_TRANSFER: usize = 1 << 20;
+
+// 系统调用通过它访问客户机的虚拟地址
+pub trait GuestMemory {
+    fn read(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), EmuError>;
+    fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), EmuError>;
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SyscallResult {
+    Return(i64),
+    Exit(u32),
+}
+
+enum HostFile {
+    Stdin,
+    Stdout,
+    Stderr,
+    // readable/writable 是客户机请求的访问方式，主机上的文件可能以更宽的权限打开
+    File {
+        file: File,
+        readable: bool,
+        writable: bool,
+    },
+}
+
+pub struct Syscalls {
+    files: Vec<Option<HostFile>>, // 下标即客户机的文件描述符
+    sandbox: Option<PathBuf>,
+    brk_start: u64,
+    brk: u64,
+    brk_limit: u64,
+}
+
+impl Default for Syscalls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Syscalls {
+    pub fn new() -> Self {
+        Self {
+            files: vec![
+                Some(HostFile::Stdin),
+                Some(HostFile::Stdout),
+                Some(HostFile::Stderr),
+            ],
+            sandbox: None,
+            brk_start: 0,
+            brk: 0,
+            brk_limit: 0,
+        }
+    }
+
+    // 允许客户机访问的主机目录，客户机的绝对路径和相对路径都从这里解析
+    pub fn set_sandbox(&mut self, dir: &Path) -> io::Result<()> {
+        self.sandbox = Some(dir.canonicalize()?);
+        Ok(())
+    }
+
+    // 堆从程序映像末尾开始，不能超过 limit
+    pub fn set_brk(&mut self, start: u64, limit: u64) {
+        self.brk_start = start;
+        self.brk = start;
+        self.brk_limit = limit;
+    }
+
+    pub fn handle(
+        &mut self,
+        number: u64,
+        args: [u64; 6],
+        xlen: Xlen,
+        memory: &mut dyn GuestMemory,
+        cycles: u64,
+    ) -> SyscallResult {
+        // 按 XLEN 把参数解释为有符号数
+        let signed = |value: u64| match xlen {
+            Xlen::Rv32 => value as i32 as i64,
+            Xlen::Rv64 => value as i64,
+        };
+        let word = xlen.bits() as usize / 8;
+
+        let result = match number {
+            SYS_EXIT | SYS_EXIT_GROUP => return SyscallResult::Exit(args[0] as u32),
+            SYS_WRITE => self.write(memory, args[0], args[1], args[2]),
+            SYS_READ => self.read(memory, args[0], args[1], args[2]),
+            SYS_OPENAT => self.open(memory, signed(args[0]), args[1], args[2]),
+            SYS_OPEN => self.open(memory, AT_FDCWD, args[0], args[1]),
+            SYS_CLOSE => self.close(args[0]),
+            SYS_LSEEK => self.lseek(args[0], signed(args[1]), args[2]),
+            SYS_FSTAT => self.fstat(memory, args[0], args[1]),
+            SYS_BRK => self.set_break(args[0]) as i64,
+            // riscv-pk 的 timeval/timespec 两个字段都是 XLEN 宽
+            SYS_GETTIMEOFDAY => write_time(memory, args[0], cycles, 1_000, word),
+            SYS_CLOCK_GETTIME => write_time(memory, args[1], cycles, 1, word),
+            SYS_CLOCK_GETTIME64 => write_time(memory, args[1], cycles, 1, 8),
+            _ => {
+                println!("[SYSTEM] Unimplemented syscall: {}", number);
+                -ENOSYS
+            }
+        };
+        SyscallResult::Return(result)
+    }
+
+    fn file(&mut self, fd: u64) -> Result<&mut HostFile, i64> {
+        self.files
+            .get_mut(fd as usize)
+            .and_then(|f| f.as_mut())
+            .ok_or(-EBADF)
+    }
+
+    fn write(&mut self, memory: &mut dyn GuestMemory, fd: u64, buf: u64, len: u64) -> i64 {
+        let mut data = vec![0; (len as usize).min(MAX_TRANSFER)];
+        if memory.read(buf, &mut data).is_err() {
+            return -EFAULT;
+        }
+        let result = match self.file(fd) {
+            Ok(HostFile::Stdout) => {
+                let mut stdout = io::stdout();
+                stdout.write_all(&data).and_then(|_| stdout.flush())
+            }
+            Ok(HostFile::Stderr) => io::stderr().write_all(&data),
+            Ok(HostFile::File {
+                file,
+                writable: true,
+                ..
+            }) => file.write_all(&data),
+            Ok(_) => return -EBADF,
+            Err(e) => return e,
+        };
+        match result {
+            Ok(()) => data.len() as i64,
+            Err(e) => -errno(&e),
+        }
+    }
+
+    fn read(&mut self, memory: &mut dyn GuestMemory, fd: u64, buf: u64, len: u64) -> i64 {
+        let mut data = vec![0; (len as usize).min(MAX_TRANSFER)];
+        let result = match self.file(fd) {
+            Ok(HostFile::Stdin) => io::stdin().read(&mut data),
+            Ok(HostFile::File {
+                file,
+                readable: true,
+                ..
+            }) => file.read(&mut data),
+            Ok(_) => return -EBADF,
+            Err(e) => return e,
+        };
+        match result {
+            Ok(n) if memory.write(buf, &data[..n]).is_ok() => n as i64,
+            Ok(_) => -EFAULT,
+            Err(e) => -errno(&e),
+        }
+    }
+
+    fn open(&mut self, memory: &mut dyn GuestMemory, dirfd: i64, path: u64, flags: u64) -> i64 {
+        let Ok(path) = read_cstring(memory, path) else {
+            return -EFAULT;
+        };
+        // 相对路径只支持相对于当前目录（沙箱根目录）
+        if dirfd != AT_FDCWD && !path.starts_with('/') {
+            return -EBADF;
+        }
+        let host_path = match self.resolve(&path) {
+            Ok(host_path) => host_path,
+            Err(e) => return e,
+        };
+
+        let (readable, writable) = match flags & O_ACCMODE {
+            O_WRONLY => (false, true),
+            O_RDWR => (true, true),
+            _ => (true, false),
+        };
+        let create = flags & O_CREAT != 0;
+        let exclusive = create && flags & O_EXCL != 0;
+
+        let mut options = OpenOptions::new();
+        options
+            .read(readable)
+            .write(writable)
+            .append(writable && flags & O_APPEND != 0);
+        // Linux 允许以只读方式截断或新建文件，主机上只有这两种情况才额外请求写权限
+        if flags & O_TRUNC != 0 {
+            options.write(true).truncate(true);
+        }
+        if exclusive {
+            options.write(true).create_new(true);
+        }
+        // resolve 之后路径又被替换成符号链接时不跟随
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.custom_flags(libc::O_NOFOLLOW);
+        }
+
+        // 先按已有文件打开，不存在时才创建
+        let mut result = options.open(&host_path);
+        let missing = matches!(&result, Err(e) if e.kind() == io::ErrorKind::NotFound);
+        if create && !exclusive && missing {
+            result = options.write(true).create(true).open(&host_path);
+        }
+
+        match result {
+            Ok(file) => {
+                // 复用最小的空闲描述符
+                let entry = Some(HostFile::File {
+                    file,
+                    readable,
+                    writable,
+                });
+                match self.files.iter().position(|f| f.is_none()) {
+                    Some(fd) => {
+                        self.files[fd] = entry;
+                        fd as i64
+                    }
+                    None => {
+                        self.files.push(entry);
+                        self.files.len() as i64 - 1
+                    }
+                }
+            }
+            Err(e) => -errno(&e),
+        }
+    }
+
+    // 把客户机路径映射到沙箱内，.. 不能越过沙箱根目录，符号链接也不能指向沙箱之外。
+    // 返回解析符号链接后的真实路径
+    fn resolve(&self, path: &str) -> Result<PathBuf, i64> {
+        let root = self.sandbox.as_ref().ok_or(-EACCES)?;
+        let mut resolved = root.clone();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => resolved.push(name),
+                Component::ParentDir if resolved == *root => return Err(-EACCES),
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                _ => (),
+            }
+        }
+
+        let real = match resolved.symlink_metadata() {
+            // 悬空的符号链接不能打开，否则 O_CREAT 会在链接指向的位置（可能在沙箱之外）创建文件
+            Ok(meta) if meta.file_type().is_symlink() => {
+                resolved.canonicalize().map_err(|_| -EACCES)?
+            }
+            Ok(_) => resolved.canonicalize().map_err(|_| -ENOENT)?,
+            // 新建的文件检查其所在目录
+            Err(_) => {
+                let parent = resolved.parent().unwrap_or(root);
+                let dir = parent.canonicalize().map_err(|_| -ENOENT)?;
+                match resolved.file_name() {
+                    Some(name) => dir.join(name),
+                    None => dir,
+                }
+            }
+        };
+        if real.starts_with(root) {
+            Ok(real)
+        } else {
+            Err(-EACCES)
+        }
+    }
+
+    fn close(&mut self, fd: u64) -> i64 {
+        match self.file(fd) {
+            // 标准输入输出保持打开
+            Ok(HostFile::File { .. }) => {
+                self.files[fd as usize] = None;
+                0
+            }
+            Ok(_) => 0,
+            Err(e) => e,
+        }
+    }
+
+    fn lseek(&mut self, fd: u64, offset: i64, whence: u64) -> i64 {
+        let file = match self.file(fd) {
+            Ok(HostFile::File { file, .. }) => file,
+            Ok(_) => return -ESPIPE,
+            Err(e) => return e,
+        };
+        let position = match whence {
+            0 if offset >= 0 => SeekFrom::Start(offset as u64),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => return -EINVAL,
+        };
+        match file.seek(position) {
+            Ok(position) => position as i64,
+            Err(e) => -errno(&e),
+        }
+    }
+
+    // struct kernel_stat：布局在 RV32 和 RV64 下相同，共 128 字节
+    fn fstat(&mut self, memory: &mut dyn GuestMemory, fd: u64, buf: u64) -> i64 {
+        let (mode, size) = match self.file(fd) {
+            Ok(HostFile::File { file, .. }) => match file.metadata() {
+                Ok(meta) if meta.is_dir() => (S_IFDIR | 0o755, meta.len()),
+                Ok(meta) => (S_IFREG | 0o644, meta.len()),
+                Err(e) => return -errno(&e),
+            },
+            // 字符设备让 newlib 对标准输出使用行缓冲
+            Ok(_) => (S_IFCHR | 0o620, 0),
+            Err(e) => return e,
+        };
+        let mut stat = [0u8; 128];
+        stat[16..20].copy_from_slice(&mode.to_le_bytes()); // st_mode
+        stat[20..24].copy_from_slice(&1u32.to_le_bytes()); // st_nlink
+        stat[48..56].copy_from_slice(&size.to_le_bytes()); // st_size
+        stat[56..60].copy_from_slice(&4096u32.to_le_bytes()); // st_blksize
+        stat[64..72].copy_from_slice(&size.div_ceil(512).to_le_bytes()); // st_blocks
+        match memory.write(buf, &stat) {
+            Ok(()) => 0,
+            Err(_) => -EFAULT,
+        }
+    }
+
+    // brk(0) 查询当前位置；超出范围时不改变，返回原位置
+    fn set_break(&mut self, addr: u64) -> u64 {
+        if addr >= self.brk_start && addr <= self.brk_limit {
+            self.brk = addr;
+        }
+        self.brk
+    }
+}
+
+// 写入 {秒, 秒以下的部分}，unit 为秒以下部分的纳秒数（微秒为 1000）
+fn write_time(
+    memory: &mut dyn GuestMemory,
+    addr: u64,
+    cycles: u64,
+    unit: u64,
+    width: usize,
+) -> i64 {
+    let nanos = cycles as u128 * 1_000_000_000 / CLOCK_HZ as u128;
+    let seconds = (nanos / 1_000_000_000) as u64;
+    let fraction = (nanos % 1_000_000_000) as u64 / unit;
+    let mut data = Vec::with_capacity(2 * width);
+    data.extend_from_slice(&seconds.to_le_bytes()[..width]);
+    data.extend_from_slice(&fraction.to_le_bytes()[..width]);
+    match memory.write(addr, &data) {
+        Ok(()) => 0,
+        Err(_) => -EFAULT,
+    }
+}
+
+// 以 NUL 结尾的路径，最长 4096 字节
+fn read_cstring(memory: &mut dyn GuestMemory, addr: u64) -> Result<String, EmuError> {
+    let mut bytes = Vec::new();
+    let mut byte = [0u8];
+    while bytes.len() < 4096 {
+        memory.read(addr.wrapping_add(bytes.len() as u64), &mut byte)?;
+        if byte[0] == 0 {
+            break;
+        }
+        bytes.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// 主机错误按类别转换为 errno，不依赖主机的 errno 编码
+fn errno(e: &io::Error) -> i64 {
+    match e.kind() {
+        io::ErrorKind::NotFound => ENOENT,
+        io::ErrorKind::PermissionDenied => EACCES,
+        io::ErrorKind::AlreadyExists => EEXIST,
+        io::ErrorKind::InvalidInput => EINVAL,
+        _ => EIO,
+    }
+}
+
+// riscv-pk 风格的初始栈：sp 处依次是 argc、argv[]、NULL、envp 的 NULL、auxv 的 AT_NULL，
+// 之后是参数字符串。返回 16 字节对齐的 sp 和从 sp 开始的内容
+pub fn initial_stack(top: u64, args: &[&str], xlen: Xlen) -> (u64, Vec<u8>) {
+    let word = xlen.bits() as usize / 8;
+    let header = (args.len() + 5) * word;
+    let strings: usize = args.iter().map(|a| a.len() + 1).sum();
+    let sp = (top - (header + strings) as u64) & !0xf;
+
+    let mut words = vec![args.len() as u64];
+    let mut image = Vec::new();
+    for arg in args {
+        words.push(sp + (header + image.len()) as u64);
+        image.extend_from_slice(arg.as_bytes());
+        image.push(0);
+    }
+    words.extend_from_slice(&[0, 0, 0, 0]);
+
+    let mut stack: Vec<u8> = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes()[..word].to_vec())
+        .collect();
+    stack.extend_from_slice(&image);
+    (sp, stack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x1000 起的一段平坦内存
+    struct Ram(Vec<u8>);
+
+    impl GuestMemory for Ram {
+        fn read(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), EmuError> {
+            let offset = (addr as usize).wrapping_sub(0x1000);
+            let data = self
+                .0
+                .get(offset..offset + buf.len())
+                .ok_or(EmuError::AccessFault { addr })?;
+            buf.copy_from_slice(data);
+            Ok(())
+        }
+
+        fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), EmuError> {
+            let offset = (addr as usize).wrapping_sub(0x1000);
+            self.0
+                .get_mut(offset..offset + data.len())
+                .ok_or(EmuError::AccessFault { addr })?
+                .copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    fn call(sys: &mut Syscalls, ram: &mut Ram, number: u64, args: &[u64]) -> i64 {
+        let mut full = [0; 6];
+        full[..args.len()].copy_from_slice(args);
+        match sys.handle(number, full, Xlen::Rv32, ram, 250_000_000) {
+            SyscallResult::Return(value) => value,
+            SyscallResult::Exit(code) => panic!("unexpected exit {}", code),
+        }
+    }
+
+    #[test]
+    fn test_file_syscalls() {
+        let dir =
+            std::env::temp_dir().join(format!("riscv_emu_syscall_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut sys = Syscalls::new();
+        let mut ram = Ram(vec![0; 0x1000]);
+        ram.write(0x1000, b"out.txt\0hello\0../escape\0").unwrap();
+        let at_fdcwd = AT_FDCWD as u32 as u64;
+
+        // 没有沙箱时不能打开文件
+        assert_eq!(
+            call(&mut sys, &mut ram, SYS_OPENAT, &[at_fdcwd, 0x1000, 0]),
+            -EACCES
+        );
+        sys.set_sandbox(&dir).unwrap();
+
+        let flags = O_WRONLY | O_CREAT | O_TRUNC;
+        assert_eq!(
+            call(&mut sys, &mut ram, SYS_OPENAT, &[at_fdcwd, 0x1000, flags]),
+            3
+        );
+        assert_eq!(call(&mut sys, &mut ram, SYS_WRITE, &[3, 0x1008, 5]), 5);
+        assert_eq!(call(&mut sys, &mut ram, SYS_READ, &[3, 0x1300, 5]), -EBADF);
+        assert_eq!(call(&mut sys, &mut ram, SYS_CLOSE, &[3]), 0);
+        assert_eq!(call(&mut sys, &mut ram, SYS_CLOSE, &[3]), -EBADF);
+        assert_eq!(std::fs::read(dir.join("out.txt")).unwrap(), b"hello");
+
+        // 绝对路径同样在沙箱内解析
+        ram.write(0x1100, b"/out.txt\0").unwrap();
+        assert_eq!(call(&mut sys, &mut ram, SYS_OPEN, &[0x1100, 0]), 3);
+        assert_eq!(call(&mut sys, &mut ram, SYS_FSTAT, &[3, 0x1200]), 0);
+        let mut stat = [0u8; 128];
+        ram.read(0x1200, &mut stat).unwrap();
+        assert_eq!(
+            u32::from_le_bytes(stat[16..20].try_into().unwrap()),
+            S_IFREG | 0o644
+        );
+        assert_eq!(u64::from_le_bytes(stat[48..56].try_into().unwrap()), 5);
+        assert_eq!(call(&mut sys, &mut ram, SYS_LSEEK, &[3, 1, 0]), 1);
+        assert_eq!(call(&mut sys, &mut ram, SYS_READ, &[3, 0x1300, 100]), 4);
+        let mut data = [0u8; 4];
+        ram.read(0x1300, &mut data).unwrap();
+        assert_eq!(&data, b"ello");
+        assert_eq!(call(&mut sys, &mut ram, SYS_LSEEK, &[1, 0, 0]), -ESPIPE);
+
+        // O_RDONLY | O_CREAT 创建空文件
+        ram.write(0x1400, b"new.txt\0").unwrap();
+        assert_eq!(
+            call(&mut sys, &mut ram, SYS_OPENAT, &[at_fdcwd, 0x1400, O_CREAT]),
+            4
+        );
+        assert_eq!(call(&mut sys, &mut ram, SYS_READ, &[4, 0x1300, 100]), 0);
+        assert_eq!(call(&mut sys, &mut ram, SYS_WRITE, &[4, 0x1008, 5]), -EBADF);
+        assert!(std::fs::read(dir.join("new.txt")).unwrap().is_empty());
+
+        // 文件已存在时 O_CREAT 不要求写权限，也不改变内容
+        let out = dir.join("out.txt");
+        let mut permissions = std::fs::metadata(&out).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&out, permissions).unwrap();
+        assert_eq!(
+            call(&mut sys, &mut ram, SYS_OPENAT, &[at_fdcwd, 0x1000, O_CREAT]),
+            5
+        );
+        assert_eq!(call(&mut sys, &mut ram, SYS_READ, &[5, 0x1300, 100]), 5);
+
+        assert_eq!(
+            call(&mut sys, &mut ram, SYS_OPENAT, &[at_fdcwd, 0x100e, 0]),
+            -EACCES
+        );
+        assert_eq!(
+            call(&mut sys, &mut ram, SYS_WRITE, &[1, 0x3000, 4]),
+            -EFAULT
+        );
+
+        // 指向沙箱之外不存在的文件的符号链接不能用来创建文件
+        #[cfg(unix)]
+        {
+            let outside = dir.with_extension("outside");
+            std::os::unix::fs::symlink(&outside, dir.join("link")).unwrap();
+            ram.write(0x1500, b"link\0").unwrap();
+            let flags = O_WRONLY | O_CREAT;
+            assert_eq!(
+                call(&mut sys, &mut ram, SYS_OPENAT, &[at_fdcwd, 0x1500, flags]),
+                -EACCES
+            );
+            assert!(!outside.exists());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_brk_time_and_stack() {
+        let mut sys = Syscalls::new();
+        let mut ram = Ram(vec![0; 0x100]);
+        sys.set_brk(0x80010000, 0x80020000);
+        assert_eq!(call(&mut sys, &mut ram, SYS_BRK, &[0]), 0x80010000);
+        assert_eq!(call(&mut sys, &mut ram, SYS_BRK, &[0x80018000]), 0x80018000);
+        assert_eq!(call(&mut sys, &mut ram, SYS_BRK, &[0x80030000]), 0x80018000);
+        assert_eq!(call(&mut sys, &mut ram, 1234, &[]), -ENOSYS);
+        let exit = sys.handle(SYS_EXIT, [7, 0, 0, 0, 0, 0], Xlen::Rv32, &mut ram, 0);
+        assert_eq!(exit, SyscallResult::Exit(7));
+
+        // 250M 个周期 = 2.5 秒
+        assert_eq!(call(&mut sys, &mut ram, SYS_CLOCK_GETTIME, &[0, 0x1000]), 0);
+        let mut time = [0u8; 8];
+        ram.read(0x1000, &mut time).unwrap();
+        assert_eq!(u32::from_le_bytes(time[..4].try_into().unwrap()), 2);
+        assert_eq!(
+            u32::from_le_bytes(time[4..].try_into().unwrap()),
+            500_000_000
+        );
+        assert_eq!(call(&mut sys, &mut ram, SYS_GETTIMEOFDAY, &[0x1000]), 0);
+        ram.read(0x1000, &mut time).unwrap();
+        assert_eq!(u32::from_le_bytes(time[4..].try_into().unwrap()), 500_000);
+
+        let (sp, stack) = initial_stack(0x80100000, &["prog"], Xlen::Rv32);
+        assert_eq!(sp, 0x800fffe0);
+        assert_eq!(&stack[..8], &[1, 0, 0, 0, 0xf8, 0xff, 0x0f, 0x80]);
+        assert_eq!(&stack[24..29], b"prog\0");
+    }
+}
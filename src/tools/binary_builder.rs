@@ -22,6 +22,12 @@ pub struct BinaryBuilder {
     code: Vec<u8>,
 }
 
+impl Default for BinaryBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BinaryBuilder {
     pub fn new() -> Self {
         Self { code: Vec::new() }
@@ -58,7 +64,10 @@ mod tests {
         // add x3, x1, x2    // x3 = x1 + x2
         builder.add_instruction(0x002081b3);
         
-        builder.save("test_program.bin")?;
+        let name = format!("riscv_emu_test_program_{}.bin", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        builder.save(path.to_str().unwrap())?;
+        std::fs::remove_file(&path)?;
         Ok(())
     }
 } 
\ No newline at end of file
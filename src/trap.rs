@@ -0,0 +1,93 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// 同步异常，携带写入 mtval 的值
+#[derive(Debug, Copy, Clone)]
+pub enum Exception {
+    InstructionAddressMisaligned(u64),
+    InstructionAccessFault(u64),
+    IllegalInstruction(u64),
+    Breakpoint(u64),
+    LoadAddressMisaligned(u64),
+    LoadAccessFault(u64),
+    StoreAddressMisaligned(u64),
+    StoreAccessFault(u64),
+    EcallFromUMode,
+    EcallFromSMode,
+    EcallFromMMode,
+    InstructionPageFault(u64),
+    LoadPageFault(u64),
+    StorePageFault(u64),
+}
+
+impl Exception {
+    // mcause 中的异常编码
+    pub fn code(&self) -> u64 {
+        match self {
+            Exception::InstructionAddressMisaligned(_) => 0,
+            Exception::InstructionAccessFault(_) => 1,
+            Exception::IllegalInstruction(_) => 2,
+            Exception::Breakpoint(_) => 3,
+            Exception::LoadAddressMisaligned(_) => 4,
+            Exception::LoadAccessFault(_) => 5,
+            Exception::StoreAddressMisaligned(_) => 6,
+            Exception::StoreAccessFault(_) => 7,
+            Exception::EcallFromUMode => 8,
+            Exception::EcallFromSMode => 9,
+            Exception::EcallFromMMode => 11,
+            Exception::InstructionPageFault(_) => 12,
+            Exception::LoadPageFault(_) => 13,
+            Exception::StorePageFault(_) => 15,
+        }
+    }
+
+    pub fn tval(&self) -> u64 {
+        match *self {
+            Exception::InstructionAddressMisaligned(v)
+            | Exception::InstructionAccessFault(v)
+            | Exception::IllegalInstruction(v)
+            | Exception::Breakpoint(v)
+            | Exception::LoadAddressMisaligned(v)
+            | Exception::LoadAccessFault(v)
+            | Exception::StoreAddressMisaligned(v)
+            | Exception::StoreAccessFault(v)
+            | Exception::InstructionPageFault(v)
+            | Exception::LoadPageFault(v)
+            | Exception::StorePageFault(v) => v,
+            Exception::EcallFromUMode | Exception::EcallFromSMode | Exception::EcallFromMMode => 0,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Exception::InstructionAddressMisaligned(_) => "Instruction address misaligned",
+            Exception::InstructionAccessFault(_) => "Instruction access fault",
+            Exception::IllegalInstruction(_) => "Illegal instruction",
+            Exception::Breakpoint(_) => "Breakpoint",
+            Exception::LoadAddressMisaligned(_) => "Load address misaligned",
+            Exception::LoadAccessFault(_) => "Load access fault",
+            Exception::StoreAddressMisaligned(_) => "Store address misaligned",
+            Exception::StoreAccessFault(_) => "Store access fault",
+            Exception::EcallFromUMode => "Environment call from U-mode",
+            Exception::EcallFromSMode => "Environment call from S-mode",
+            Exception::EcallFromMMode => "Environment call from M-mode",
+            Exception::InstructionPageFault(_) => "Instruction page fault",
+            Exception::LoadPageFault(_) => "Load page fault",
+            Exception::StorePageFault(_) => "Store page fault",
+        }
+    }
+}
@@ -0,0 +1,332 @@
+/*
+ * Copyright (C) 2024 ZhaoCake
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// RV32I 指令一致性测试：手工编码的程序由 BinaryBuilder 生成，按原始二进制加载到 0x80000000，
+// 运行到 exit 系统调用后检查寄存器。每个测试覆盖一类操作码
+
+use riscv_emu::cpu::{Cpu, StopReason};
+use riscv_emu::register::ABI_NAMES;
+use riscv_emu::tools::binary_builder::BinaryBuilder;
+
+// 程序末尾追加 li a7, 93; ecall，测试程序不使用 a7
+const EXIT: [u32; 2] = [0x05d00893, 0x00000073];
+
+fn run(name: &str, words: &[u32]) -> Cpu {
+    let mut builder = BinaryBuilder::new();
+    for &word in words.iter().chain(EXIT.iter()) {
+        builder.add_instruction(word);
+    }
+    let file = format!("riscv_emu_rv32i_{}_{}.bin", std::process::id(), name);
+    let path = std::env::temp_dir().join(file);
+    let path = path.to_str().unwrap();
+    builder.save(path).unwrap();
+
+    let mut cpu = Cpu::new(0x03000000);
+    cpu.set_itrace(false);
+    cpu.set_mtrace(false);
+    cpu.set_regtrace(false);
+    cpu.load_program(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    let reason = cpu.run(Some(1000));
+    assert!(
+        matches!(reason, StopReason::Exited(_)),
+        "{} stopped with {:?}",
+        name,
+        reason
+    );
+    cpu
+}
+
+fn check(cpu: &Cpu, expected: &[(&str, u32)]) {
+    for &(name, value) in expected {
+        let index = ABI_NAMES.iter().position(|&n| n == name).unwrap();
+        assert_eq!(cpu.read_register(index), value as u64, "register {}", name);
+    }
+}
+
+// OP：寄存器-寄存器运算，移位量只取低 5 位
+#[test]
+fn test_op() {
+    let cpu = run(
+        "op",
+        &[
+            0xff900293, // li t0, -7
+            0x00300313, // li t1, 3
+            0x00628533, // add a0, t0, t1
+            0x406285b3, // sub a1, t0, t1
+            0x00631633, // sll a2, t1, t1
+            0x0062a6b3, // slt a3, t0, t1
+            0x0062b733, // sltu a4, t0, t1
+            0x0062c7b3, // xor a5, t0, t1
+            0x0062d833, // srl a6, t0, t1
+            0x4062d933, // sra s2, t0, t1
+            0x0062e9b3, // or s3, t0, t1
+            0x0062fa33, // and s4, t0, t1
+            0x02100393, // li t2, 33
+            0x00731ab3, // sll s5, t1, t2
+        ],
+    );
+    check(
+        &cpu,
+        &[
+            ("a0", 0xfffffffc),
+            ("a1", 0xfffffff6),
+            ("a2", 0x18),
+            ("a3", 1),
+            ("a4", 0),
+            ("a5", 0xfffffffa),
+            ("a6", 0x1fffffff),
+            ("s2", 0xffffffff),
+            ("s3", 0xfffffffb),
+            ("s4", 1),
+            ("s5", 6),
+        ],
+    );
+}
+
+// OP-IMM：立即数符号扩展，SLTIU 按无符号比较符号扩展后的立即数
+#[test]
+fn test_op_imm() {
+    let cpu = run(
+        "op_imm",
+        &[
+            0xff900293, // li t0, -7
+            0x05000313, // li t1, 0x50
+            0x7ff28513, // addi a0, t0, 2047
+            0xffa2a593, // slti a1, t0, -6
+            0xfff2b613, // sltiu a2, t0, -1
+            0x0052b693, // sltiu a3, t0, 5
+            0xfff2c713, // xori a4, t0, -1
+            0x00a36793, // ori a5, t1, 0x0a
+            0x0ff2f813, // andi a6, t0, 0xff
+            0x00429913, // slli s2, t0, 4
+            0x01c2d993, // srli s3, t0, 28
+            0x4012da13, // srai s4, t0, 1
+        ],
+    );
+    check(
+        &cpu,
+        &[
+            ("a0", 0x7f8),
+            ("a1", 1),
+            ("a2", 1),
+            ("a3", 0),
+            ("a4", 6),
+            ("a5", 0x5a),
+            ("a6", 0xf9),
+            ("s2", 0xffffff90),
+            ("s3", 0xf),
+            ("s4", 0xfffffffc),
+        ],
+    );
+}
+
+// LUI/AUIPC：AUIPC 的结果相对于该指令的 PC
+#[test]
+fn test_lui_auipc() {
+    let cpu = run(
+        "lui_auipc",
+        &[
+            0x12345537, // lui a0, 0x12345
+            0xfffff5b7, // lui a1, 0xfffff
+            0x00000617, // auipc a2, 0
+            0x00001697, // auipc a3, 1
+            0xfffff717, // auipc a4, 0xfffff
+        ],
+    );
+    check(
+        &cpu,
+        &[
+            ("a0", 0x12345000),
+            ("a1", 0xfffff000),
+            ("a2", 0x80000008),
+            ("a3", 0x8000100c),
+            ("a4", 0x7ffff010),
+        ],
+    );
+}
+
+// LOAD/STORE：LB/LH 符号扩展，LBU/LHU 零扩展，SB/SH 只写低位字节
+#[test]
+fn test_load_store() {
+    let cpu = run(
+        "load_store",
+        &[
+            0x80001437, // lui s0, 0x80001
+            0x876542b7, // lui t0, 0x87654
+            0x32128293, // addi t0, t0, 0x321
+            0x00542023, // sw t0, 0(s0)
+            0x00040503, // lb a0, 0(s0)
+            0x00340583, // lb a1, 3(s0)
+            0x00344603, // lbu a2, 3(s0)
+            0x00241683, // lh a3, 2(s0)
+            0x00245703, // lhu a4, 2(s0)
+            0x00042783, // lw a5, 0(s0)
+            0x00041803, // lh a6, 0(s0)
+            0x00540223, // sb t0, 4(s0)
+            0x00541323, // sh t0, 6(s0)
+            0x00442903, // lw s2, 4(s0)
+            0xfff00313, // li t1, -1
+            0x00642423, // sw t1, 8(s0)
+            0x000404a3, // sb zero, 9(s0)
+            0x00842983, // lw s3, 8(s0)
+            0x01040493, // addi s1, s0, 16
+            0xff04aa03, // lw s4, -16(s1)
+        ],
+    );
+    check(
+        &cpu,
+        &[
+            ("a0", 0x21),
+            ("a1", 0xffffff87),
+            ("a2", 0x87),
+            ("a3", 0xffff8765),
+            ("a4", 0x8765),
+            ("a5", 0x87654321),
+            ("a6", 0x4321),
+            ("s2", 0x43210021),
+            ("s3", 0xffff00ff),
+            ("s4", 0x87654321),
+        ],
+    );
+}
+
+// BRANCH：每个条件分别测试跳转与不跳转，a0 记录应当跳过的指令，a1 记录应当执行的指令
+#[test]
+fn test_branch() {
+    let cpu = run(
+        "branch",
+        &[
+            0xfff00293, // li t0, -1
+            0x00100313, // li t1, 1
+            0x00000513, // li a0, 0
+            0x00528463, // beq t0, t0, 1f
+            0x00156513, // ori a0, a0, 1
+            0x00629463, // 1: bne t0, t1, 2f
+            0x00256513, // ori a0, a0, 2
+            0x0062c463, // 2: blt t0, t1, 3f
+            0x00456513, // ori a0, a0, 4
+            0x00535463, // 3: bge t1, t0, 4f
+            0x00856513, // ori a0, a0, 8
+            0x00536463, // 4: bltu t1, t0, 5f
+            0x01056513, // ori a0, a0, 16
+            0x0062f463, // 5: bgeu t0, t1, 6f
+            0x02056513, // ori a0, a0, 32
+            0x00000593, // 6: li a1, 0
+            0x00628463, // beq t0, t1, 7f
+            0x0015e593, // ori a1, a1, 1
+            0x00529463, // 7: bne t0, t0, 8f
+            0x0025e593, // ori a1, a1, 2
+            0x00534463, // 8: blt t1, t0, 9f
+            0x0045e593, // ori a1, a1, 4
+            0x0062d463, // 9: bge t0, t1, 10f
+            0x0085e593, // ori a1, a1, 8
+            0x0062e463, // 10: bltu t0, t1, 11f
+            0x0105e593, // ori a1, a1, 16
+            0x00537463, // 11: bgeu t1, t0, 12f
+            0x0205e593, // ori a1, a1, 32
+            0x00000613, // 12: li a2, 0
+            0x00500393, // li t2, 5
+            0x00160613, // 13: addi a2, a2, 1
+            0xfe764ee3, // blt a2, t2, 13b
+        ],
+    );
+    check(&cpu, &[("a0", 0), ("a1", 0x3f), ("a2", 5)]);
+}
+
+// JAL/JALR：JALR 清除目标地址最低位，rd 与 rs1 相同时使用旧值
+#[test]
+fn test_jump() {
+    let cpu = run(
+        "jump",
+        &[
+            0x008000ef, // jal ra, 1f
+            0x00100513, // li a0, 1
+            0x00000297, // 1: auipc t0, 0
+            0x00d285e7, // jalr a1, 13(t0)
+            0x00100613, // li a2, 1
+            0x0080006f, // j 2f
+            0x00100693, // li a3, 1
+            0x00000397, // 2: auipc t2, 0
+            0x00c383e7, // jalr t2, 12(t2)
+            0x00100713, // li a4, 1
+        ],
+    );
+    check(
+        &cpu,
+        &[
+            ("ra", 0x80000004),
+            ("a0", 0),
+            ("a1", 0x80000010),
+            ("a2", 0),
+            ("a3", 0),
+            ("a4", 0),
+            ("t2", 0x80000024),
+        ],
+    );
+}
+
+// MISC-MEM/SYSTEM：FENCE 不影响执行，x0 保持为 0，EBREAK 进入断点异常
+#[test]
+fn test_fence_ebreak() {
+    let cpu = run(
+        "fence_ebreak",
+        &[
+            0x800002b7, // lui t0, 0x80000
+            0x02828293, // addi t0, t0, 0x28
+            0x30529073, // csrw mtvec, t0
+            0x0ff0000f, // fence
+            0x0330000f, // fence rw, rw
+            0x8330000f, // fence.tso
+            0x00500013, // addi zero, zero, 5
+            0x00000533, // add a0, zero, zero
+            0x00100073, // ebreak
+            0x00100593, // li a1, 1
+            0x34202673, // csrr a2, mcause
+            0x341026f3, // csrr a3, mepc
+            0x30501073, // csrw mtvec, zero
+        ],
+    );
+    check(
+        &cpu,
+        &[
+            ("zero", 0),
+            ("a0", 0),
+            ("a1", 0),
+            ("a2", 3),
+            ("a3", 0x80000020),
+        ],
+    );
+}
+
+// MISC-MEM：FENCE.I（Zifencei）之后执行刚写入的指令
+#[test]
+fn test_fence_i() {
+    let cpu = run(
+        "fence_i",
+        &[
+            0x80000437, // lui s0, 0x80000
+            0x02a002b7, // lui t0, 0x2a00
+            0x51328293, // addi t0, t0, 0x513
+            0x00542a23, // sw t0, 20(s0)      写入 li a0, 42
+            0x0000100f, // fence.i
+            0x00000013, // nop，执行前被改写
+        ],
+    );
+    check(&cpu, &[("a0", 42)]);
+}